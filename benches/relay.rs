@@ -0,0 +1,111 @@
+//! Throughput and CPU cost of `Socks5::relay` for a large transfer.
+//!
+//! Compare the buffered copy with the `splice(2)` path:
+//!
+//! ```bash
+//! cargo bench --bench relay
+//! cargo bench --bench relay --features splice
+//! ```
+//!
+//! The CPU time covers the whole process, including the client writing and
+//! the target reading, which cost the same in both runs; the difference
+//! between them is what the relay saves.
+
+use std::time::{Duration, Instant};
+
+use simple_socks5::Socks5;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Bytes sent through the relay per run.
+const TRANSFER: u64 = 4 << 30;
+const CHUNK: usize = 256 * 1024;
+
+/// Returns both ends of a loopback TCP connection.
+async fn pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let near = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (far, _) = listener.accept().await.unwrap();
+    (near, far)
+}
+
+/// User plus system CPU time of the process so far.
+#[cfg(unix)]
+fn cpu_time() -> Duration {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `usage` is valid for writes, and `getrusage` fills it in on
+    // success.
+    let usage = unsafe {
+        assert_eq!(libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()), 0);
+        usage.assume_init()
+    };
+    let timeval = |tv: libc::timeval| Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000);
+    timeval(usage.ru_utime) + timeval(usage.ru_stime)
+}
+
+/// CPU time is only measured on Unix.
+#[cfg(not(unix))]
+fn cpu_time() -> Duration {
+    Duration::ZERO
+}
+
+async fn run() {
+    let (mut client, mut proxy_client) = pair().await;
+    let (mut proxy_target, mut target) = pair().await;
+    let relay =
+        tokio::spawn(async move { Socks5::relay(&mut proxy_client, &mut proxy_target).await });
+
+    let reader = tokio::spawn(async move {
+        let mut buf = vec![0u8; CHUNK];
+        let mut total = 0u64;
+        loop {
+            match target.read(&mut buf).await.unwrap() {
+                0 => return total,
+                n => total += n as u64,
+            }
+        }
+    });
+
+    let wall = Instant::now();
+    let cpu = cpu_time();
+
+    let chunk = vec![0x42u8; CHUNK];
+    let mut sent = 0u64;
+    while sent < TRANSFER {
+        client.write_all(&chunk).await.unwrap();
+        sent += CHUNK as u64;
+    }
+    client.shutdown().await.unwrap();
+    assert_eq!(reader.await.unwrap(), TRANSFER);
+    drop(client);
+    relay.await.unwrap().unwrap();
+
+    let wall = wall.elapsed();
+    let cpu = cpu_time() - cpu;
+    let gib = TRANSFER as f64 / (1u64 << 30) as f64;
+    println!(
+        "relay {} GiB: {:.2?} wall, {:.0} MiB/s, {:.2?} CPU ({:.2} s per GiB)",
+        gib,
+        wall,
+        gib * 1024.0 / wall.as_secs_f64(),
+        cpu,
+        cpu.as_secs_f64() / gib,
+    );
+}
+
+fn main() {
+    let path = if cfg!(all(target_os = "linux", feature = "splice")) {
+        "splice"
+    } else {
+        "buffered"
+    };
+    println!("path: {path}");
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(run());
+}
@@ -1,11 +1,38 @@
-use simple_socks5::conn::request::CMD;
-use simple_socks5::{ATYP, Socks5, conn::reply::Rep, error::SocksError, parse::AddrPort};
-use std::net::{IpAddr, Ipv4Addr};
-use std::sync::Arc;
-use tokio::io;
-use tokio::io::AsyncWriteExt;
+use simple_socks5::auth::AuthContext;
+use simple_socks5::conn::request::ConnRequest;
+use simple_socks5::handler::{ConnectionHandler, HandleFuture, RelayHandler};
+use simple_socks5::{Socks5, error::SocksError};
+use std::net::SocketAddr;
 use tokio::net::TcpStream;
-use tracing::{error, info, warn};
+use tracing::{error, info};
+
+/// Logs who each client authenticated as, then relays its request.
+struct LogIdentity;
+
+impl ConnectionHandler for LogIdentity {
+    fn handle(
+        &self,
+        stream: TcpStream,
+        client: SocketAddr,
+        request: ConnRequest,
+        auth: AuthContext,
+    ) -> HandleFuture<'_> {
+        Box::pin(async move {
+            let user = auth.username.as_deref().unwrap_or("anonymous");
+            info!(
+                "Client {client} authenticated as {user} ({:?})",
+                auth.method
+            );
+
+            let result = RelayHandler.handle(stream, client, request, auth).await;
+            match &result {
+                Ok(()) => info!("Connection with {client} closed"),
+                Err(e) => error!("Client {client} error: {e}"),
+            }
+            result
+        })
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), SocksError> {
@@ -20,97 +47,16 @@ async fn main() -> Result<(), SocksError> {
     server.allow_no_auth();
 
     // Example with a username and password if you need authentication
-    // server.allow_userpass(|u, p| u == "admin" && p == "admin");
+    // server.allow_userpass_static("admin", "admin");
 
-    let server = Arc::new(server);
+    // Example forwarding all CONNECT traffic through another SOCKS5 proxy
+    // (honored by `server.run()`; the `RelayHandler` used here dials directly)
+    // server.set_upstream(simple_socks5::UpstreamKind::Socks5 {
+    //     addr: "127.0.0.1:1081".into(),
+    //     auth: Some(("user".into(), "pass".into())),
+    // });
 
     info!("SOCKS5 proxy listening on {}", server.local_addr()?);
 
-    loop {
-        let (client, addr) = server.accept().await?;
-        let server_ref = Arc::clone(&server);
-
-        tokio::spawn(async move {
-            if let Err(e) = handle_client(server_ref, client, addr).await {
-                error!("Client {addr} error: {e}");
-            }
-        });
-    }
-}
-
-async fn handle_client(
-    server: Arc<Socks5>,
-    mut stream: TcpStream,
-    addr: std::net::SocketAddr,
-) -> Result<(), SocksError> {
-    info!("New client connected from {addr}");
-
-    if let Err(e) = server.authenticate(&mut stream).await {
-        warn!("Authentication failed for {addr}: {e}");
-        let _ = stream.shutdown().await;
-        return Ok(());
-    }
-    info!("Authentication succeeded for {addr}");
-
-    let req = match Socks5::read_conn_request(&mut stream).await {
-        Ok(r) => {
-            info!(client=%addr, "Connection request");
-            info!(request=%r, "Request format");
-            r
-        }
-        Err(e) => {
-            error!("Failed to read connection request from {addr}: {e}");
-            let _ = stream.shutdown().await;
-            return Ok(());
-        }
-    };
-
-    match req.cmd {
-        CMD::Connect => {
-            info!(client=%addr, dest=%req.dst, "Connecting to destination");
-
-            let mut target = match req.dst {
-                AddrPort::V4(ip, port) => TcpStream::connect((ip, port)).await?,
-                AddrPort::V6(ip, port) => TcpStream::connect((ip, port)).await?,
-                AddrPort::Domain(ref host, port) => {
-                    TcpStream::connect((host.as_str(), port)).await?
-                }
-            };
-
-            let local_addr = target.local_addr()?;
-            let bnd = match local_addr.ip() {
-                IpAddr::V4(ip) => AddrPort::V4(ip, local_addr.port()),
-                IpAddr::V6(ip) => AddrPort::V6(ip, local_addr.port()),
-            };
-
-            let atyp = match bnd {
-                AddrPort::V4(_, _) => ATYP::V4,
-                AddrPort::V6(_, _) => ATYP::V6,
-                _ => ATYP::DomainName,
-            };
-
-            info!(client=%addr, bind=%bnd, atyp=%atyp, "Connection established");
-
-            Socks5::send_conn_reply(&mut stream, Rep::Succeeded, atyp, bnd).await?;
-
-            if let Err(e) = io::copy_bidirectional(&mut stream, &mut target).await {
-                warn!("TCP connection with {addr} closed with error: {e}");
-            } else {
-                info!("TCP connection with {addr} closed");
-            }
-        }
-
-        _ => {
-            warn!("Unsupported command from {addr}: {}", req.cmd);
-            Socks5::send_conn_reply(
-                &mut stream,
-                Rep::CommandNotSupported,
-                ATYP::V4,
-                AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0),
-            )
-            .await?;
-        }
-    }
-
-    Ok(())
+    server.serve(LogIdentity).await
 }
@@ -0,0 +1,156 @@
+//! Per-connection audit records.
+//!
+//! Unlike [`metrics`](crate::metrics), which only keeps aggregate counters,
+//! an [`AuditSink`] receives one [`AuditEvent`] when a connection starts
+//! relaying and another when it ends, carrying who connected, as which
+//! user, to where, and how many bytes moved. Install one with
+//! [`Socks5::set_audit_sink`](crate::Socks5::set_audit_sink).
+//!
+//! ```
+//! use std::io::Write;
+//! use std::sync::Mutex;
+//! use simple_socks5::audit::{AuditEvent, AuditFuture, AuditSink};
+//!
+//! /// Appends one line per finished connection to a writer.
+//! struct LineLog<W>(Mutex<W>);
+//!
+//! impl<W: Write + Send> AuditSink for LineLog<W> {
+//!     fn record(&self, event: AuditEvent) -> AuditFuture<'_> {
+//!         Box::pin(async move {
+//!             if event.ended.is_some() {
+//!                 let mut out = self.0.lock().unwrap();
+//!                 let _ = writeln!(
+//!                     out,
+//!                     "{} {:?} {} up={} down={}",
+//!                     event.client,
+//!                     event.user,
+//!                     event.dst,
+//!                     event.client_to_target,
+//!                     event.target_to_client,
+//!                 );
+//!             }
+//!         })
+//!     }
+//! }
+//! ```
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::SystemTime;
+
+use crate::conn::request::CMD;
+use crate::parse::AddrPort;
+
+/// Future returned by [`AuditSink::record`].
+pub type AuditFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Receives a record of every proxied connection.
+///
+/// The server awaits [`AuditSink::record`] before moving on, so a sink
+/// that writes somewhere slow should hand the event off, e.g. to a
+/// channel, rather than hold up the connection.
+pub trait AuditSink: Send + Sync {
+    /// Records `event`.
+    fn record(&self, event: AuditEvent) -> AuditFuture<'_>;
+}
+
+/// One audit record of a proxied connection.
+///
+/// The record sent when the connection starts has `ended` set to `None`
+/// and zero byte counts; the one sent when it ends carries the end time
+/// and the totals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// Address of the client.
+    pub client: SocketAddr,
+    /// The username the client authenticated as, if any.
+    pub user: Option<String>,
+    /// Destination requested by the client, or for `UDP ASSOCIATE` the
+    /// address it said it would send from.
+    pub dst: AddrPort,
+    /// The requested command.
+    pub command: CMD,
+    /// When the connection started relaying.
+    pub started: SystemTime,
+    /// When the connection ended, `None` in the start record.
+    pub ended: Option<SystemTime>,
+    /// Bytes copied from the client to the target.
+    pub client_to_target: u64,
+    /// Bytes copied from the target to the client.
+    pub target_to_client: u64,
+}
+
+impl AuditEvent {
+    /// The start record of a connection, timestamped now.
+    pub(crate) fn started(
+        client: SocketAddr,
+        user: Option<String>,
+        dst: AddrPort,
+        command: CMD,
+    ) -> Self {
+        Self {
+            client,
+            user,
+            dst,
+            command,
+            started: SystemTime::now(),
+            ended: None,
+            client_to_target: 0,
+            target_to_client: 0,
+        }
+    }
+
+    /// The end record matching this start record, timestamped now.
+    pub(crate) fn ended(&self, client_to_target: u64, target_to_client: u64) -> Self {
+        Self {
+            ended: Some(SystemTime::now()),
+            client_to_target,
+            target_to_client,
+            ..self.clone()
+        }
+    }
+}
+
+/// The default [`AuditSink`], which discards every event.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopAudit;
+
+impl AuditSink for NoopAudit {
+    fn record(&self, _event: AuditEvent) -> AuditFuture<'_> {
+        Box::pin(async {})
+    }
+}
+
+/// An [`AuditSink`] that emits each event as an `INFO` event on the
+/// `simple_socks5::audit` target.
+///
+/// Available with the `tracing` feature.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingAudit;
+
+#[cfg(feature = "tracing")]
+impl AuditSink for TracingAudit {
+    fn record(&self, event: AuditEvent) -> AuditFuture<'_> {
+        Box::pin(async move {
+            let since_epoch = |t: SystemTime| {
+                t.duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis())
+            };
+            tracing::info!(
+                target: "simple_socks5::audit",
+                client = %event.client,
+                user = event.user.as_deref().unwrap_or("-"),
+                dst = %event.dst,
+                command = %event.command,
+                started_ms = since_epoch(event.started),
+                ended_ms = event.ended.map(since_epoch),
+                client_to_target = event.client_to_target,
+                target_to_client = event.target_to_client,
+                "{}",
+                if event.ended.is_some() { "connection ended" } else { "connection started" }
+            );
+        })
+    }
+}
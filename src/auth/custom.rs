@@ -0,0 +1,62 @@
+//! Sub-negotiation of authentication methods the crate does not implement.
+//!
+//! Besides `NO AUTH` and username/password, RFC 1928 leaves room for
+//! IANA-assigned (`0x03`–`0x7F`) and private (`0x80`–`0xFE`) methods. A
+//! [`MethodHandler`] registered with
+//! [`Socks5::register_method`](crate::Socks5::register_method) takes over
+//! the stream once its method has been selected and runs whatever exchange
+//! the method defines.
+//!
+//! ```
+//! use simple_socks5::auth::custom::{MethodFuture, MethodHandler, MethodStream};
+//! use simple_socks5::error::SocksError;
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! /// Private method: the client sends a one-byte key, answered with
+//! /// `0x00` if it matches and `0x01` otherwise.
+//! struct SharedKey(u8);
+//!
+//! impl MethodHandler for SharedKey {
+//!     fn authenticate<'a>(&'a self, stream: &'a mut dyn MethodStream) -> MethodFuture<'a> {
+//!         Box::pin(async move {
+//!             let key = stream.read_u8().await?;
+//!             if key != self.0 {
+//!                 stream.write_all(&[0x01]).await?;
+//!                 return Err(SocksError::AuthFailed("wrong key".into()));
+//!             }
+//!             stream.write_all(&[0x00]).await?;
+//!             Ok(None)
+//!         })
+//!     }
+//! }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::error::SocksError;
+
+/// Future returned by [`MethodHandler::authenticate`].
+pub type MethodFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Option<String>, SocksError>> + Send + 'a>>;
+
+/// A client stream handed to a [`MethodHandler`].
+///
+/// Implemented for every stream the server can authenticate.
+pub trait MethodStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> MethodStream for S {}
+
+/// Runs the sub-negotiation of a registered authentication method.
+pub trait MethodHandler: Send + Sync {
+    /// Authenticates the client on `stream`, right after the server has
+    /// selected the method.
+    ///
+    /// The handler owns the exchange, including any failure reply the
+    /// method defines. Returns the identity the client authenticated as,
+    /// reported as [`AuthContext::username`](crate::auth::AuthContext::username),
+    /// or an error, usually [`SocksError::AuthFailed`], to close the
+    /// connection.
+    fn authenticate<'a>(&'a self, stream: &'a mut dyn MethodStream) -> MethodFuture<'a>;
+}
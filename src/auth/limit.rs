@@ -0,0 +1,54 @@
+//! Per-client limit on failed username/password attempts.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Failures of one client IP within the current window.
+struct Failures {
+    count: u32,
+    since: Instant,
+}
+
+/// Blocks a client IP once it has failed `max_failures` times within
+/// `window` of its first failure, until that window ends.
+pub(crate) struct AuthLimiter {
+    max_failures: u32,
+    window: Duration,
+    clients: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl AuthLimiter {
+    pub(crate) fn new(max_failures: u32, window: Duration) -> Self {
+        Self {
+            max_failures,
+            window,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns `true` if `ip` has used up its failures in the current window.
+    pub(crate) fn is_blocked(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        self.clients
+            .lock()
+            .unwrap()
+            .get(&ip)
+            .is_some_and(|f| f.count >= self.max_failures && now - f.since < self.window)
+    }
+
+    /// Counts a failed attempt from `ip`, pruning windows that have ended.
+    pub(crate) fn record_failure(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|_, f| now - f.since < self.window);
+        clients
+            .entry(ip)
+            .or_insert(Failures {
+                count: 0,
+                since: now,
+            })
+            .count += 1;
+    }
+}
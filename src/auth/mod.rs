@@ -1,3 +1,100 @@
+pub mod custom;
+pub(crate) mod limit;
 pub mod reply;
 pub mod request;
+#[cfg(feature = "argon2")]
+pub mod store;
 
+use std::any::Any;
+use std::sync::Arc;
+
+use subtle::ConstantTimeEq;
+
+use crate::msg::method::Method;
+
+/// Compares a secret the server knows with one a client sent, in time that
+/// does not depend on where they first differ.
+///
+/// Validators passed to [`Socks5::allow_userpass`](crate::Socks5::allow_userpass)
+/// that check passwords or tokens should use this rather than `==`, which
+/// returns as soon as a byte differs and so lets a client guess the secret
+/// one byte at a time by timing the replies. Only the lengths of the two
+/// values can be told apart.
+///
+/// # Example
+/// ```
+/// use simple_socks5::auth::verify_constant_time;
+///
+/// assert!(verify_constant_time("secret", "secret"));
+/// assert!(!verify_constant_time("secret", "secreT"));
+/// assert!(!verify_constant_time("secret", "secret2"));
+/// ```
+pub fn verify_constant_time(expected: &str, provided: &str) -> bool {
+    expected.as_bytes().ct_eq(provided.as_bytes()).into()
+}
+
+/// Per-user data returned by a validator registered with
+/// [`Socks5::allow_userpass_ctx`](crate::Socks5::allow_userpass_ctx).
+pub type UserContext = Arc<dyn Any + Send + Sync>;
+
+/// The outcome of a successful [`Socks5::authenticate`](crate::Socks5::authenticate).
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    /// The method the server selected.
+    pub method: Method,
+    /// The username the client authenticated as, when the method was
+    /// username/password.
+    pub username: Option<String>,
+    /// The data the validator attached to the user, see
+    /// [`Socks5::allow_userpass_ctx`](crate::Socks5::allow_userpass_ctx).
+    pub context: Option<UserContext>,
+}
+
+impl AuthContext {
+    /// The context of a client that did not authenticate.
+    pub(crate) fn no_auth() -> Self {
+        Self {
+            method: Method::no_auth(),
+            username: None,
+            context: None,
+        }
+    }
+
+    /// Returns the user's context if it is a `C`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::Arc;
+    /// use simple_socks5::auth::AuthContext;
+    /// use simple_socks5::msg::method::Method;
+    ///
+    /// struct Plan {
+    ///     bytes_per_sec: u64,
+    /// }
+    ///
+    /// let auth = AuthContext {
+    ///     method: Method::userpass(),
+    ///     username: Some("alice".into()),
+    ///     context: Some(Arc::new(Plan { bytes_per_sec: 1 << 20 })),
+    /// };
+    /// assert_eq!(auth.user_context::<Plan>().unwrap().bytes_per_sec, 1 << 20);
+    /// assert!(auth.user_context::<String>().is_none());
+    /// ```
+    pub fn user_context<C: Any>(&self) -> Option<&C> {
+        self.context.as_deref()?.downcast_ref()
+    }
+}
+
+/// Contexts are compared by identity, since they need not be comparable.
+impl PartialEq for AuthContext {
+    fn eq(&self, other: &Self) -> bool {
+        self.method == other.method
+            && self.username == other.username
+            && match (&self.context, &other.context) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+    }
+}
+
+impl Eq for AuthContext {}
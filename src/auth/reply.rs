@@ -18,19 +18,44 @@
 //!
 //! If the status is non-zero, the client MUST close the connection.
 
+use crate::AUTH_VERSION;
 use crate::error::SocksError;
 
 /// Represents the status of the authentication, as per RFC 1929.
-#[repr(u8)]
+///
+/// RFC 1929 only gives meaning to `0x00` (success); any other value is a
+/// failure, which leaves room for codes that tell failures apart, e.g. for
+/// auditing.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AuthStatus {
     /// Authentication succeeded (`STATUS = 0x00`).
-    Success = 0x00,
-    /// Authentication failed (any non-zero `STATUS`).
-    Failure = 0x01,
+    Success,
+    /// Authentication failed (`STATUS = 0x01`).
+    Failure,
+    /// Authentication failed with a specific non-zero `STATUS`, e.g. `0x02`
+    /// for a locked account. A code of `0x00` is sent as `0x01`, so it can
+    /// never read as success.
+    FailureCode(u8),
+}
+
+impl AuthStatus {
+    /// Returns the `STATUS` byte sent on the wire.
+    pub fn code(self) -> u8 {
+        match self {
+            AuthStatus::Success => 0x00,
+            AuthStatus::Failure => 0x01,
+            AuthStatus::FailureCode(code) => code.max(0x01),
+        }
+    }
+
+    /// Returns `true` for [`AuthStatus::Success`].
+    pub fn is_success(self) -> bool {
+        self == AuthStatus::Success
+    }
 }
 
 /// Represents an authentication reply sent by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct AuthReply {
     /// Authentication protocol version (`VER`), always `0x01` (RFC 1929 §2).
     pub ver: u8,
@@ -45,14 +70,17 @@ impl AuthReply {
     ///
     /// * `status` - Authentication outcome (success or failure).
     pub fn new(status: AuthStatus) -> Self {
-        Self { ver: 0x01, status }
+        Self {
+            ver: AUTH_VERSION,
+            status,
+        }
     }
 
     /// Converts the `AuthReply` into its 2–byte wire format.
     ///
     /// Layout: `[VER, STATUS]`.
     pub fn to_bytes(&self) -> [u8; 2] {
-        [self.ver, self.status as u8]
+        [self.ver, self.status.code()]
     }
 }
 
@@ -70,16 +98,16 @@ impl TryFrom<&[u8]> for AuthReply {
         }
 
         let ver = bytes[0];
-        if ver != 0x01 {
+        if ver != AUTH_VERSION {
             return Err(SocksError::UnsupportedAuthVersion(ver));
         }
 
         let status = match bytes[1] {
             0x00 => AuthStatus::Success,
-            _ => AuthStatus::Failure,
+            0x01 => AuthStatus::Failure,
+            code => AuthStatus::FailureCode(code),
         };
 
         Ok(Self { ver, status })
     }
 }
-
@@ -20,9 +20,12 @@
 //! o PASSWD  - password (1–255 bytes)
 //! ```
 
+use crate::AUTH_VERSION;
 use crate::error::SocksError;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// Represents an authentication request from a client (RFC 1929 §2).
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AuthRequest {
     /// Authentication protocol version (`VER`), always `0x01`.
     pub ver: u8,
@@ -41,11 +44,70 @@ impl AuthRequest {
     /// * `passwd` - Password for authentication.
     pub fn new(uname: String, passwd: String) -> Self {
         Self {
-            ver: 0x01,
+            ver: AUTH_VERSION,
             uname,
             passwd,
         }
     }
+
+    /// Serializes the request into the RFC 1929 wire format.
+    ///
+    /// The caller is responsible for keeping `uname` and `passwd` within
+    /// 255 bytes each; longer values overflow the length prefix.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![self.ver, self.uname.len() as u8];
+        buf.extend_from_slice(self.uname.as_bytes());
+        buf.push(self.passwd.len() as u8);
+        buf.extend_from_slice(self.passwd.as_bytes());
+        buf
+    }
+
+    /// Reads exactly one authentication request from an async stream.
+    ///
+    /// Reads the fields one length prefix at a time, so bytes a client
+    /// pipelines after the request, such as the connection request, are
+    /// left in the stream for the next read. A stream that ends before the
+    /// first byte fails with [`SocksError::ClientDisconnected`]; one that
+    /// ends later with the same error as parsing the truncated bytes with
+    /// [`TryFrom`].
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::auth::request::AuthRequest;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let bytes = AuthRequest::new("alice".into(), "secret".into()).to_bytes();
+    /// let req = AuthRequest::read_from(&mut &bytes[..]).await.unwrap();
+    /// assert_eq!(req.uname, "alice");
+    /// # }
+    /// ```
+    pub async fn read_from<R>(reader: &mut R) -> Result<Self, SocksError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut buf = Vec::with_capacity(513);
+        if (&mut *reader).take(2).read_to_end(&mut buf).await? == 0 {
+            return Err(SocksError::ClientDisconnected);
+        }
+
+        if let [AUTH_VERSION, ulen] = buf[..]
+            && ulen > 0
+        {
+            // UNAME and PLEN, then PASSWD once its length is known.
+            let want = ulen as u64 + 1;
+            let n = (&mut *reader).take(want).read_to_end(&mut buf).await?;
+            if n as u64 == want {
+                let plen = buf[buf.len() - 1];
+                (&mut *reader)
+                    .take(plen as u64)
+                    .read_to_end(&mut buf)
+                    .await?;
+            }
+        }
+
+        Self::try_from(&buf[..])
+    }
 }
 
 impl TryFrom<&[u8]> for AuthRequest {
@@ -56,19 +118,22 @@ impl TryFrom<&[u8]> for AuthRequest {
     /// # Errors
     /// - [`SocksError::AuthMessageTooShort`] if the message is shorter than 2 bytes.
     /// - [`SocksError::UnsupportedAuthVersion`] if `VER != 0x01`.
-    /// - [`SocksError::AuthFailed`] if the username or password are invalid UTF-8,
-    ///   or the buffer is truncated before expected fields.
+    /// - [`SocksError::AuthFailed`] if the username or password is empty or
+    ///   invalid UTF-8, or the buffer is truncated before expected fields.
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
         if bytes.len() < 2 {
             return Err(SocksError::AuthMessageTooShort);
         }
 
         let ver = bytes[0];
-        if ver != 0x01 {
+        if ver != AUTH_VERSION {
             return Err(SocksError::UnsupportedAuthVersion(ver));
         }
 
         let ulen = bytes[1] as usize;
+        if ulen == 0 {
+            return Err(SocksError::AuthFailed("empty username".into()));
+        }
         if bytes.len() < 2 + ulen + 1 {
             return Err(SocksError::AuthFailed("truncated before username".into()));
         }
@@ -78,6 +143,9 @@ impl TryFrom<&[u8]> for AuthRequest {
 
         let plen_index = 2 + ulen;
         let plen = bytes[plen_index] as usize;
+        if plen == 0 {
+            return Err(SocksError::AuthFailed("empty password".into()));
+        }
 
         if bytes.len() < plen_index + 1 + plen {
             return Err(SocksError::AuthFailed("truncated before password".into()));
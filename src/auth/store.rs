@@ -0,0 +1,138 @@
+//! Username/password credentials loaded from a file of Argon2 hashes.
+//!
+//! Each line of the file holds one user as `name:hash`, where the hash is
+//! an encoded Argon2 hash as produced by `argon2 -e` or most password
+//! tooling, e.g.:
+//!
+//! ```text
+//! # Lines starting with `#` and blank lines are ignored.
+//! alice:$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$...
+//! ```
+//!
+//! Install a store with
+//! [`Socks5::allow_userpass_from_file`](crate::Socks5::allow_userpass_from_file).
+//! Available with the `argon2` feature.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::error::SocksError;
+
+/// Hash that passwords of unknown users are checked against, with the
+/// parameters of the example in the module docs. It hashes the empty
+/// password, which [`CredentialStore::verify`] still rejects.
+const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$c2ltcGxlX3NvY2tzNS1kdW1teQ$IIynyrZRtckbavd4dalJiAufPMS26TsAz5QrO5thyrE";
+
+/// Users and their password hashes, loaded from a file.
+///
+/// The file can be edited while the server runs and picked up with
+/// [`CredentialStore::reload`], e.g. on `SIGHUP`:
+///
+/// ```no_run
+/// use simple_socks5::Socks5;
+/// use tokio::signal::unix::{SignalKind, signal};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut server = Socks5::bind("0.0.0.0:1080").await?;
+/// let store = server.allow_userpass_from_file("/etc/socks5/users")?;
+///
+/// let mut hangups = signal(SignalKind::hangup())?;
+/// tokio::spawn(async move {
+///     while hangups.recv().await.is_some() {
+///         if let Err(e) = store.reload() {
+///             eprintln!("keeping the old credentials: {e}");
+///         }
+///     }
+/// });
+///
+/// server.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CredentialStore {
+    path: PathBuf,
+    users: RwLock<HashMap<String, String>>,
+}
+
+impl CredentialStore {
+    /// Loads the credentials in the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::InvalidCredentialFile`] with the 1-based line number
+    ///   of the first line that is not `name:hash` with an Argon2 hash.
+    /// - [`SocksError::Io`] if the file cannot be read.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SocksError> {
+        let path = path.as_ref().to_path_buf();
+        let users = parse(&fs::read_to_string(&path)?)?;
+        Ok(Self {
+            path,
+            users: RwLock::new(users),
+        })
+    }
+
+    /// Reads the file again and replaces the credentials with its contents.
+    ///
+    /// Connections that already authenticated are not affected. If the file
+    /// cannot be read or parsed, the current credentials are kept.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`CredentialStore::load`].
+    pub fn reload(&self) -> Result<(), SocksError> {
+        let users = parse(&fs::read_to_string(&self.path)?)?;
+        *self.users.write().unwrap() = users;
+        Ok(())
+    }
+
+    /// Returns `true` if `passwd` matches the hash stored for `uname`.
+    ///
+    /// The hash is recomputed for every call and compared in constant time.
+    /// Unknown users are checked against a dummy hash with the parameters
+    /// of the example in the module docs, so they take as long as known
+    /// users and response times do not reveal which names exist.
+    ///
+    /// This is slow by design, tens of milliseconds at `m=19456`, and blocks
+    /// the calling thread. From async code, call it through
+    /// [`tokio::task::spawn_blocking`], as
+    /// [`Socks5::allow_userpass_from_file`](crate::Socks5::allow_userpass_from_file)
+    /// does.
+    pub fn verify(&self, uname: &str, passwd: &str) -> bool {
+        let (hash, known) = match self.users.read().unwrap().get(uname) {
+            Some(hash) => (hash.clone(), true),
+            None => (DUMMY_HASH.to_string(), false),
+        };
+        argon2::verify_encoded(&hash, passwd.as_bytes()).unwrap_or(false) & known
+    }
+
+    /// Returns the number of users loaded.
+    pub fn len(&self) -> usize {
+        self.users.read().unwrap().len()
+    }
+
+    /// Returns `true` if the file lists no users.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Parses the `name:hash` lines of a credential file.
+fn parse(contents: &str) -> Result<HashMap<String, String>, SocksError> {
+    let mut users = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(':') {
+            Some((name, hash)) if !name.is_empty() && hash.starts_with("$argon2") => {
+                users.insert(name.to_string(), hash.to_string());
+            }
+            _ => return Err(SocksError::InvalidCredentialFile(i + 1)),
+        }
+    }
+    Ok(users)
+}
@@ -0,0 +1,252 @@
+//! Minimal SOCKS5 client connector.
+//!
+//! This module implements the client side of the handshake described in
+//! [RFC 1928](https://www.rfc-editor.org/rfc/rfc1928) and, when credentials
+//! are supplied, the username/password subnegotiation of
+//! [RFC 1929](https://www.rfc-editor.org/rfc/rfc1929).
+//!
+//! A minimal HTTP `CONNECT` client ([RFC 9110 §9.3.6](https://www.rfc-editor.org/rfc/rfc9110#section-9.3.6))
+//! is provided as well, for upstreams that only speak HTTP.
+//!
+//! Both are used by the server to forward `CONNECT` requests through an
+//! upstream proxy, but can also be used on their own:
+//!
+//! ```no_run
+//! use simple_socks5::client;
+//! use simple_socks5::parse::AddrPort;
+//!
+//! # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+//! let dst = AddrPort::Domain("example.com".into(), 80);
+//! let (stream, bnd) = client::connect("127.0.0.1:1080", &dst, None).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::ATYP;
+use crate::auth::reply::AuthReply;
+use crate::auth::request::AuthRequest;
+use crate::conn::reply::{ConnReply, Rep};
+use crate::conn::request::ConnRequest;
+use crate::error::SocksError;
+use crate::http::base64_encode;
+use crate::msg::message::{MethodSelection, VersionMessage};
+use crate::msg::method::{FixedMethod, Method};
+use crate::parse::{AddrPort, Parse};
+
+/// Connects to `dst` through the SOCKS5 proxy listening on `proxy`.
+///
+/// The connector always offers `NO AUTH`, and additionally offers
+/// `USERNAME/PASSWORD` when `credentials` is `Some((username, password))`.
+///
+/// # Returns
+///
+/// The established stream, ready to carry application data, together with
+/// the bound address (`BND.ADDR`, `BND.PORT`) reported by the proxy.
+///
+/// # Errors
+///
+/// - [`SocksError::AuthFailed`] if the proxy rejects every offered method or
+///   the supplied credentials.
+/// - [`SocksError::UpstreamRejected`] if the proxy answers the request with a
+///   non-success reply code.
+/// - [`SocksError::InvalidDomain`] if `dst` is a domain name longer than 255
+///   bytes; nothing is sent in that case.
+/// - [`SocksError::Io`] on transport failures.
+pub async fn connect(
+    proxy: &str,
+    dst: &AddrPort,
+    credentials: Option<(&str, &str)>,
+) -> Result<(TcpStream, AddrPort), SocksError> {
+    if let AddrPort::Domain(name, port) = dst {
+        AddrPort::domain(name.as_str(), *port)?;
+    }
+
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    let mut methods = vec![Method::no_auth()];
+    if credentials.is_some() {
+        methods.push(Method::userpass());
+    }
+    let greeting = VersionMessage::new(methods);
+    stream.write_all(&greeting.to_bytes()?).await?;
+
+    let mut buf = [0u8; 2];
+    stream.read_exact(&mut buf).await?;
+    let selection = MethodSelection::try_from(&buf[..])?;
+
+    match (selection.method, credentials) {
+        (Method::Fixed(FixedMethod::NoAuth), _) => {}
+        (Method::Fixed(FixedMethod::UsePass), Some((uname, passwd))) => {
+            if uname.len() > 255 || passwd.len() > 255 {
+                return Err(SocksError::AuthFailed("credentials too long".into()));
+            }
+
+            let req = AuthRequest::new(uname.into(), passwd.into());
+            stream.write_all(&req.to_bytes()).await?;
+
+            stream.read_exact(&mut buf).await?;
+            if !AuthReply::try_from(&buf[..])?.status.is_success() {
+                return Err(SocksError::AuthFailed(
+                    "upstream rejected credentials".into(),
+                ));
+            }
+        }
+        _ => {
+            return Err(SocksError::AuthFailed(
+                "upstream offered no acceptable method".into(),
+            ));
+        }
+    }
+
+    let req = ConnRequest::connect(dst.clone());
+    stream.write_all(&req.to_bytes()?).await?;
+
+    let reply = read_conn_reply(&mut stream).await?;
+    if reply.rep != Rep::Succeeded {
+        return Err(SocksError::UpstreamRejected(reply.rep));
+    }
+
+    Ok((stream, reply.bnd))
+}
+
+/// Connects to `dst` through the HTTP proxy listening on `proxy`.
+///
+/// Sends `CONNECT host:port HTTP/1.1`, with a `Basic` `Proxy-Authorization`
+/// header when `credentials` is `Some((username, password))`, and waits for
+/// a `2xx` response before handing the tunnel back.
+///
+/// # Returns
+///
+/// The established tunnel, together with the local address of the socket
+/// to the proxy (HTTP proxies do not report a bound address).
+///
+/// # Errors
+///
+/// - [`SocksError::InvalidDomain`] if `dst` is a domain name that cannot be
+///   sent in a request line, e.g. one containing CR or LF.
+/// - [`SocksError::AuthFailed`] if the proxy answers `407`.
+/// - [`SocksError::UpstreamRejected`] for other non-`2xx` responses, carrying
+///   the closest matching [`Rep`].
+/// - [`SocksError::InvalidHttpResponse`] if the response cannot be parsed.
+/// - [`SocksError::Io`] on transport failures.
+pub async fn connect_http(
+    proxy: &str,
+    dst: &AddrPort,
+    credentials: Option<(&str, &str)>,
+) -> Result<(TcpStream, AddrPort), SocksError> {
+    let authority = http_authority(dst)?;
+    let mut stream = TcpStream::connect(proxy).await?;
+
+    let mut req = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some((uname, passwd)) = credentials {
+        let token = base64_encode(format!("{uname}:{passwd}").as_bytes());
+        req.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes()).await?;
+
+    let head = read_http_head(&mut stream).await?;
+    let status = head
+        .split(|&b| b == b'\r')
+        .next()
+        .and_then(|line| std::str::from_utf8(line).ok())
+        .and_then(|line| {
+            let mut parts = line.split(' ');
+            match (parts.next(), parts.next()) {
+                (Some(version), Some(code)) if version.starts_with("HTTP/1.") => {
+                    code.parse::<u16>().ok()
+                }
+                _ => None,
+            }
+        })
+        .ok_or(SocksError::InvalidHttpResponse)?;
+
+    match status {
+        200..=299 => {}
+        407 => {
+            return Err(SocksError::AuthFailed(
+                "upstream rejected credentials".into(),
+            ));
+        }
+        code => return Err(SocksError::UpstreamRejected(rep_for_http_status(code))),
+    }
+
+    let bnd = AddrPort::from(stream.local_addr()?);
+    Ok((stream, bnd))
+}
+
+/// Formats `dst` as the `host:port` target of an HTTP `CONNECT` request.
+///
+/// HTTP has no quoting, so a domain name that could end the request line or
+/// change the meaning of the authority (control characters, spaces, `/`,
+/// `@` and `?`) is refused with [`SocksError::InvalidDomain`].
+pub(crate) fn http_authority(dst: &AddrPort) -> Result<String, SocksError> {
+    match dst {
+        AddrPort::Domain(name, _)
+            if name.contains(|c: char| c.is_control() || matches!(c, ' ' | '/' | '@' | '?')) =>
+        {
+            Err(SocksError::InvalidDomain(name.clone()))
+        }
+        AddrPort::Domain(name, port) => Ok(format!("{name}:{port}")),
+        addr => Ok(addr.to_string()),
+    }
+}
+
+/// Maximum size of an HTTP response head accepted from an upstream proxy.
+const MAX_HTTP_HEAD: usize = 8 * 1024;
+
+/// Reads an HTTP response head up to and including the blank line.
+///
+/// Reads byte by byte so that no tunneled data past the head is consumed.
+async fn read_http_head(stream: &mut TcpStream) -> Result<Vec<u8>, SocksError> {
+    let mut head = Vec::new();
+
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HTTP_HEAD {
+            return Err(SocksError::InvalidHttpResponse);
+        }
+        head.push(stream.read_u8().await?);
+    }
+
+    Ok(head)
+}
+
+/// Maps an HTTP proxy's failure status to the closest SOCKS5 reply code.
+fn rep_for_http_status(code: u16) -> Rep {
+    match code {
+        403 => Rep::ConnectionNotAllowed,
+        404 | 502 => Rep::HostUnreachable,
+        405 | 501 => Rep::CommandNotSupported,
+        503 => Rep::NetworkUnreachable,
+        504 => Rep::TTLExpired,
+        _ => Rep::GeneralFailure,
+    }
+}
+
+/// Reads exactly one connection reply from the stream.
+async fn read_conn_reply(stream: &mut TcpStream) -> Result<ConnReply, SocksError> {
+    let mut head = [0u8; 4];
+    stream
+        .read_exact(&mut head)
+        .await
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => SocksError::ReplyTooShort,
+            _ => SocksError::Io(e),
+        })?;
+    let [ver, rep, rsv, atyp] = head;
+    let rep = Rep::from_u8(rep)?;
+    let atyp = ATYP::from_u8(atyp)?;
+    let bnd = Parse::read_addr_port(stream, atyp)
+        .await
+        .map_err(|e| match e {
+            SocksError::ConnRequestTooShort => SocksError::ReplyTooShort,
+            e => e,
+        })?;
+
+    Ok(ConnReply::new(ver, rep, rsv, atyp, bnd))
+}
@@ -19,6 +19,9 @@
 //! The BND fields are meaningful in BIND/UDP_ASSOCIATE, but may be ignored in CONNECT.
 //! ```
 
+use std::fmt;
+use std::io;
+
 use crate::ATYP;
 use crate::error::SocksError;
 use crate::parse::{AddrPort, Parse};
@@ -26,6 +29,7 @@ use crate::parse::{AddrPort, Parse};
 /// Reply codes (`REP`) for SOCKS5 connection replies (RFC 1928 §6).
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Rep {
     /// 0x00 - Succeeded
     Succeeded = 0x00,
@@ -47,6 +51,79 @@ pub enum Rep {
     AddressTypeNotSupported = 0x08,
 }
 
+impl fmt::Display for Rep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Rep::Succeeded => "succeeded",
+            Rep::GeneralFailure => "general SOCKS server failure",
+            Rep::ConnectionNotAllowed => "connection not allowed by ruleset",
+            Rep::NetworkUnreachable => "network unreachable",
+            Rep::HostUnreachable => "host unreachable",
+            Rep::ConnectionRefused => "connection refused",
+            Rep::TTLExpired => "TTL expired",
+            Rep::CommandNotSupported => "command not supported",
+            Rep::AddressTypeNotSupported => "address type not supported",
+        };
+        write!(f, "{text}")
+    }
+}
+
+impl Rep {
+    /// Parses a `REP` byte.
+    ///
+    /// # Errors
+    ///
+    /// [`SocksError::InvalidReplyCode`] for codes past `0x08`.
+    pub(crate) fn from_u8(byte: u8) -> Result<Self, SocksError> {
+        match byte {
+            0x00 => Ok(Rep::Succeeded),
+            0x01 => Ok(Rep::GeneralFailure),
+            0x02 => Ok(Rep::ConnectionNotAllowed),
+            0x03 => Ok(Rep::NetworkUnreachable),
+            0x04 => Ok(Rep::HostUnreachable),
+            0x05 => Ok(Rep::ConnectionRefused),
+            0x06 => Ok(Rep::TTLExpired),
+            0x07 => Ok(Rep::CommandNotSupported),
+            0x08 => Ok(Rep::AddressTypeNotSupported),
+            other => Err(SocksError::InvalidReplyCode(other)),
+        }
+    }
+
+    /// Maps a failed dial to the reply code describing it.
+    ///
+    /// `ConnectionRefused`, `HostUnreachable` and `NetworkUnreachable` map to
+    /// the codes of the same name, a downed network (`NetworkDown`) counts as
+    /// unreachable, and `TimedOut` maps to [`Rep::TTLExpired`]. Everything
+    /// else is a [`Rep::GeneralFailure`]. The kinds are decoded from the OS
+    /// error codes (`ECONNREFUSED`, `EHOSTUNREACH`, `ENETUNREACH`,
+    /// `ENETDOWN`, `ETIMEDOUT`), so raw errors map the same way.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io;
+    /// use simple_socks5::conn::reply::Rep;
+    ///
+    /// let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+    /// assert_eq!(Rep::from_io_error(&refused), Rep::ConnectionRefused);
+    ///
+    /// let timed_out = io::Error::from(io::ErrorKind::TimedOut);
+    /// assert_eq!(Rep::from_io_error(&timed_out), Rep::TTLExpired);
+    ///
+    /// assert_eq!(Rep::from_io_error(&io::Error::other("boom")), Rep::GeneralFailure);
+    /// ```
+    pub fn from_io_error(err: &io::Error) -> Rep {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused => Rep::ConnectionRefused,
+            io::ErrorKind::HostUnreachable => Rep::HostUnreachable,
+            io::ErrorKind::NetworkUnreachable | io::ErrorKind::NetworkDown => {
+                Rep::NetworkUnreachable
+            }
+            io::ErrorKind::TimedOut => Rep::TTLExpired,
+            _ => Rep::GeneralFailure,
+        }
+    }
+}
+
 /// Represents a SOCKS5 server reply (RFC 1928 §6).
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ConnReply {
@@ -74,27 +151,63 @@ impl ConnReply {
         }
     }
 
+    /// Checks that `RSV` is `0x00`, as RFC 1928 §6 requires.
+    ///
+    /// Parsing accepts any reserved byte; call this to reject replies from
+    /// non-compliant servers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SocksError::InvalidReserved`] with the byte if it is not zero.
+    pub fn validate_reserved(&self) -> Result<(), SocksError> {
+        match self.rsv {
+            0x00 => Ok(()),
+            rsv => Err(SocksError::InvalidReserved(rsv)),
+        }
+    }
+
     /// Serializes the reply into the SOCKS5 wire format.
-    pub fn to_bytes(&self) -> Vec<u8> {
+    ///
+    /// A domain-name `BND.ADDR` is written with its one-byte length prefix.
+    ///
+    /// # Errors
+    ///
+    /// [`SocksError::InvalidDomain`] if `BND.ADDR` is a domain name longer
+    /// than 255 bytes.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::conn::reply::{ConnReply, Rep};
+    /// use simple_socks5::parse::AddrPort;
+    /// use simple_socks5::ATYP;
+    ///
+    /// let bnd = AddrPort::domain("relay.example", 40000).unwrap();
+    /// let reply = ConnReply::new(0x05, Rep::Succeeded, 0x00, ATYP::DomainName, bnd);
+    ///
+    /// let bytes = reply.to_bytes().unwrap();
+    /// assert_eq!(bytes[4] as usize, "relay.example".len());
+    /// assert_eq!(ConnReply::try_from(&bytes[..]).unwrap(), reply);
+    ///
+    /// let long = AddrPort::Domain("x".repeat(256), 40000);
+    /// let reply = ConnReply::new(0x05, Rep::Succeeded, 0x00, ATYP::DomainName, long);
+    /// assert!(reply.to_bytes().is_err());
+    /// ```
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SocksError> {
         let mut buf = vec![self.ver, self.rep as u8, self.rsv, self.atyp as u8];
+        self.bnd.encode_into(&mut buf)?;
+        Ok(buf)
+    }
+}
 
-        match &self.bnd {
-            AddrPort::V4(addr, port) => {
-                buf.extend_from_slice(&addr.octets());
-                buf.extend_from_slice(&port.to_be_bytes());
-            }
-            AddrPort::V6(addr, port) => {
-                buf.extend_from_slice(&addr.octets());
-                buf.extend_from_slice(&port.to_be_bytes());
-            }
-            AddrPort::Domain(name, port) => {
-                buf.push(name.len() as u8);
-                buf.extend_from_slice(name.as_bytes());
-                buf.extend_from_slice(&port.to_be_bytes());
-            }
-        }
-
-        buf
+impl fmt::Display for ConnReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "SOCKS5 Reply {{")?;
+        writeln!(f, "  REP : {}", self.rep)?;
+        writeln!(f, "  ATYP: {}", self.atyp)?;
+        writeln!(f, "  BND : {}", self.bnd)?;
+        writeln!(f, "  VER : {}", self.ver)?;
+        writeln!(f, "  RSV : {}", self.rsv)?;
+        write!(f, "}}")
     }
 }
 
@@ -102,6 +215,12 @@ impl TryFrom<&[u8]> for ConnReply {
     type Error = SocksError;
 
     /// Parses a SOCKS5 connection reply from raw bytes.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::ReplyTooShort`] if the reply is truncated.
+    /// - [`SocksError::InvalidReplyCode`] if `REP` is not a known code.
+    /// - [`SocksError::InvalidAddressType`] if `ATYP` is unknown.
     fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
         if buf.len() < 4 {
             return Err(SocksError::ReplyTooShort);
@@ -109,32 +228,14 @@ impl TryFrom<&[u8]> for ConnReply {
 
         let ver = buf[0];
 
-        let rep = match buf[1] {
-            0x00 => Rep::Succeeded,
-            0x01 => Rep::GeneralFailure,
-            0x02 => Rep::ConnectionNotAllowed,
-            0x03 => Rep::NetworkUnreachable,
-            0x04 => Rep::HostUnreachable,
-            0x05 => Rep::ConnectionRefused,
-            0x06 => Rep::TTLExpired,
-            0x07 => Rep::CommandNotSupported,
-            0x08 => Rep::AddressTypeNotSupported,
-            _ => return Err(SocksError::ConnRequestTooShort),
-        };
-
+        let rep = Rep::from_u8(buf[1])?;
         let rsv = buf[2];
-
-        let atyp = match buf[3] {
-            0x01 => ATYP::V4,
-            0x03 => ATYP::DomainName,
-            0x04 => ATYP::V6,
-            other => return Err(SocksError::InvalidAddressType(other)),
-        };
+        let atyp = ATYP::from_u8(buf[3])?;
 
         let bnd = match atyp {
             ATYP::V4 => {
                 let (ip_port, _) =
-                    Parse::parse_ip_port(&buf[4..], 0x01).ok_or(SocksError::ConnRequestTooShort)?;
+                    Parse::parse_ip_port(&buf[4..], 0x01).ok_or(SocksError::ReplyTooShort)?;
                 if let AddrPort::V4(ip, port) = ip_port {
                     AddrPort::V4(ip, port)
                 } else {
@@ -143,7 +244,7 @@ impl TryFrom<&[u8]> for ConnReply {
             }
             ATYP::V6 => {
                 let (ip_port, _) =
-                    Parse::parse_ip_port(&buf[4..], 0x04).ok_or(SocksError::ConnRequestTooShort)?;
+                    Parse::parse_ip_port(&buf[4..], 0x04).ok_or(SocksError::ReplyTooShort)?;
                 if let AddrPort::V6(ip, port) = ip_port {
                     AddrPort::V6(ip, port)
                 } else {
@@ -151,13 +252,9 @@ impl TryFrom<&[u8]> for ConnReply {
                 }
             }
             ATYP::DomainName => {
-                let len = buf[4] as usize;
-                if buf.len() < 5 + len + 2 {
-                    return Err(SocksError::InvalidDomain);
-                }
-                let domain = String::from_utf8_lossy(&buf[5..5 + len]).to_string();
-                let port = u16::from_be_bytes([buf[5 + len], buf[5 + len + 1]]);
-                AddrPort::Domain(domain, port)
+                let (domain, _) =
+                    Parse::parse_domain(&buf[4..]).ok_or(SocksError::ReplyTooShort)?;
+                domain
             }
         };
 
@@ -170,4 +267,3 @@ impl TryFrom<&[u8]> for ConnReply {
         })
     }
 }
-
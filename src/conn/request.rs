@@ -23,14 +23,20 @@
 //! o DST.PORT - destination port in network byte order
 //! ```
 
-use crate::ATYP;
 use crate::error::SocksError;
 use crate::parse::{AddrPort, Parse};
-use std::fmt;
+use crate::{ATYP, SOCKS_VERSION};
+use std::{fmt, io};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Length of the longest possible request: the 4-byte header, a domain
+/// name with its length byte (1 + 255) and the port.
+pub const MAX_REQUEST_LEN: usize = 4 + 1 + 255 + 2;
 
 /// The command (`CMD`) of a SOCKS5 request (RFC 1928 §4).
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CMD {
     /// CONNECT command (0x01): establishes a TCP connection to the target host.
     Connect = 0x01,
@@ -50,8 +56,25 @@ impl fmt::Display for CMD {
     }
 }
 
+impl CMD {
+    /// Parses a `CMD` byte.
+    ///
+    /// # Errors
+    ///
+    /// [`SocksError::UnsupportedCommand`] for an unknown command.
+    pub(crate) fn from_u8(byte: u8) -> Result<Self, SocksError> {
+        match byte {
+            0x01 => Ok(CMD::Connect),
+            0x02 => Ok(CMD::Bind),
+            0x03 => Ok(CMD::UdpAssociate),
+            other => Err(SocksError::UnsupportedCommand(other)),
+        }
+    }
+}
+
 /// Represents a SOCKS5 connection request (RFC 1928 §4).
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConnRequest {
     /// Protocol version (`VER`), must be 0x05.
     pub ver: u8,
@@ -66,7 +89,14 @@ pub struct ConnRequest {
 }
 
 impl ConnRequest {
-    /// Creates a new `ConnRequest`.
+    /// Creates a new `ConnRequest` from raw field values.
+    ///
+    /// Nothing is checked: `ver` and `rsv` are sent as given, and an `atyp`
+    /// that does not match `dst`, e.g. [`ATYP::V4`] with an
+    /// [`AddrPort::Domain`], produces a request peers cannot parse. Meant
+    /// for tests and deliberately malformed requests; prefer
+    /// [`ConnRequest::connect`], [`ConnRequest::bind`] or
+    /// [`ConnRequest::udp_associate`].
     pub fn new(ver: u8, cmd: CMD, rsv: u8, atyp: ATYP, dst: AddrPort) -> Self {
         Self {
             ver,
@@ -77,27 +107,146 @@ impl ConnRequest {
         }
     }
 
+    /// Creates a well-formed `CONNECT` request for `dst`.
+    ///
+    /// `VER` is `0x05`, `RSV` is `0x00` and `ATYP` matches the variant of
+    /// `dst`.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::ATYP;
+    /// use simple_socks5::conn::request::{CMD, ConnRequest};
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// let req = ConnRequest::connect(AddrPort::domain("example.com", 443).unwrap());
+    /// assert_eq!(req.cmd, CMD::Connect);
+    /// assert_eq!(req.atyp, ATYP::DomainName);
+    /// assert_eq!(req.to_bytes().unwrap()[..5], [0x05, 0x01, 0x00, 0x03, 11]);
+    /// ```
+    pub fn connect(dst: AddrPort) -> Self {
+        Self::for_command(CMD::Connect, dst)
+    }
+
+    /// Creates a well-formed `BIND` request for `dst`, see
+    /// [`ConnRequest::connect`].
+    pub fn bind(dst: AddrPort) -> Self {
+        Self::for_command(CMD::Bind, dst)
+    }
+
+    /// Creates a well-formed `UDP ASSOCIATE` request, see
+    /// [`ConnRequest::connect`]. `dst` is the address the client will send
+    /// datagrams from, or all zeros if it does not know yet.
+    pub fn udp_associate(dst: AddrPort) -> Self {
+        Self::for_command(CMD::UdpAssociate, dst)
+    }
+
+    fn for_command(cmd: CMD, dst: AddrPort) -> Self {
+        Self::new(SOCKS_VERSION, cmd, 0x00, dst.atyp(), dst)
+    }
+
+    /// Checks that `RSV` is `0x00`, as RFC 1928 §4 requires.
+    ///
+    /// Parsing accepts any reserved byte; call this to reject requests from
+    /// non-compliant clients, as [`Socks5::set_strict`](crate::Socks5::set_strict)
+    /// does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SocksError::InvalidReserved`] with the byte if it is not zero.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::conn::request::ConnRequest;
+    ///
+    /// let bytes = [0x05, 0x01, 0x01, 0x01, 127, 0, 0, 1, 0x00, 0x50];
+    /// let req = ConnRequest::try_from(&bytes[..]).unwrap();
+    /// assert!(req.validate_reserved().is_err());
+    /// ```
+    pub fn validate_reserved(&self) -> Result<(), SocksError> {
+        match self.rsv {
+            0x00 => Ok(()),
+            rsv => Err(SocksError::InvalidReserved(rsv)),
+        }
+    }
+
     /// Serializes the request into the SOCKS5 wire format.
-    pub fn to_bytes(&self) -> Vec<u8> {
+    ///
+    /// # Errors
+    ///
+    /// [`SocksError::InvalidDomain`] if `DST.ADDR` is a domain name longer
+    /// than 255 bytes, which the one-byte length prefix cannot describe.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SocksError> {
         let mut buf = vec![self.ver, self.cmd as u8, self.rsv, self.atyp as u8];
+        self.dst.encode_into(&mut buf)?;
+        Ok(buf)
+    }
 
-        match &self.dst {
-            AddrPort::V4(addr, port) => {
-                buf.extend_from_slice(&addr.octets());
-                buf.extend_from_slice(&port.to_be_bytes());
-            }
-            AddrPort::V6(addr, port) => {
-                buf.extend_from_slice(&addr.octets());
-                buf.extend_from_slice(&port.to_be_bytes());
-            }
-            AddrPort::Domain(name, port) => {
-                buf.push(name.len() as u8);
-                buf.extend_from_slice(name.as_bytes());
-                buf.extend_from_slice(&port.to_be_bytes());
-            }
+    /// Reads exactly one connection request from an async stream.
+    ///
+    /// Unlike parsing a single `read` with [`TryFrom`], this reads the 4-byte
+    /// header, then the domain length byte (for `ATYP = 0x03`), then the
+    /// remaining address and port, so the request may arrive split across
+    /// arbitrary segment boundaries. No bytes past the request are consumed.
+    /// A stream that ends before the first byte fails with
+    /// [`SocksError::ClientDisconnected`], one that ends later with
+    /// [`SocksError::ConnRequestTooShort`].
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::conn::request::ConnRequest;
+    /// use simple_socks5::parse::AddrPort;
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let (mut client, mut server) = tokio::io::duplex(1);
+    ///
+    /// tokio::spawn(async move {
+    ///     let mut bytes = vec![0x05, 0x01, 0x00, 0x03, 11];
+    ///     bytes.extend_from_slice(b"example.com");
+    ///     bytes.extend_from_slice(&[0x00, 0x50]);
+    ///     // Feed the request one byte at a time.
+    ///     for b in bytes {
+    ///         client.write_all(&[b]).await.unwrap();
+    ///     }
+    /// });
+    ///
+    /// let req = ConnRequest::read_from(&mut server).await.unwrap();
+    /// assert_eq!(req.dst, AddrPort::Domain("example.com".into(), 80));
+    /// # }
+    /// ```
+    pub async fn read_from<R>(reader: &mut R) -> Result<Self, SocksError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut head = [0u8; 4];
+        if reader.read(&mut head[..1]).await? == 0 {
+            return Err(SocksError::ClientDisconnected);
         }
+        reader
+            .read_exact(&mut head[1..])
+            .await
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => SocksError::ConnRequestTooShort,
+                _ => SocksError::Io(e),
+            })?;
+        let [ver, cmd, rsv, atyp] = head;
+        if ver != SOCKS_VERSION {
+            return Err(SocksError::UnsupportedVersion(ver));
+        }
+        let atyp = ATYP::from_u8(atyp)?;
+        let dst = Parse::read_addr_port(reader, atyp).await?;
+        // Checked last so the whole request is consumed before the error
+        // reply goes out.
+        let cmd = CMD::from_u8(cmd)?;
 
-        buf
+        Ok(ConnRequest {
+            ver,
+            cmd,
+            rsv,
+            atyp,
+            dst,
+        })
     }
 }
 
@@ -117,28 +266,28 @@ impl TryFrom<&[u8]> for ConnRequest {
     type Error = SocksError;
 
     /// Parses a SOCKS5 connection request from raw bytes.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::ConnRequestTooShort`] if the request is truncated.
+    /// - [`SocksError::UnsupportedVersion`] if `VER` is not
+    ///   [`SOCKS_VERSION`].
+    /// - [`SocksError::UnsupportedCommand`] for an unknown `CMD`.
+    /// - [`SocksError::InvalidAddressType`] if `ATYP` is unknown.
+    /// - [`SocksError::InvalidDomain`] if a domain name is truncated.
     fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
         if buf.len() < 4 {
             return Err(SocksError::ConnRequestTooShort);
         }
 
         let ver = buf[0];
+        if ver != SOCKS_VERSION {
+            return Err(SocksError::UnsupportedVersion(ver));
+        }
 
-        let cmd = match buf[1] {
-            0x01 => CMD::Connect,
-            0x02 => CMD::Bind,
-            0x03 => CMD::UdpAssociate,
-            other => return Err(SocksError::UnsupportedCommand(other)),
-        };
-
+        let cmd = CMD::from_u8(buf[1])?;
         let rsv = buf[2];
-
-        let atyp = match buf[3] {
-            0x01 => ATYP::V4,
-            0x03 => ATYP::DomainName,
-            0x04 => ATYP::V6,
-            other => return Err(SocksError::InvalidAddressType(other)),
-        };
+        let atyp = ATYP::from_u8(buf[3])?;
 
         let dst = match atyp {
             ATYP::V4 => {
@@ -160,13 +309,15 @@ impl TryFrom<&[u8]> for ConnRequest {
                 }
             }
             ATYP::DomainName => {
-                let len = buf[4] as usize;
-                if buf.len() < 5 + len + 2 {
-                    return Err(SocksError::InvalidDomain);
-                }
-                let domain = String::from_utf8_lossy(&buf[5..5 + len]).to_string();
-                let port = u16::from_be_bytes([buf[5 + len], buf[5 + len + 1]]);
-                AddrPort::Domain(domain, port)
+                let (domain, _) = Parse::parse_domain(&buf[4..]).ok_or_else(|| {
+                    // Report whatever part of the name arrived.
+                    let partial = match &buf[4..] {
+                        [len, rest @ ..] => &rest[..rest.len().min(*len as usize)],
+                        [] => &[],
+                    };
+                    SocksError::InvalidDomain(String::from_utf8_lossy(partial).into_owned())
+                })?;
+                domain
             }
         };
 
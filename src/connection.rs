@@ -0,0 +1,190 @@
+//! A server-side connection driven through the SOCKS5 handshake.
+//!
+//! [`Connection`] reads from the client through a buffer, so a handshake
+//! that arrives in one segment costs one read, and bytes a client pipelines
+//! ahead of the server's answers are kept for the next phase rather than
+//! lost. The steps run in protocol order:
+//!
+//! 1. [`Connection::negotiate`] reads the version message and selects a
+//!    method.
+//! 2. [`Connection::authenticate`] runs the sub-negotiation of that method.
+//! 3. [`Connection::read_request`] reads the connection request.
+//!
+//! Each step runs the ones before it if they have not run yet, so calling
+//! [`Connection::read_request`] alone performs the whole handshake. The
+//! configuration, such as the enabled methods and credential validator, is
+//! taken from the [`Socks5`] passed to each step.
+//!
+//! ```no_run
+//! use simple_socks5::Socks5;
+//! use simple_socks5::connection::Connection;
+//!
+//! # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+//! let mut server = Socks5::bind("127.0.0.1:1080").await?;
+//! server.allow_no_auth();
+//!
+//! let (stream, _) = server.accept().await?;
+//! let mut conn = Connection::new(stream);
+//! let auth = conn.authenticate(&server).await?;
+//! let req = conn.read_request(&server).await?;
+//! println!("{:?} asked for {}", auth.username, req.dst);
+//!
+//! // Whatever the client sent after the request is still buffered.
+//! let (stream, early_data) = conn.into_parts();
+//! # Ok(())
+//! # }
+//! ```
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::auth::AuthContext;
+use crate::conn::request::ConnRequest;
+use crate::error::SocksError;
+use crate::msg::method::Method;
+use crate::{SOCKS_VERSION, Socks5};
+
+/// Where a [`Connection`] is in the handshake.
+#[derive(Debug)]
+enum Phase {
+    Start,
+    Negotiated(Method),
+    Authenticated(AuthContext),
+    Requested,
+    Failed,
+}
+
+/// A client connection and its progress through the handshake.
+#[derive(Debug)]
+pub struct Connection {
+    stream: BufReader<TcpStream>,
+    phase: Phase,
+}
+
+impl Connection {
+    /// Wraps a freshly accepted client stream.
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            phase: Phase::Start,
+        }
+    }
+
+    /// Reads the version message and answers it with the selected method.
+    ///
+    /// The method is chosen as in [`Socks5::authenticate`]; a client
+    /// offering none of the enabled methods is refused the same way.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::HandshakeOutOfOrder`] if this step already ran.
+    /// - The errors of [`Socks5::authenticate`] for the version message.
+    pub async fn negotiate(&mut self, server: &Socks5) -> Result<Method, SocksError> {
+        if !matches!(self.phase, Phase::Start) {
+            return Err(SocksError::HandshakeOutOfOrder);
+        }
+
+        let result = async {
+            match self.stream.fill_buf().await?.first() {
+                None => return Err(SocksError::ClientDisconnected),
+                Some(&ver) if ver != SOCKS_VERSION => {
+                    return Err(SocksError::UnsupportedVersion(ver));
+                }
+                Some(_) => {}
+            }
+            server.negotiate(&mut self.stream).await
+        }
+        .await;
+
+        self.advance(result, |&method| Phase::Negotiated(method))
+    }
+
+    /// Runs the sub-negotiation of the selected method, negotiating first
+    /// if [`Connection::negotiate`] has not run.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::HandshakeOutOfOrder`] if this step already ran or an
+    ///   earlier one failed.
+    /// - The errors of [`Socks5::authenticate`].
+    pub async fn authenticate(&mut self, server: &Socks5) -> Result<AuthContext, SocksError> {
+        if matches!(self.phase, Phase::Start) {
+            self.negotiate(server).await?;
+        }
+        let Phase::Negotiated(selected) = self.phase else {
+            return Err(SocksError::HandshakeOutOfOrder);
+        };
+
+        let result = async {
+            let peer = server.limiter_peer(self.stream.get_ref().peer_addr()?);
+            server
+                .authenticate_method(&mut self.stream, selected, peer)
+                .await
+        }
+        .await;
+
+        self.advance(result, |auth| Phase::Authenticated(auth.clone()))
+    }
+
+    /// Reads the connection request, authenticating first if
+    /// [`Connection::authenticate`] has not run.
+    ///
+    /// A request that cannot be parsed is answered with the matching reply
+    /// before the error is returned. Answering a valid request is left to
+    /// the caller, e.g. with [`Socks5::send_conn_reply`] on the stream from
+    /// [`Connection::into_parts`].
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::HandshakeOutOfOrder`] if this step already ran or an
+    ///   earlier one failed.
+    /// - The errors of [`Socks5::read_conn_request`].
+    /// - [`SocksError::RequestRejected`] if the inspector set with
+    ///   [`Socks5::set_request_inspector`] refused the request.
+    pub async fn read_request(&mut self, server: &Socks5) -> Result<ConnRequest, SocksError> {
+        if matches!(self.phase, Phase::Start | Phase::Negotiated(_)) {
+            self.authenticate(server).await?;
+        }
+        let Phase::Authenticated(auth) = &self.phase else {
+            return Err(SocksError::HandshakeOutOfOrder);
+        };
+
+        let result = server.read_request(&mut self.stream, auth).await;
+        self.advance(result, |_| Phase::Requested)
+    }
+
+    /// The client stream.
+    pub fn get_ref(&self) -> &TcpStream {
+        self.stream.get_ref()
+    }
+
+    /// Bytes received from the client that no step has consumed yet.
+    pub fn buffered(&self) -> &[u8] {
+        self.stream.buffer()
+    }
+
+    /// Returns the client stream and the bytes that were read from it but
+    /// not consumed, which come before anything still to be read from the
+    /// stream.
+    ///
+    /// After [`Connection::read_request`], these are the first bytes of the
+    /// client's payload and must be forwarded ahead of the relay.
+    pub fn into_parts(self) -> (TcpStream, Vec<u8>) {
+        let buffered = self.stream.buffer().to_vec();
+        (self.stream.into_inner(), buffered)
+    }
+
+    /// Moves to the phase built from a successful step, or to
+    /// [`Phase::Failed`].
+    fn advance<T>(
+        &mut self,
+        result: Result<T, SocksError>,
+        next: impl FnOnce(&T) -> Phase,
+    ) -> Result<T, SocksError> {
+        self.phase = match &result {
+            Ok(value) => next(value),
+            Err(_) => Phase::Failed,
+        };
+        result
+    }
+}
@@ -0,0 +1,243 @@
+//! Outbound connection establishment.
+//!
+//! For `CONNECT` requests the server resolves the destination itself and
+//! hands every candidate address to a [`Connector`], in order, until one
+//! connects. When the client asked for a domain name, the original hostname
+//! travels along with the resolved address, so a custom connector can still
+//! use it after resolution, e.g. for TLS SNI or a PROXY protocol TLV.
+//!
+//! ```
+//! use simple_socks5::connector::{ConnectFuture, Connector, Destination};
+//! use tokio::net::TcpStream;
+//!
+//! struct LoggingConnector;
+//!
+//! impl Connector for LoggingConnector {
+//!     fn connect<'a>(&'a self, dst: &'a Destination) -> ConnectFuture<'a> {
+//!         Box::pin(async move {
+//!             println!("dialing {} for {:?}", dst.addr, dst.hostname);
+//!             TcpStream::connect(dst.addr).await
+//!         })
+//!     }
+//! }
+//! ```
+//!
+//! If every candidate fails, [`ConnectErrorPolicy`] decides which of the
+//! errors is reported to the client.
+//!
+//! With [`Socks5::set_happy_eyeballs`](crate::Socks5::set_happy_eyeballs)
+//! the candidates are raced instead of tried one after the other: each
+//! attempt gets a head start of [`HAPPY_EYEBALLS_DELAY`] before the next
+//! one begins, as in RFC 8305, so an unreachable address family costs a
+//! fraction of a second rather than a full connect timeout.
+//!
+//! [`BoundConnector`] dials from a fixed source address, for hosts with
+//! more than one.
+//!
+//! On Linux, the `netns` feature adds [`NetnsConnector`], which dials from
+//! inside another network namespace.
+
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::{Pin, pin};
+use std::task::Poll;
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::time::{self, Instant};
+
+#[cfg(all(target_os = "linux", feature = "netns"))]
+mod netns;
+#[cfg(all(target_os = "linux", feature = "netns"))]
+pub use netns::NetnsConnector;
+
+/// Future returned by [`Connector::connect`].
+pub type ConnectFuture<'a> = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send + 'a>>;
+
+/// How long a Happy Eyeballs attempt runs alone before the next candidate
+/// is dialed alongside it, the default recommended by RFC 8305 §5.
+pub const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// A resolved destination handed to a [`Connector`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    /// The address to connect to.
+    pub addr: SocketAddr,
+    /// The hostname requested by the client, if the request carried a
+    /// domain name rather than an IP address.
+    pub hostname: Option<String>,
+}
+
+/// Opens outbound TCP connections on behalf of the server.
+pub trait Connector: Send + Sync {
+    /// Connects to a single resolved destination.
+    fn connect<'a>(&'a self, dst: &'a Destination) -> ConnectFuture<'a>;
+}
+
+/// The default [`Connector`], which dials the address with no extra setup.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DirectConnector;
+
+impl Connector for DirectConnector {
+    fn connect<'a>(&'a self, dst: &'a Destination) -> ConnectFuture<'a> {
+        Box::pin(TcpStream::connect(dst.addr))
+    }
+}
+
+/// A [`Connector`] that binds every outbound socket to a source address
+/// before connecting, so traffic leaves from that address on a multi-homed
+/// host. Installed by [`Socks5::set_egress_bind`](crate::Socks5::set_egress_bind).
+///
+/// A destination of the other address family cannot be reached from the
+/// source address and fails with [`io::ErrorKind::InvalidInput`], which is
+/// answered with [`Rep::GeneralFailure`](crate::conn::reply::Rep::GeneralFailure).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundConnector {
+    local: IpAddr,
+}
+
+impl BoundConnector {
+    /// Dials from `local`, with a port picked by the system.
+    pub fn new(local: IpAddr) -> Self {
+        Self { local }
+    }
+
+    /// The source address outbound sockets are bound to.
+    pub fn local(&self) -> IpAddr {
+        self.local
+    }
+}
+
+impl Connector for BoundConnector {
+    fn connect<'a>(&'a self, dst: &'a Destination) -> ConnectFuture<'a> {
+        Box::pin(async move {
+            let socket = match (self.local, dst.addr) {
+                (IpAddr::V4(_), SocketAddr::V4(_)) => TcpSocket::new_v4()?,
+                (IpAddr::V6(_), SocketAddr::V6(_)) => TcpSocket::new_v6()?,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("cannot reach {} from {}", dst.addr, self.local),
+                    ));
+                }
+            };
+            socket.bind(SocketAddr::new(self.local, 0))?;
+            socket.connect(dst.addr).await
+        })
+    }
+}
+
+/// Dials `addrs` in order, starting the next attempt when the previous one
+/// fails or has run for `delay`, and returns the first stream to connect.
+///
+/// The other attempts are dropped once one succeeds. If all fail, their
+/// errors are returned in dialing order.
+pub(crate) async fn race<'a, F>(
+    addrs: Vec<SocketAddr>,
+    delay: Duration,
+    mut dial: F,
+) -> Result<TcpStream, Vec<io::Error>>
+where
+    F: FnMut(SocketAddr) -> ConnectFuture<'a>,
+{
+    let mut pending = addrs.into_iter().peekable();
+    let mut attempts: Vec<(usize, ConnectFuture<'a>)> = Vec::new();
+    let mut errors: Vec<(usize, io::Error)> = Vec::new();
+    let mut started = 0;
+    let mut next_attempt = pin!(time::sleep(Duration::ZERO));
+
+    while pending.peek().is_some() || !attempts.is_empty() {
+        let (index, result) = std::future::poll_fn(|cx| {
+            while pending.peek().is_some() && next_attempt.as_mut().poll(cx).is_ready() {
+                let addr = pending.next().expect("peeked");
+                attempts.push((started, dial(addr)));
+                started += 1;
+                next_attempt.as_mut().reset(Instant::now() + delay);
+            }
+            for (i, (index, attempt)) in attempts.iter_mut().enumerate() {
+                if let Poll::Ready(result) = attempt.as_mut().poll(cx) {
+                    let index = *index;
+                    drop(attempts.swap_remove(i));
+                    return Poll::Ready((index, result));
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => {
+                errors.push((index, e));
+                // A failed attempt hands its head start to the next one.
+                next_attempt.as_mut().reset(Instant::now());
+            }
+        }
+    }
+
+    errors.sort_by_key(|(index, _)| *index);
+    Err(errors.into_iter().map(|(_, e)| e).collect())
+}
+
+/// Chooses the error reported when every candidate address of a `CONNECT`
+/// fails, set with
+/// [`Socks5::set_connect_reply_on_partial_success`](crate::Socks5::set_connect_reply_on_partial_success).
+///
+/// A failed candidate followed by a successful one is not an error: the
+/// client gets a single [`Rep::Succeeded`](crate::conn::reply::Rep::Succeeded).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectErrorPolicy {
+    /// Report the error of the first candidate tried.
+    FirstError,
+    /// Report the error of the last candidate tried.
+    #[default]
+    LastError,
+    /// Report the error that says the most about the destination:
+    /// connection refused, then host unreachable, then network unreachable,
+    /// then timed out, then anything else. Ties go to the later candidate.
+    MostSpecific,
+}
+
+impl ConnectErrorPolicy {
+    /// Picks one error out of the per-candidate errors, in dialing order.
+    ///
+    /// Returns `None` if `errors` is empty.
+    ///
+    /// ```
+    /// use std::io::{Error, ErrorKind};
+    /// use simple_socks5::connector::ConnectErrorPolicy;
+    ///
+    /// let errors = || {
+    ///     vec![
+    ///         Error::from(ErrorKind::TimedOut),
+    ///         Error::from(ErrorKind::ConnectionRefused),
+    ///         Error::from(ErrorKind::HostUnreachable),
+    ///     ]
+    /// };
+    ///
+    /// let pick = |policy: ConnectErrorPolicy| policy.select(errors()).unwrap().kind();
+    /// assert_eq!(pick(ConnectErrorPolicy::FirstError), ErrorKind::TimedOut);
+    /// assert_eq!(pick(ConnectErrorPolicy::LastError), ErrorKind::HostUnreachable);
+    /// assert_eq!(pick(ConnectErrorPolicy::MostSpecific), ErrorKind::ConnectionRefused);
+    /// ```
+    pub fn select(self, errors: Vec<io::Error>) -> Option<io::Error> {
+        match self {
+            ConnectErrorPolicy::FirstError => errors.into_iter().next(),
+            ConnectErrorPolicy::LastError => errors.into_iter().last(),
+            ConnectErrorPolicy::MostSpecific => {
+                errors.into_iter().max_by_key(|e| specificity(e.kind()))
+            }
+        }
+    }
+}
+
+/// Ranks how much an error tells the client about the destination.
+fn specificity(kind: io::ErrorKind) -> u8 {
+    match kind {
+        io::ErrorKind::ConnectionRefused => 4,
+        io::ErrorKind::HostUnreachable => 3,
+        io::ErrorKind::NetworkUnreachable => 2,
+        io::ErrorKind::TimedOut => 1,
+        _ => 0,
+    }
+}
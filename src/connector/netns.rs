@@ -0,0 +1,95 @@
+//! Dialing from inside a Linux network namespace.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use tokio::net::TcpSocket;
+use tokio::sync::oneshot;
+
+use super::{ConnectFuture, Connector, Destination};
+
+/// A [`Connector`] whose outbound sockets are created in another network
+/// namespace, so connections originate from that namespace's interfaces and
+/// routes while the server keeps listening in its own.
+///
+/// The namespace is a per-thread property, so each connect enters it with
+/// `setns(2)` on a short-lived thread, creates the socket there and hands it
+/// back to the runtime to connect. Tokio's worker threads never change
+/// namespace. Entering a namespace needs `CAP_SYS_ADMIN`; without it every
+/// connect fails with the `setns` error.
+///
+/// Available on Linux with the `netns` feature.
+///
+/// ```no_run
+/// use simple_socks5::Socks5;
+/// use simple_socks5::connector::NetnsConnector;
+///
+/// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+/// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+/// server.allow_no_auth();
+/// server.set_connector(NetnsConnector::open("/run/netns/tenant-a")?);
+/// server.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct NetnsConnector {
+    ns: Arc<File>,
+}
+
+impl NetnsConnector {
+    /// Opens the namespace at `path`, e.g. `/run/netns/<name>` as created by
+    /// `ip netns add`, or `/proc/<pid>/ns/net`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file cannot be opened.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_file(File::open(path)?))
+    }
+
+    /// Uses an already open namespace file.
+    pub fn from_file(ns: File) -> Self {
+        Self { ns: Arc::new(ns) }
+    }
+
+    /// Creates a socket for `dst` inside the namespace.
+    async fn socket(&self, dst: &Destination) -> io::Result<TcpSocket> {
+        let ns = Arc::clone(&self.ns);
+        let ipv4 = dst.addr.is_ipv4();
+        let (tx, rx) = oneshot::channel();
+
+        // The thread exits right after, so it never has to switch back.
+        thread::spawn(move || {
+            let socket = enter(&ns).and_then(|()| {
+                if ipv4 {
+                    TcpSocket::new_v4()
+                } else {
+                    TcpSocket::new_v6()
+                }
+            });
+            let _ = tx.send(socket);
+        });
+
+        rx.await
+            .map_err(|_| io::Error::other("namespace thread exited"))?
+    }
+}
+
+/// Moves the current thread into the network namespace `ns`.
+fn enter(ns: &File) -> io::Result<()> {
+    // SAFETY: `setns` only reads the descriptor, which `ns` keeps open.
+    if unsafe { libc::setns(ns.as_raw_fd(), libc::CLONE_NEWNET) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl Connector for NetnsConnector {
+    fn connect<'a>(&'a self, dst: &'a Destination) -> ConnectFuture<'a> {
+        Box::pin(async move { self.socket(dst).await?.connect(dst.addr).await })
+    }
+}
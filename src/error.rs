@@ -10,8 +10,12 @@
 //! Each variant carries enough context to help diagnose protocol violations
 //! or unexpected input during parsing.
 
+use std::io;
 use thiserror::Error;
 
+use crate::conn::reply::Rep;
+use crate::parse::AddrPort;
+
 /// Represents all possible errors that can occur while using the SOCKS5 server.
 #[derive(Debug, Error)]
 pub enum SocksError {
@@ -28,6 +32,22 @@ pub enum SocksError {
     #[error("incomplete version message")]
     IncompleteVersionMessage,
 
+    /// A version message listed no authentication methods.
+    #[error("no authentication methods offered")]
+    NoMethodsOffered,
+
+    /// A version message listed more methods than `NMETHODS` can express.
+    #[error("too many authentication methods: {0}")]
+    TooManyMethods(usize),
+
+    /// None of the methods offered by the client is enabled on the server.
+    ///
+    /// The client has already been answered with `NO ACCEPTABLE METHODS`
+    /// (`0xFF`) and the connection closed for writing, so this is an
+    /// expected outcome rather than a fault.
+    #[error("no acceptable authentication method offered")]
+    NoAcceptableMethod,
+
     /// The client requested an unknown or invalid authentication method.
     #[error("unknown authentication method: {0}")]
     UnknownMethod(u8),
@@ -50,14 +70,25 @@ pub enum SocksError {
     #[error("invalid address type: {0}")]
     InvalidAddressType(u8),
 
-    /// The client provided an invalid or malformed domain name.
-    #[error("invalid domain name")]
-    InvalidDomain,
+    /// The client sent an IP address as a domain name while the server
+    /// requires IP addresses to use their own address type.
+    #[error("IP address {0} sent as a domain name")]
+    IpLiteralInDomain(String),
+
+    /// The client provided an invalid or malformed domain name. Carries the
+    /// name, or as much of it as was received.
+    #[error("invalid domain name: {0:?}")]
+    InvalidDomain(String),
 
     /// The connection request from the client was too short.
     #[error("connection request too short")]
     ConnRequestTooShort,
 
+    /// A request or reply carried a non-zero `RSV` byte, rejected in
+    /// strict mode.
+    #[error("reserved byte is not zero: {0:#04x}")]
+    InvalidReserved(u8),
+
     /// The client requested an unsupported command (e.g., not CONNECT/BIND/UDP).
     #[error("unsupported command: {0}")]
     UnsupportedCommand(u8),
@@ -66,9 +97,186 @@ pub enum SocksError {
     #[error("reply too short")]
     ReplyTooShort,
 
+    /// The reply from the server carried a `REP` code outside the RFC 1928
+    /// range.
+    #[error("invalid reply code: {0:#04x}")]
+    InvalidReplyCode(u8),
+
+    /// The host name of the destination could not be resolved.
+    #[error("failed to resolve {host}: {source}")]
+    DnsResolution {
+        /// The host name that was looked up.
+        host: String,
+        /// The error returned by the resolver.
+        #[source]
+        source: io::Error,
+    },
+
+    /// Dialing the destination failed.
+    ///
+    /// Carries the error picked by the
+    /// [`ConnectErrorPolicy`](crate::connector::ConnectErrorPolicy) when
+    /// several addresses were tried.
+    #[error("failed to connect to {dst}: {source}")]
+    ConnectFailed {
+        /// The destination that was dialed.
+        dst: AddrPort,
+        /// The underlying error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// The request was refused by the inspector set with
+    /// [`Socks5::set_request_inspector`](crate::Socks5::set_request_inspector),
+    /// which picked the reply code.
+    #[error("request rejected with {0:?}")]
+    RequestRejected(Rep),
+
+    /// The destination was rejected by the server's policy.
+    #[error("connection to {0} not allowed")]
+    ConnectionNotAllowed(AddrPort),
+
+    /// The upstream proxy answered a forwarded request with a failure reply.
+    #[error("upstream proxy rejected request: {0:?}")]
+    UpstreamRejected(Rep),
+
+    /// The upstream HTTP proxy sent a response that could not be parsed.
+    #[error("invalid HTTP response from upstream proxy")]
+    InvalidHttpResponse,
+
+    /// A client of the HTTP `CONNECT` listener sent a request that could not
+    /// be parsed or used a method other than `CONNECT`.
+    #[error("invalid HTTP request")]
+    InvalidHttpRequest,
+
+    /// A SOCKS4 client sent a `USERID` or host name that is longer than
+    /// 255 bytes or not UTF-8.
+    #[error("invalid SOCKS4 request")]
+    InvalidSocks4Request,
+
     // ===== General =====
+    /// The listen address is already taken by another socket.
+    #[error("address {addr} already in use")]
+    AddrInUse {
+        /// The address that was passed to `bind`.
+        addr: String,
+        /// The underlying error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// Binding the listen address requires privileges the process lacks,
+    /// e.g. a port below 1024 without `CAP_NET_BIND_SERVICE`.
+    #[error("permission denied binding {addr}")]
+    BindPermissionDenied {
+        /// The address that was passed to `bind`.
+        addr: String,
+        /// The underlying error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// A line of a credential file is not a `name:hash` entry with an
+    /// Argon2 hash. Carries the 1-based line number.
+    #[error("invalid credential file entry on line {0}")]
+    InvalidCredentialFile(usize),
+
+    /// A relay buffer size outside the accepted range was configured.
+    #[error("invalid relay buffer size: {0}")]
+    InvalidBufferSize(usize),
+
+    /// A string could not be parsed as a `host:port` address.
+    #[error("invalid address: {0}")]
+    InvalidAddress(String),
+
+    /// A [`Connection`](crate::connection::Connection) step was called after
+    /// it had already run, or after an earlier step failed.
+    #[error("handshake step called out of order")]
+    HandshakeOutOfOrder,
+
+    /// The client closed the connection before sending the next handshake
+    /// message. Under normal churn this is a benign disconnect rather than
+    /// a protocol violation.
+    #[error("client disconnected during the handshake")]
+    ClientDisconnected,
+
+    /// A relay was closed because no bytes moved in either direction for
+    /// the configured idle timeout.
+    #[error("connection idle for too long")]
+    IdleTimeout,
+
+    /// The TLS handshake with a client of a
+    /// [`Socks5::bind_tls`](crate::Socks5::bind_tls) server failed.
+    #[error("TLS handshake failed: {0}")]
+    Tls(#[source] io::Error),
+
     /// A general I/O error occurred in the underlying transport.
     #[error("I/O error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(#[from] io::Error),
 }
 
+impl SocksError {
+    /// Maps the error to the reply code a server should send for it.
+    ///
+    /// Failed lookups and dials, like other I/O errors, are mapped with
+    /// [`Rep::from_io_error`].
+    ///
+    /// Returns `None` for errors raised before the request phase, such as
+    /// version or authentication failures, and for configuration errors,
+    /// since no connection reply applies to them.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::conn::reply::Rep;
+    /// use simple_socks5::error::SocksError;
+    ///
+    /// let err = SocksError::InvalidAddressType(0x02);
+    /// assert_eq!(err.to_rep(), Some(Rep::AddressTypeNotSupported));
+    ///
+    /// let err = SocksError::UnsupportedCommand(0x09);
+    /// assert_eq!(err.to_rep(), Some(Rep::CommandNotSupported));
+    ///
+    /// assert_eq!(SocksError::AuthFailed("bad".into()).to_rep(), None);
+    /// ```
+    pub fn to_rep(&self) -> Option<Rep> {
+        let rep = match self {
+            SocksError::UnsupportedCommand(_) => Rep::CommandNotSupported,
+            SocksError::InvalidAddressType(_) | SocksError::IpLiteralInDomain(_) => {
+                Rep::AddressTypeNotSupported
+            }
+            SocksError::ConnectionNotAllowed(_) => Rep::ConnectionNotAllowed,
+            SocksError::UpstreamRejected(rep) | SocksError::RequestRejected(rep) => *rep,
+            SocksError::InvalidDomain(_)
+            | SocksError::ConnRequestTooShort
+            | SocksError::InvalidReserved(_)
+            | SocksError::InvalidHttpResponse => Rep::GeneralFailure,
+            SocksError::DnsResolution { source, .. }
+            | SocksError::ConnectFailed { source, .. }
+            | SocksError::Io(source) => Rep::from_io_error(source),
+            SocksError::UnsupportedVersion(_)
+            | SocksError::VersionMessageTooShort
+            | SocksError::IncompleteVersionMessage
+            | SocksError::NoMethodsOffered
+            | SocksError::NoAcceptableMethod
+            | SocksError::TooManyMethods(_)
+            | SocksError::UnknownMethod(_)
+            | SocksError::UnsupportedAuthVersion(_)
+            | SocksError::AuthMessageTooShort
+            | SocksError::AuthFailed(_)
+            | SocksError::ReplyTooShort
+            | SocksError::InvalidReplyCode(_)
+            | SocksError::InvalidHttpRequest
+            | SocksError::InvalidSocks4Request
+            | SocksError::InvalidAddress(_)
+            | SocksError::HandshakeOutOfOrder
+            | SocksError::IdleTimeout
+            | SocksError::ClientDisconnected
+            | SocksError::Tls(_)
+            | SocksError::AddrInUse { .. }
+            | SocksError::BindPermissionDenied { .. }
+            | SocksError::InvalidBufferSize(_)
+            | SocksError::InvalidCredentialFile(_) => return None,
+        };
+        Some(rep)
+    }
+}
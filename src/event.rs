@@ -0,0 +1,73 @@
+//! Connection lifecycle events.
+//!
+//! [`Socks5::events`](crate::Socks5::events) hands out a bounded channel of
+//! [`Event`]s. The server never waits for the consumer: when the channel is
+//! full, the event is dropped and counted in
+//! [`MetricsSnapshot::events_dropped`](crate::metrics::MetricsSnapshot::events_dropped),
+//! so a slow consumer cannot slow down proxying but can still detect gaps.
+//!
+//! ```no_run
+//! use simple_socks5::Socks5;
+//!
+//! # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+//! let mut server = Socks5::bind("127.0.0.1:1080").await?;
+//! server.allow_no_auth();
+//! server.set_event_buffer(256);
+//! let mut events = server.events();
+//!
+//! tokio::spawn(async move {
+//!     while let Some(event) = events.recv().await {
+//!         println!("{event:?}");
+//!     }
+//! });
+//! server.run().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::net::SocketAddr;
+
+use crate::parse::AddrPort;
+use crate::relay::RelayStats;
+
+/// Default capacity of the event channel.
+pub(crate) const DEFAULT_EVENT_BUFFER: usize = 1024;
+
+/// Something that happened to a client connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A client connected and is about to negotiate.
+    Connected {
+        /// Address of the client.
+        client: SocketAddr,
+    },
+    /// A `CONNECT` request succeeded and relaying started.
+    RelayStarted {
+        /// Address of the client.
+        client: SocketAddr,
+        /// Destination requested by the client.
+        dst: AddrPort,
+    },
+    /// The first bytes of a connection belong to no protocol the server
+    /// serves, e.g. a TLS `ClientHello` without
+    /// `Socks5::set_tls_on_socks_port`, or an HTTP request without
+    /// [`Socks5::allow_http_connect`](crate::Socks5::allow_http_connect).
+    /// The connection then fails with
+    /// [`SocksError::UnsupportedVersion`](crate::error::SocksError::UnsupportedVersion).
+    ProtocolMismatch {
+        /// Address of the client.
+        client: SocketAddr,
+        /// Up to two bytes peeked from the connection, enough to tell a TLS
+        /// record (`0x16 0x03`) apart.
+        first_bytes: Vec<u8>,
+    },
+    /// A relay ended.
+    RelayClosed {
+        /// Address of the client.
+        client: SocketAddr,
+        /// Destination requested by the client.
+        dst: AddrPort,
+        /// Byte totals and the reason the relay ended.
+        stats: RelayStats,
+    },
+}
@@ -0,0 +1,130 @@
+//! Pluggable handling of parsed client requests.
+//!
+//! [`Socks5::serve`](crate::Socks5::serve) runs the accept loop, negotiates
+//! authentication and reads the connection request, then hands the stream
+//! to a [`ConnectionHandler`]. The handler owns everything after that,
+//! including sending the reply, so custom `CONNECT` behaviour does not have
+//! to reimplement the handshake.
+//!
+//! ```no_run
+//! use std::net::{Ipv4Addr, SocketAddr};
+//! use simple_socks5::auth::AuthContext;
+//! use simple_socks5::conn::reply::Rep;
+//! use simple_socks5::conn::request::ConnRequest;
+//! use simple_socks5::handler::{ConnectionHandler, HandleFuture, RelayHandler};
+//! use simple_socks5::parse::AddrPort;
+//! use simple_socks5::Socks5;
+//! use tokio::net::TcpStream;
+//!
+//! /// Refuses one host and relays everything else.
+//! struct Blocklist;
+//!
+//! impl ConnectionHandler for Blocklist {
+//!     fn handle(
+//!         &self,
+//!         mut stream: TcpStream,
+//!         client: SocketAddr,
+//!         request: ConnRequest,
+//!         auth: AuthContext,
+//!     ) -> HandleFuture<'_> {
+//!         Box::pin(async move {
+//!             if matches!(&request.dst, AddrPort::Domain(host, _) if host == "blocked.example") {
+//!                 let bnd = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+//!                 let rep = Rep::ConnectionNotAllowed;
+//!                 return Socks5::send_conn_reply(&mut stream, rep, bnd).await;
+//!             }
+//!             RelayHandler.handle(stream, client, request, auth).await
+//!         })
+//!     }
+//! }
+//!
+//! # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+//! let mut server = Socks5::bind("127.0.0.1:1080").await?;
+//! server.allow_no_auth();
+//! server.serve(Blocklist).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use tokio::net::TcpStream;
+
+use crate::Socks5;
+use crate::auth::AuthContext;
+use crate::conn::reply::Rep;
+use crate::conn::request::{CMD, ConnRequest};
+use crate::error::SocksError;
+use crate::parse::AddrPort;
+
+/// Future returned by [`ConnectionHandler::handle`].
+pub type HandleFuture<'a> = Pin<Box<dyn Future<Output = Result<(), SocksError>> + Send + 'a>>;
+
+/// Handles a client once authentication and request parsing are done.
+pub trait ConnectionHandler: Send + Sync {
+    /// Serves one parsed request.
+    ///
+    /// `auth` tells how the client authenticated, and as whom.
+    ///
+    /// The handler must send the connection reply itself, e.g. with
+    /// [`Socks5::send_conn_reply`], or [`Socks5::send_error_reply`] on failure.
+    fn handle(
+        &self,
+        stream: TcpStream,
+        client: SocketAddr,
+        request: ConnRequest,
+        auth: AuthContext,
+    ) -> HandleFuture<'_>;
+}
+
+/// The default [`ConnectionHandler`], which dials the destination of a
+/// `CONNECT` request directly and relays bytes until both sides close.
+///
+/// Other commands are answered with [`Rep::CommandNotSupported`], and
+/// destinations that cannot be reached with the reply matching the error
+/// (see [`Socks5::send_error_reply`]).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RelayHandler;
+
+impl ConnectionHandler for RelayHandler {
+    fn handle(
+        &self,
+        mut stream: TcpStream,
+        _client: SocketAddr,
+        request: ConnRequest,
+        _auth: AuthContext,
+    ) -> HandleFuture<'_> {
+        Box::pin(async move {
+            let unspecified = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+
+            if request.cmd != CMD::Connect {
+                return Socks5::send_conn_reply(&mut stream, Rep::CommandNotSupported, unspecified)
+                    .await;
+            }
+
+            let dialed = match request.dst.normalized() {
+                AddrPort::V4(ip, port) => TcpStream::connect((ip, port)).await,
+                AddrPort::V6(ip, port) => TcpStream::connect((ip, port)).await,
+                AddrPort::Domain(host, port) => TcpStream::connect((host.as_str(), port)).await,
+            };
+            let mut target = match dialed {
+                Ok(target) => target,
+                Err(source) => {
+                    let e = SocksError::ConnectFailed {
+                        dst: request.dst,
+                        source,
+                    };
+                    Socks5::send_error_reply(&mut stream, &e).await?;
+                    return Err(e);
+                }
+            };
+
+            let bnd = AddrPort::from(target.local_addr()?);
+            Socks5::send_conn_reply(&mut stream, Rep::Succeeded, bnd).await?;
+
+            Socks5::relay(&mut stream, &mut target).await?;
+            Ok(())
+        })
+    }
+}
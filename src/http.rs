@@ -0,0 +1,234 @@
+//! HTTP `CONNECT` proxying on the SOCKS5 port.
+//!
+//! With [`Socks5::allow_http_connect`](crate::Socks5::allow_http_connect),
+//! a client whose first byte starts an HTTP method rather than the SOCKS5
+//! version is served as an HTTP proxy
+//! ([RFC 9110 §9.3.6](https://www.rfc-editor.org/rfc/rfc9110#section-9.3.6)):
+//! the `CONNECT host:port HTTP/1.1` request is read, the target dialed with
+//! the same policy and connector as SOCKS5 requests, and the tunnel relayed
+//! after a `200 Connection Established` response.
+//!
+//! Nothing here is used unless the server enables it.
+//!
+//! ```
+//! use simple_socks5::http;
+//!
+//! assert!(http::is_http(b"CONNECT example.com:443 HTTP/1.1\r\n"));
+//! assert!(!http::is_http(&[0x05, 0x01, 0x00]));
+//! ```
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::conn::reply::Rep;
+use crate::error::SocksError;
+use crate::parse::AddrPort;
+
+/// Maximum size of an HTTP request head accepted from a client.
+const MAX_HEAD: usize = 8 * 1024;
+
+/// Returns `true` if `peeked`, the first bytes a client sent, start an HTTP
+/// request rather than a SOCKS5 version message.
+///
+/// HTTP methods are upper-case tokens, while a SOCKS5 client starts with
+/// `0x05`, so the first byte is enough to tell them apart.
+pub fn is_http(peeked: &[u8]) -> bool {
+    peeked.first().is_some_and(u8::is_ascii_uppercase)
+}
+
+/// The head of an HTTP request sent to the proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyRequest {
+    /// The request method; only `CONNECT` is served.
+    pub method: String,
+    /// The request target, `host:port` for `CONNECT`.
+    pub target: String,
+    /// Username and password from a `Proxy-Authorization: Basic` header.
+    pub credentials: Option<(String, String)>,
+}
+
+impl ProxyRequest {
+    /// Parses the target of a `CONNECT` request, e.g. `example.com:443` or
+    /// `[2001:db8::1]:443`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SocksError::InvalidHttpRequest`] if the target does not
+    /// parse as an [`AddrPort`], and [`SocksError::InvalidDomain`] if the
+    /// host name is longer than 255 bytes.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::http::ProxyRequest;
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// let req = ProxyRequest {
+    ///     method: "CONNECT".into(),
+    ///     target: "[::1]:443".into(),
+    ///     credentials: None,
+    /// };
+    /// assert_eq!(req.dst().unwrap(), AddrPort::V6("::1".parse().unwrap(), 443));
+    /// ```
+    pub fn dst(&self) -> Result<AddrPort, SocksError> {
+        self.target.parse().map_err(|e| match e {
+            SocksError::InvalidAddress(_) => SocksError::InvalidHttpRequest,
+            e => e,
+        })
+    }
+}
+
+/// Reads an HTTP request head up to and including the blank line.
+///
+/// Reads byte by byte so that no tunneled data past the head is consumed.
+///
+/// # Errors
+///
+/// - [`SocksError::InvalidHttpRequest`] if the head is malformed or larger
+///   than 8 KiB.
+/// - [`SocksError::Io`] on transport failures.
+pub async fn read_request(stream: &mut TcpStream) -> Result<ProxyRequest, SocksError> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD {
+            return Err(SocksError::InvalidHttpRequest);
+        }
+        head.push(stream.read_u8().await?);
+    }
+
+    let head = std::str::from_utf8(&head).map_err(|_| SocksError::InvalidHttpRequest)?;
+    let mut lines = head.split("\r\n");
+
+    let mut parts = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None)
+            if !method.is_empty() && version.starts_with("HTTP/1.") =>
+        {
+            (method, target)
+        }
+        _ => return Err(SocksError::InvalidHttpRequest),
+    };
+
+    let credentials = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("proxy-authorization"))
+        .and_then(|(_, value)| basic_credentials(value.trim()));
+
+    Ok(ProxyRequest {
+        method: method.into(),
+        target: target.into(),
+        credentials,
+    })
+}
+
+/// Decodes the username and password of a `Basic` authorization value.
+fn basic_credentials(value: &str) -> Option<(String, String)> {
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(base64_decode(token.trim())?).ok()?;
+    let (uname, passwd) = decoded.split_once(':')?;
+    Some((uname.into(), passwd.into()))
+}
+
+/// Sends a response without a body, closing the head.
+///
+/// A `407` response carries the `Proxy-Authenticate` challenge for `Basic`
+/// authentication.
+pub async fn send_response(stream: &mut TcpStream, status: u16) -> Result<(), SocksError> {
+    let mut response = format!("HTTP/1.1 {status} {}\r\n", reason(status));
+    if status == 407 {
+        response.push_str("Proxy-Authenticate: Basic realm=\"proxy\"\r\n");
+    }
+    response.push_str("\r\n");
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Maps a SOCKS5 reply code to the HTTP status answering a `CONNECT`.
+///
+/// The inverse of the mapping [`client::connect_http`](crate::client::connect_http)
+/// applies to the responses of an upstream HTTP proxy.
+pub fn status_for_rep(rep: Rep) -> u16 {
+    match rep {
+        Rep::Succeeded => 200,
+        Rep::ConnectionNotAllowed => 403,
+        Rep::HostUnreachable | Rep::ConnectionRefused => 502,
+        Rep::NetworkUnreachable => 503,
+        Rep::TTLExpired => 504,
+        Rep::CommandNotSupported | Rep::AddressTypeNotSupported => 501,
+        Rep::GeneralFailure => 500,
+    }
+}
+
+/// The reason phrase sent with `status`.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "Connection Established",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        405 => "Method Not Allowed",
+        407 => "Proxy Authentication Required",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Internal Server Error",
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 encoding with padding, as used by HTTP `Basic` auth.
+pub(crate) fn base64_encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        out.push(BASE64[(n >> 18) as usize & 0x3F] as char);
+        out.push(BASE64[(n >> 12) as usize & 0x3F] as char);
+        out.push(if chunk.len() > 1 {
+            BASE64[(n >> 6) as usize & 0x3F] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            BASE64[n as usize & 0x3F] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+/// Decodes standard base64 with padding, returning `None` if it is invalid.
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    let chunks = input.as_bytes().chunks(4);
+    let last = chunks.len().saturating_sub(1);
+    for (i, chunk) in chunks.enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && i != last) {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let value = BASE64.iter().position(|&a| a == c)?;
+            n = n << 6 | value as u32;
+        }
+        n <<= 6 * padding as u32;
+
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
@@ -10,37 +10,282 @@
 //! actual UDP packet forwarding and relay logic is not handled yet.
 //! Users should not rely on UDP support for production usage.
 
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
 use std::fmt;
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::future::{self, Future};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time;
 
+pub mod audit;
 pub mod auth;
+pub mod client;
 pub mod conn;
+pub mod connection;
+pub mod connector;
 pub mod error;
+pub mod event;
+pub mod handler;
+pub mod http;
+pub mod metrics;
 pub mod msg;
 pub mod parse;
+pub mod relay;
+pub mod resolver;
+pub mod socks4;
+#[cfg(feature = "test-util")]
+pub mod testkit;
+mod trace;
+pub mod udp;
 
+/// The `rustls` version used by [`Socks5::bind_tls`].
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
+
+/// The acceptor taken by [`Socks5::set_tls_on_socks_port`].
+#[cfg(feature = "tls")]
+pub use tokio_rustls::TlsAcceptor;
+
+use audit::{AuditEvent, AuditSink, NoopAudit};
+use auth::custom::MethodHandler;
+use auth::limit::AuthLimiter;
 use auth::reply::*;
 use auth::request::*;
+#[cfg(feature = "argon2")]
+use auth::store::CredentialStore;
+use auth::{AuthContext, UserContext};
 use conn::reply::*;
 use conn::request::*;
+use connector::{
+    BoundConnector, ConnectErrorPolicy, ConnectFuture, Connector, Destination, DirectConnector,
+};
+use event::{DEFAULT_EVENT_BUFFER, Event};
+use handler::ConnectionHandler;
+use metrics::{Metrics, MetricsSnapshot};
 use msg::message::*;
 use msg::method::*;
-use parse::AddrPort;
+use parse::{AddrPort, is_ip_literal, is_private_ip};
+use relay::{BufferPool, ConnectionInfo, MAX_BUFFER_SIZE, Registry, RelayOptions, Traffic};
+use resolver::{AddressPreference, Resolver, SystemResolver};
+use udp::UdpAssociation;
 
 use crate::error::SocksError;
 
+/// The SOCKS protocol version byte, `VER` in RFC 1928 messages.
+pub const SOCKS_VERSION: u8 = 0x05;
+
+/// The version byte of the username/password sub-negotiation (RFC 1929).
+pub const AUTH_VERSION: u8 = 0x01;
+
 /// Represents an IPv4 address.
 pub type V4 = Ipv4Addr;
 /// Represents an IPv6 address.
 pub type V6 = Ipv6Addr;
 
-type UserPassValidator = Box<dyn Fn(&str, &str) -> bool + Send + Sync>;
+/// Returns `None` to reject the credentials, otherwise the context, if any,
+/// to attach to the user.
+type UserPassValidator = Arc<dyn Fn(&str, &str) -> Option<Option<UserContext>> + Send + Sync>;
+type DestinationRewriter = Box<dyn Fn(&AddrPort) -> Option<AddrPort> + Send + Sync>;
+type UserDestinationFilter = Box<dyn Fn(&AuthContext, &AddrPort) -> bool + Send + Sync>;
+type UserRateLimit = Box<dyn Fn(&AuthContext) -> Option<u64> + Send + Sync>;
+type RequestInspector = Box<dyn Fn(&AuthContext, &ConnRequest) -> Result<(), Rep> + Send + Sync>;
+
+/// An upstream proxy that `CONNECT` traffic is forwarded through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpstreamKind {
+    /// A SOCKS5 proxy, with optional username/password credentials.
+    Socks5 {
+        /// Address of the proxy, e.g., `"10.0.0.1:1080"`.
+        addr: String,
+        /// Optional username and password.
+        auth: Option<(String, String)>,
+    },
+    /// An HTTP proxy supporting the `CONNECT` method, with optional
+    /// `Basic` credentials sent in `Proxy-Authorization`.
+    HttpConnect {
+        /// Address of the proxy, e.g., `"10.0.0.1:3128"`.
+        addr: String,
+        /// Optional username and password.
+        auth: Option<(String, String)>,
+    },
+}
+
+/// Default time in-flight connections get to finish after a shutdown signal.
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// How long the accept loop pauses after running out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// What the accept loop does with a new connection once the limit set by
+/// [`Socks5::set_max_connections`] is reached.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Stop accepting until a running connection finishes. New clients queue
+    /// in the listen backlog.
+    #[default]
+    Wait,
+    /// Accept and immediately close connections over the limit.
+    Reject,
+}
+
+/// Outcome of a graceful shutdown started by [`Socks5::run_with_shutdown`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// Connections that finished on their own within the grace period.
+    pub drained: usize,
+    /// Connections still running when the grace period ran out, which were aborted.
+    pub aborted: usize,
+}
+
+/// Options for the listening socket created by [`Socks5::bind_with`].
+///
+/// # Platform differences
+///
+/// - `SO_REUSEADDR` on Unix only lets a restarted server bind while
+///   connections of the previous one linger in `TIME_WAIT`. On Windows it
+///   lets another socket take over a port that is in active use, so it is
+///   off by default there; Windows already allows rebinding over
+///   `TIME_WAIT`.
+/// - `SO_REUSEPORT` lets several processes, or several listeners in one
+///   process, bind the same address. Linux and FreeBSD (12+) balance new
+///   connections across them; macOS and the other BSDs hand every
+///   connection to the most recently bound listener. It is not available on
+///   Windows, Solaris or illumos, where binding fails with
+///   [`io::ErrorKind::Unsupported`].
+/// - The backlog is a hint: Linux caps it at `net.core.somaxconn`, and
+///   other systems apply their own limits.
+///
+/// # Example
+/// ```no_run
+/// use simple_socks5::{BindOptions, Socks5};
+///
+/// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+/// // One of several worker processes sharing port 1080.
+/// let options = BindOptions {
+///     reuse_port: true,
+///     backlog: 4096,
+///     ..BindOptions::default()
+/// };
+/// let mut server = Socks5::bind_with("0.0.0.0:1080", options).await?;
+/// server.allow_no_auth();
+/// server.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindOptions {
+    /// Set `SO_REUSEADDR`. Defaults to `true` on Unix and `false` elsewhere,
+    /// like [`Socks5::bind`].
+    pub reuse_address: bool,
+    /// Set `SO_REUSEPORT`. Defaults to `false`.
+    pub reuse_port: bool,
+    /// The most connections the kernel queues until they are accepted.
+    /// Defaults to 1024, like [`Socks5::bind`].
+    pub backlog: u32,
+}
+
+impl Default for BindOptions {
+    fn default() -> Self {
+        Self {
+            reuse_address: cfg!(unix),
+            reuse_port: false,
+            backlog: 1024,
+        }
+    }
+}
+
+impl BindOptions {
+    /// Creates a listener on `addr` with these options.
+    fn listen(&self, addr: SocketAddr) -> io::Result<std::net::TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(self.reuse_address)?;
+        if self.reuse_port {
+            #[cfg(all(
+                unix,
+                not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+            ))]
+            socket.set_reuse_port(true)?;
+            #[cfg(not(all(
+                unix,
+                not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+            )))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "SO_REUSEPORT is not supported on this platform",
+            ));
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(i32::try_from(self.backlog).unwrap_or(i32::MAX))?;
+        Ok(socket.into())
+    }
+}
+
+/// Non-standard header bytes for the replies the server sends, set with
+/// `Socks5::set_reply_overrides`.
+#[derive(Debug, Default, Clone, Copy)]
+struct ReplyOverrides {
+    ver: Option<u8>,
+    rsv: Option<u8>,
+}
+
+/// Options for client and outbound sockets, set with
+/// [`Socks5::set_socket_buffer_sizes`], [`Socks5::set_nodelay`] and
+/// [`Socks5::set_keepalive`].
+#[derive(Debug, Clone, Copy)]
+struct SocketOptions {
+    send: Option<usize>,
+    recv: Option<usize>,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            send: None,
+            recv: None,
+            nodelay: true,
+            keepalive: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Sets the kernel buffer sizes, which a listener passes on to the
+    /// sockets it accepts.
+    fn apply_buffers(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(size) = self.send {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(())
+    }
+
+    /// Sets every option on a connected socket.
+    fn apply(&self, socket: &Socket) -> io::Result<()> {
+        self.apply_buffers(socket)?;
+        socket.set_tcp_nodelay(self.nodelay)?;
+        if let Some(time) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        Ok(())
+    }
+}
 
 /// Represents the address type in SOCKS5 messages.
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ATYP {
     /// IPv4 address
     V4 = 0x01,
@@ -60,6 +305,92 @@ impl fmt::Display for ATYP {
     }
 }
 
+impl ATYP {
+    /// Parses an `ATYP` byte.
+    ///
+    /// # Errors
+    ///
+    /// [`SocksError::InvalidAddressType`] for anything but `0x01`, `0x03`
+    /// and `0x04`.
+    pub(crate) fn from_u8(byte: u8) -> Result<Self, SocksError> {
+        match byte {
+            0x01 => Ok(ATYP::V4),
+            0x03 => Ok(ATYP::DomainName),
+            0x04 => Ok(ATYP::V6),
+            other => Err(SocksError::InvalidAddressType(other)),
+        }
+    }
+}
+
+/// How the accept loop reacts to a failed `accept`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AcceptFailure {
+    /// Only the pending connection is affected; accept the next one.
+    Transient,
+    /// The process or system is out of file descriptors or memory; back
+    /// off before accepting again, since retrying at once would spin.
+    Exhausted,
+    /// The listener itself is broken.
+    Fatal,
+}
+
+/// Classifies a failed `accept`.
+///
+/// Besides aborted and reset connections, Linux reports pending network
+/// errors of the new connection from `accept`, so those only affect that
+/// connection too.
+fn classify_accept_error(err: &io::Error) -> AcceptFailure {
+    // `EMFILE` and `ENFILE` have the same values on every Unix, and
+    // `WSAEMFILE` is Windows' equivalent.
+    #[cfg(unix)]
+    const FD_EXHAUSTED: [i32; 2] = [24, 23];
+    #[cfg(windows)]
+    const FD_EXHAUSTED: [i32; 1] = [10024];
+    #[cfg(not(any(unix, windows)))]
+    const FD_EXHAUSTED: [i32; 0] = [];
+
+    if err
+        .raw_os_error()
+        .is_some_and(|code| FD_EXHAUSTED.contains(&code))
+    {
+        return AcceptFailure::Exhausted;
+    }
+
+    match err.kind() {
+        io::ErrorKind::OutOfMemory => AcceptFailure::Exhausted,
+        io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::WouldBlock
+        | io::ErrorKind::TimedOut
+        | io::ErrorKind::NetworkDown
+        | io::ErrorKind::NetworkUnreachable
+        | io::ErrorKind::HostUnreachable => AcceptFailure::Transient,
+        _ => AcceptFailure::Fatal,
+    }
+}
+
+/// Returns `true` if `peeked` starts a TLS handshake record: content type
+/// `0x16` and major version `0x03`.
+///
+/// A record whose second byte has not arrived yet is assumed to be TLS; the
+/// acceptor rejects it if it is not.
+#[cfg(feature = "tls")]
+fn is_tls_record(peeked: &[u8]) -> bool {
+    matches!(peeked, [0x16] | [0x16, 0x03, ..])
+}
+
+/// Classifies a failure to bind the listen address `addr`.
+fn bind_error(addr: &str, source: io::Error) -> SocksError {
+    let addr = addr.to_string();
+    match source.kind() {
+        io::ErrorKind::AddrInUse => SocksError::AddrInUse { addr, source },
+        io::ErrorKind::PermissionDenied => SocksError::BindPermissionDenied { addr, source },
+        _ => SocksError::Io(source),
+    }
+}
+
 /// The main SOCKS5 server struct.
 ///
 /// Handles incoming TCP connections, negotiates authentication, and manages
@@ -71,26 +402,247 @@ impl fmt::Display for ATYP {
 pub struct Socks5 {
     listener: TcpListener,
     allow_no_auth: bool,
+    allow_http_connect: bool,
+    allow_socks4: bool,
+    strict: bool,
+    commands: Vec<CMD>,
     userpass_validator: Option<UserPassValidator>,
+    /// Whether the validator is slow enough to run on the blocking pool.
+    userpass_blocking: bool,
+    method_preference: Vec<Method>,
+    custom_methods: Vec<(Method, Box<dyn MethodHandler>)>,
+    max_credential_len: Option<usize>,
+    auth_limiter: Option<AuthLimiter>,
+    upstream: Option<UpstreamKind>,
+    deny_private: bool,
+    dotless_domain_exceptions: Option<Vec<String>>,
+    reject_ip_literal_domains: bool,
+    allowed_ports: Option<Vec<RangeInclusive<u16>>>,
+    blocked_ports: Vec<RangeInclusive<u16>>,
+    destination_rewriter: Option<DestinationRewriter>,
+    user_destination_filter: Option<UserDestinationFilter>,
+    request_inspector: Option<RequestInspector>,
+    user_rate_limit: Option<UserRateLimit>,
+    advertised_bind_addr: Option<AddrPort>,
+    advertised_udp_host: Option<String>,
+    connector: Box<dyn Connector>,
+    audit: Box<dyn AuditSink>,
+    connect_error_policy: ConnectErrorPolicy,
+    connect_timeout: Option<Duration>,
+    happy_eyeballs: Option<Duration>,
+    resolver: Box<dyn Resolver>,
+    address_preference: AddressPreference,
+    registry: Registry,
+    metrics: Metrics,
+    relay: RelayOptions,
+    buffers: BufferPool,
+    connection_limit: Option<Arc<Semaphore>>,
+    overload_policy: OverloadPolicy,
+    event_buffer: usize,
+    events: Option<mpsc::Sender<Event>>,
+    shutdown_grace_period: Duration,
+    reply_overrides: ReplyOverrides,
+    socket_options: SocketOptions,
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>,
+    #[cfg(feature = "tls")]
+    tls_on_socks_port: Option<tokio_rustls::TlsAcceptor>,
 }
 
 impl Socks5 {
     /// Bind a new SOCKS5 server to an address.
     ///
+    /// The listener gets [`BindOptions::default`]; use
+    /// [`Socks5::bind_with`] to change them.
+    ///
     /// # Arguments
     ///
     /// * `addr` - The address to bind to, e.g., `"127.0.0.1:1080"`.
     ///
     /// # Errors
     ///
-    /// Returns a `SocksError::Io` if binding fails.
+    /// - `SocksError::AddrInUse` if another socket already holds the address.
+    /// - `SocksError::BindPermissionDenied` if the process may not bind it,
+    ///   e.g. a privileged port.
+    /// - `SocksError::Io` if binding fails for any other reason.
     pub async fn bind(addr: &str) -> Result<Self, SocksError> {
-        let listener = TcpListener::bind(addr).await?;
-        Ok(Self {
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|source| bind_error(addr, source))?;
+        Ok(Self::from_listener(listener))
+    }
+
+    /// Bind a new SOCKS5 server to an address, with options for the
+    /// listening socket.
+    ///
+    /// Use it to share the port between processes with `SO_REUSEPORT` or to
+    /// raise the listen backlog; see [`BindOptions`] for how the options
+    /// behave on each platform. `bind_with(addr, BindOptions::default())`
+    /// behaves like [`Socks5::bind`]. If `addr` resolves to several
+    /// addresses, they are tried in order until one binds.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Socks5::bind`], reported for the last address tried.
+    pub async fn bind_with(addr: &str, options: BindOptions) -> Result<Self, SocksError> {
+        let addrs = tokio::net::lookup_host(addr)
+            .await
+            .map_err(|source| bind_error(addr, source))?;
+        let mut last_err = None;
+        for sock_addr in addrs {
+            match options.listen(sock_addr) {
+                Ok(listener) => return Self::from_std(listener),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        let source = last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            )
+        });
+        Err(bind_error(addr, source))
+    }
+
+    /// Bind a new SOCKS5-over-TLS server to an address.
+    ///
+    /// Every accepted connection completes a TLS handshake with `config`
+    /// before the SOCKS5 handshake starts, so credentials and requests are
+    /// never sent in cleartext. A failed TLS handshake ends the connection
+    /// with `SocksError::Tls`. Only SOCKS5 is served inside TLS: HTTP
+    /// `CONNECT` and SOCKS4 are not detected, and [`Socks5::serve`], whose
+    /// handlers take a plain `TcpStream`, does not terminate TLS.
+    ///
+    /// Available with the `tls` feature.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Socks5::bind`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use simple_socks5::Socks5;
+    /// use simple_socks5::rustls::ServerConfig;
+    /// use simple_socks5::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    ///
+    /// # async fn run(
+    /// #     certs: Vec<CertificateDer<'static>>,
+    /// #     key: PrivateKeyDer<'static>,
+    /// # ) -> Result<(), Box<dyn std::error::Error>> {
+    /// let config = ServerConfig::builder()
+    ///     .with_no_client_auth()
+    ///     .with_single_cert(certs, key)?;
+    ///
+    /// let mut server = Socks5::bind_tls("0.0.0.0:1080", Arc::new(config)).await?;
+    /// server.allow_userpass_static("admin", "admin");
+    /// server.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tls")]
+    pub async fn bind_tls(
+        addr: &str,
+        config: Arc<rustls::ServerConfig>,
+    ) -> Result<Self, SocksError> {
+        let mut server = Self::bind(addr).await?;
+        server.tls = Some(tokio_rustls::TlsAcceptor::from(config));
+        Ok(server)
+    }
+
+    /// Bind a new SOCKS5 server to `port` on every interface, accepting
+    /// both IPv4 and IPv6 clients on a single listener.
+    ///
+    /// The listener is an IPv6 socket on `[::]` with `IPV6_V6ONLY` turned
+    /// off, so IPv4 clients arrive as IPv4-mapped IPv6 addresses
+    /// (`::ffff:a.b.c.d`) in [`Socks5::accept`]. Must be called from within
+    /// a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Socks5::bind`]. Platforms without dual-stack sockets, or
+    /// hosts with IPv6 disabled, fail with `SocksError::Io`.
+    pub fn bind_dual_stack(port: u16) -> Result<Self, SocksError> {
+        let addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+        let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_only_v6(false)?;
+        // Matches what `TcpListener::bind` does, so restarts do not wait out
+        // TIME_WAIT.
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket
+            .bind(&addr.into())
+            .map_err(|source| bind_error(&addr.to_string(), source))?;
+        socket.listen(1024)?;
+        Self::from_std(socket.into())
+    }
+
+    /// Create a SOCKS5 server from an already bound Tokio listener.
+    ///
+    /// Useful when the listener needs custom socket options, or is handed
+    /// over by a socket-activation framework.
+    pub fn from_listener(listener: TcpListener) -> Self {
+        Self {
             listener,
             allow_no_auth: false,
+            allow_http_connect: false,
+            allow_socks4: false,
+            strict: false,
+            commands: vec![CMD::Connect],
             userpass_validator: None,
-        })
+            userpass_blocking: false,
+            method_preference: vec![Method::no_auth(), Method::userpass()],
+            custom_methods: Vec::new(),
+            max_credential_len: None,
+            auth_limiter: None,
+            upstream: None,
+            deny_private: false,
+            dotless_domain_exceptions: None,
+            reject_ip_literal_domains: false,
+            allowed_ports: None,
+            blocked_ports: Vec::new(),
+            destination_rewriter: None,
+            user_destination_filter: None,
+            request_inspector: None,
+            user_rate_limit: None,
+            advertised_bind_addr: None,
+            advertised_udp_host: None,
+            connector: Box::new(DirectConnector),
+            audit: Box::new(NoopAudit),
+            connect_error_policy: ConnectErrorPolicy::default(),
+            connect_timeout: None,
+            happy_eyeballs: None,
+            resolver: Box::new(SystemResolver),
+            address_preference: AddressPreference::default(),
+            registry: Registry::default(),
+            metrics: Metrics::default(),
+            relay: RelayOptions::default(),
+            buffers: BufferPool::default(),
+            connection_limit: None,
+            overload_policy: OverloadPolicy::default(),
+            event_buffer: DEFAULT_EVENT_BUFFER,
+            events: None,
+            shutdown_grace_period: DEFAULT_SHUTDOWN_GRACE_PERIOD,
+            reply_overrides: ReplyOverrides::default(),
+            socket_options: SocketOptions::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "tls")]
+            tls_on_socks_port: None,
+        }
+    }
+
+    /// Create a SOCKS5 server from an already bound standard library listener.
+    ///
+    /// The listener is switched to non-blocking mode and registered with the
+    /// Tokio runtime, so this must be called from within a runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SocksError::Io` if the conversion fails.
+    pub fn from_std(listener: std::net::TcpListener) -> Result<Self, SocksError> {
+        listener.set_nonblocking(true)?;
+        Ok(Self::from_listener(TcpListener::from_std(listener)?))
     }
 
     /// Enable the `NO AUTH` authentication method.
@@ -98,8 +650,67 @@ impl Socks5 {
         self.allow_no_auth = true;
     }
 
+    /// Serve HTTP `CONNECT` requests on the same listener as SOCKS5.
+    ///
+    /// A client whose first byte starts an HTTP method is handled as an HTTP
+    /// proxy client (see the [`http`] module), with the same destination
+    /// policy, connector and relay as a SOCKS5 `CONNECT`. Credentials from a
+    /// `Proxy-Authorization: Basic` header are checked with the
+    /// username/password validator; requests without them are accepted only
+    /// if `NO AUTH` is enabled and listed in the method preference (see
+    /// [`Socks5::set_method_preference`]), and answered with `407` otherwise.
+    pub fn allow_http_connect(&mut self) {
+        self.allow_http_connect = true;
+    }
+
+    /// Serve SOCKS4 and SOCKS4a `CONNECT` requests on the same listener as
+    /// SOCKS5.
+    ///
+    /// A client whose first byte is `0x04` is handled as a SOCKS4 client
+    /// (see the [`socks4`] module), with the same destination policy,
+    /// connector and relay as a SOCKS5 `CONNECT`. SOCKS4 has no
+    /// authentication, so its requests are accepted only if `NO AUTH` is
+    /// enabled and listed in the method preference (see
+    /// [`Socks5::set_method_preference`]); the `USERID` field is not checked.
+    pub fn allow_socks4(&mut self) {
+        self.allow_socks4 = true;
+    }
+
+    /// Serve SOCKS5 over TLS on the same listener as cleartext SOCKS5.
+    ///
+    /// A client whose first bytes are a TLS handshake record (`0x16 0x03`)
+    /// completes a TLS handshake with `acceptor`, then speaks SOCKS5 inside
+    /// the tunnel, as with [`Socks5::bind_tls`]; other clients are served as
+    /// usual. Without this, such a client is reported with
+    /// [`Event::ProtocolMismatch`] and refused like any other unknown
+    /// protocol.
+    ///
+    /// Available with the `tls` feature.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use simple_socks5::{Socks5, TlsAcceptor};
+    /// use simple_socks5::rustls::ServerConfig;
+    ///
+    /// # async fn run(config: ServerConfig) -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("0.0.0.0:1080").await?;
+    /// server.allow_no_auth();
+    /// server.set_tls_on_socks_port(TlsAcceptor::from(Arc::new(config)));
+    /// server.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn set_tls_on_socks_port(&mut self, acceptor: TlsAcceptor) {
+        self.tls_on_socks_port = Some(acceptor);
+    }
+
     /// Enable username/password authentication with a custom validator closure.
     ///
+    /// Compare passwords with [`auth::verify_constant_time`] rather than
+    /// `==`, so response times do not reveal how much of a guess was right.
+    ///
     /// # Arguments
     ///
     /// * `validator` - A closure that receives username and password and returns `true` if valid.
@@ -107,130 +718,2155 @@ impl Socks5 {
     where
         F: Fn(&str, &str) -> bool + Send + Sync + 'static,
     {
-        self.userpass_validator = Some(Box::new(validator));
+        self.userpass_validator = Some(Arc::new(move |uname, passwd| {
+            validator(uname, passwd).then_some(None)
+        }));
+        self.userpass_blocking = false;
     }
 
-    /// Accept a client TCP connection.
+    /// Enable username/password authentication for a single user.
     ///
-    /// # Returns
+    /// Both the username and the password are compared with
+    /// [`auth::verify_constant_time`]. Replaces any validator set with
+    /// [`Socks5::allow_userpass`].
     ///
-    /// A tuple of `(TcpStream, SocketAddr)` representing the connected client.
-    pub async fn accept(&self) -> Result<(TcpStream, SocketAddr), SocksError> {
-        let (stream, addr) = self.listener.accept().await?;
-        Ok((stream, addr))
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.allow_userpass_static("admin", "correct horse battery staple");
+    /// server.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn allow_userpass_static(&mut self, user: impl Into<String>, pass: impl Into<String>) {
+        let (user, pass) = (user.into(), pass.into());
+        self.allow_userpass(move |uname, passwd| {
+            // `&` rather than `&&`, so a wrong username takes as long as a
+            // wrong password.
+            auth::verify_constant_time(&user, uname) & auth::verify_constant_time(&pass, passwd)
+        });
     }
 
-    /// Returns the local address of the server.
-    pub fn local_addr(&self) -> Result<SocketAddr, SocksError> {
-        Ok(self.listener.local_addr()?)
+    /// Enable username/password authentication with a validator that
+    /// returns per-user data.
+    ///
+    /// The validator returns `None` to reject the credentials, or the
+    /// user's data, e.g. their plan, to accept them. The data is carried in
+    /// [`AuthContext::context`], where a [`ConnectionHandler`] and the hooks
+    /// set with [`Socks5::set_user_destination_filter`] and
+    /// [`Socks5::set_user_rate_limit`] can read it back with
+    /// [`AuthContext::user_context`]. Replaces a validator set with
+    /// [`Socks5::allow_userpass`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// struct Plan {
+    ///     bytes_per_sec: u64,
+    /// }
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.allow_userpass_ctx(|uname, passwd| match (uname, passwd) {
+    ///     ("alice", "secret") => Some(Plan { bytes_per_sec: 10 << 20 }),
+    ///     ("bob", "hunter2") => Some(Plan { bytes_per_sec: 1 << 20 }),
+    ///     _ => None,
+    /// });
+    /// server.set_user_rate_limit(|auth| Some(auth.user_context::<Plan>()?.bytes_per_sec));
+    /// server.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn allow_userpass_ctx<C, F>(&mut self, validator: F)
+    where
+        C: Send + Sync + 'static,
+        F: Fn(&str, &str) -> Option<C> + Send + Sync + 'static,
+    {
+        self.userpass_validator = Some(Arc::new(move |uname, passwd| {
+            let context = validator(uname, passwd)?;
+            Some(Some(Arc::new(context) as UserContext))
+        }));
+        self.userpass_blocking = false;
     }
 
-    // --- Protocol helpers ---
+    /// Enable username/password authentication against the credential file
+    /// at `path`.
+    ///
+    /// The file lists one `name:hash` entry per line, with Argon2 hashes
+    /// (see [`auth::store`]). Passwords are checked against the hashes in
+    /// constant time. Each check recomputes an Argon2 hash, tens of
+    /// milliseconds at the recommended cost, so it runs on tokio's blocking
+    /// thread pool rather than stalling other connections. Returns the
+    /// loaded [`CredentialStore`], whose
+    /// [`reload`](CredentialStore::reload) picks up edits to the file
+    /// without restarting the server. Replaces any validator set with
+    /// [`Socks5::allow_userpass`].
+    ///
+    /// Available with the `argon2` feature.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`CredentialStore::load`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// let store = server.allow_userpass_from_file("users.txt")?;
+    /// println!("loaded {} users", store.len());
+    /// server.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "argon2")]
+    pub fn allow_userpass_from_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Arc<CredentialStore>, SocksError> {
+        let store = Arc::new(CredentialStore::load(path)?);
+        let validator = Arc::clone(&store);
+        self.allow_userpass(move |uname, passwd| validator.verify(uname, passwd));
+        self.userpass_blocking = true;
+        Ok(store)
+    }
 
-    /// Read a SOCKS5 version/method message from the client.
-    pub async fn read_version_message(
-        stream: &mut TcpStream,
-    ) -> Result<VersionMessage, SocksError> {
-        let mut buf = [0u8; 512];
-        let n = stream.read(&mut buf).await?;
-        VersionMessage::try_from(&buf[..n])
+    /// Set the order in which authentication methods are preferred.
+    ///
+    /// During negotiation the list is walked in order and the first method
+    /// that is enabled on the server and offered by the client is selected.
+    /// Methods missing from the list are never selected, so
+    /// `vec![Method::userpass()]` requires username/password
+    /// authentication even from clients that also offer `NO AUTH`.
+    ///
+    /// Defaults to `NO AUTH`, then `USERNAME/PASSWORD`.
+    pub fn set_method_preference(&mut self, preference: Vec<Method>) {
+        self.method_preference = preference;
     }
 
-    /// Send the server's method selection message.
-    pub async fn send_method_selection(
-        stream: &mut TcpStream,
-        method: Method,
-    ) -> Result<(), SocksError> {
-        let sel = MethodSelection::new(method);
-        stream.write_all(&sel.to_bytes()).await?;
-        Ok(())
+    /// Handle `method` with `handler`.
+    ///
+    /// Lets the server offer an IANA-assigned or private method, or GSSAPI,
+    /// whose sub-negotiation the crate does not implement. `method` is
+    /// appended to the preference order (see
+    /// [`Socks5::set_method_preference`]) unless it is already listed, and
+    /// once it is selected `handler` runs the exchange that follows. A
+    /// method registered twice keeps the last handler; registering `NO AUTH`
+    /// or username/password replaces the built-in handling.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    /// use simple_socks5::auth::custom::{MethodFuture, MethodHandler, MethodStream};
+    /// use simple_socks5::msg::method::Method;
+    ///
+    /// struct Anyone;
+    ///
+    /// impl MethodHandler for Anyone {
+    ///     fn authenticate<'a>(&'a self, _stream: &'a mut dyn MethodStream) -> MethodFuture<'a> {
+    ///         Box::pin(async { Ok(Some("guest".into())) })
+    ///     }
+    /// }
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.register_method(Method::Private(0x80), Anyone);
+    /// server.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_method<H>(&mut self, method: Method, handler: H)
+    where
+        H: MethodHandler + 'static,
+    {
+        self.custom_methods.retain(|(m, _)| *m != method);
+        self.custom_methods.push((method, Box::new(handler)));
+        if !self.method_preference.contains(&method) {
+            self.method_preference.push(method);
+        }
     }
 
-    /// Read a username/password authentication request from the client.
-    pub async fn read_auth_request(stream: &mut TcpStream) -> Result<AuthRequest, SocksError> {
-        let mut buf = [0u8; 512];
-        let n = stream.read(&mut buf).await?;
-        AuthRequest::try_from(&buf[..n])
+    /// Returns the handler registered for `method`, if any.
+    fn custom_method(&self, method: Method) -> Option<&dyn MethodHandler> {
+        self.custom_methods
+            .iter()
+            .find(|(m, _)| *m == method)
+            .map(|(_, handler)| handler.as_ref())
     }
 
-    /// Send an authentication reply to the client.
-    pub async fn send_auth_reply(
-        stream: &mut TcpStream,
-        status: AuthStatus,
-    ) -> Result<(), SocksError> {
-        let reply = AuthReply::new(status);
-        stream.write_all(&reply.to_bytes()).await?;
-        Ok(())
+    /// Returns `true` if the server is configured to handle `method`.
+    fn is_method_enabled(&self, method: Method) -> bool {
+        if self.custom_method(method).is_some() {
+            return true;
+        }
+        match method {
+            Method::Fixed(FixedMethod::NoAuth) => self.allow_no_auth,
+            Method::Fixed(FixedMethod::UsePass) => self.userpass_validator.is_some(),
+            _ => false,
+        }
     }
 
-    /// Read a SOCKS5 connection request from the client.
-    pub async fn read_conn_request(stream: &mut TcpStream) -> Result<ConnRequest, SocksError> {
-        let mut buf = [0u8; 512];
-        let n = stream.read(&mut buf).await?;
-        ConnRequest::try_from(&buf[..n])
+    /// Returns `true` if clients that cannot authenticate, such as SOCKS4
+    /// ones, may be served: `NO AUTH` must be both enabled and part of the
+    /// method preference.
+    fn admits_no_auth(&self) -> bool {
+        self.allow_no_auth && self.method_preference.contains(&Method::no_auth())
     }
 
-    /// Send a connection reply to the client.
-    pub async fn send_conn_reply(
-        stream: &mut TcpStream,
-        rep: Rep,
-        atyp: ATYP,
-        addr: AddrPort,
-    ) -> Result<(), SocksError> {
-        let reply = ConnReply::new(0x05, rep, 0x00, atyp, addr);
-        stream.write_all(&reply.to_bytes()).await?;
-        Ok(())
+    /// Limit the length of usernames and passwords accepted by the server.
+    ///
+    /// Credentials longer than `len` bytes are rejected with an authentication
+    /// failure before the validator is invoked. Without this limit, only the
+    /// protocol maximum of 255 bytes applies.
+    pub fn set_max_credential_len(&mut self, len: usize) {
+        self.max_credential_len = Some(len);
     }
 
-    /// Bind a UDP socket for `UDP ASSOCIATE`.
+    /// Limit failed username/password attempts per client IP.
     ///
-    /// **Actual UDP relay is not implemented yet.**
-    pub async fn bind_udp(addr: &str) -> Result<UdpSocket, SocksError> {
-        let sock = UdpSocket::bind(addr).await?;
-        Ok(sock)
+    /// Once an IP has failed `max_failures` times within `window` of its
+    /// first failure, its further attempts are answered with
+    /// [`AuthStatus::Failure`] without invoking the validator, until the
+    /// window ends.
+    pub fn set_auth_rate_limit(&mut self, max_failures: u32, window: Duration) {
+        self.auth_limiter = Some(AuthLimiter::new(max_failures, window));
     }
 
-    /// Perform authentication according to the configured methods.
+    /// Forward all `CONNECT` traffic through an upstream proxy.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::{Socks5, UpstreamKind};
     ///
-    /// Negotiates between `NO AUTH` and `USERNAME/PASSWORD` methods if enabled.
-    pub async fn authenticate(&self, stream: &mut TcpStream) -> Result<(), SocksError> {
-        let version_msg = Self::read_version_message(stream).await?;
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.set_upstream(UpstreamKind::HttpConnect {
+    ///     addr: "10.0.0.1:3128".into(),
+    ///     auth: Some(("user".into(), "pass".into())),
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_upstream(&mut self, upstream: UpstreamKind) {
+        self.upstream = Some(upstream);
+    }
 
-        let mut selected = Method::Fixed(FixedMethod::NoAcceptable);
+    /// Use a custom [`Connector`] to open outbound `CONNECT` connections.
+    ///
+    /// The server resolves domain names itself and calls the connector once
+    /// per candidate address, passing the original hostname along. It is not
+    /// used when an upstream proxy is configured.
+    pub fn set_connector<C>(&mut self, connector: C)
+    where
+        C: Connector + 'static,
+    {
+        self.connector = Box::new(connector);
+    }
 
-        if self.allow_no_auth
-            && version_msg
-                .methods
-                .contains(&Method::Fixed(FixedMethod::NoAuth))
-        {
-            selected = Method::Fixed(FixedMethod::NoAuth);
-        } else if self.userpass_validator.is_some()
-            && version_msg
-                .methods
-                .contains(&Method::Fixed(FixedMethod::UsePass))
-        {
-            selected = Method::Fixed(FixedMethod::UsePass);
-        }
+    /// Send a record of every relayed connection to an [`AuditSink`].
+    ///
+    /// The sink is called when a `CONNECT` starts relaying, SOCKS5, SOCKS4
+    /// or HTTP alike, and when a `UDP ASSOCIATE` is granted, and again when
+    /// either ends. The default sink discards the records.
+    pub fn set_audit_sink<A>(&mut self, sink: A)
+    where
+        A: AuditSink + 'static,
+    {
+        self.audit = Box::new(sink);
+    }
 
-        Self::send_method_selection(stream, selected).await?;
+    /// Send outbound `CONNECT` traffic from `local` instead of the address
+    /// the routing table would pick.
+    ///
+    /// This installs a [`BoundConnector`], replacing any connector set with
+    /// [`Socks5::set_connector`]. Candidates of the other address family
+    /// fail with [`Rep::GeneralFailure`].
+    pub fn set_egress_bind(&mut self, local: IpAddr) {
+        self.set_connector(BoundConnector::new(local));
+    }
 
-        match selected {
-            Method::Fixed(FixedMethod::NoAuth) => Ok(()),
+    /// Use a custom [`Resolver`] to look up the domain names of `CONNECT`
+    /// requests. It is not used when an upstream proxy resolves them.
+    pub fn set_resolver<R>(&mut self, resolver: R)
+    where
+        R: Resolver + 'static,
+    {
+        self.resolver = Box::new(resolver);
+    }
 
-            Method::Fixed(FixedMethod::UsePass) => {
-                let auth_req = Self::read_auth_request(stream).await?;
-                let validator = self.userpass_validator.as_ref().unwrap();
-
-                if validator(&auth_req.uname, &auth_req.passwd) {
-                    Self::send_auth_reply(stream, AuthStatus::Success).await?;
-                    Ok(())
-                } else {
-                    Self::send_auth_reply(stream, AuthStatus::Failure).await?;
-                    Err(SocksError::AuthFailed("invalid credentials".into()))
-                }
-            }
+    /// Choose the order in which the resolved addresses of a destination
+    /// are dialed, e.g. alternating families starting with IPv6 for Happy
+    /// Eyeballs. Defaults to [`AddressPreference::System`].
+    pub fn set_address_preference(&mut self, preference: AddressPreference) {
+        self.address_preference = preference;
+    }
 
-            _ => Err(SocksError::AuthFailed("no acceptable method".into())),
-        }
+    /// Race the resolved addresses of a destination instead of dialing them
+    /// one after the other, Happy Eyeballs style (RFC 8305).
+    ///
+    /// Each attempt runs alone for `delay`, or until it fails, before the
+    /// next candidate is dialed alongside it, and the first to connect is
+    /// used. Combined with [`AddressPreference::Ipv6First`] this keeps a
+    /// broken IPv6 path from stalling dual-stack destinations. The connect
+    /// timeout still applies to each attempt, and `BND.ADDR` is the local
+    /// address of the connection that won. [`HAPPY_EYEBALLS_DELAY`] is a
+    /// good default.
+    ///
+    /// [`HAPPY_EYEBALLS_DELAY`]: connector::HAPPY_EYEBALLS_DELAY
+    pub fn set_happy_eyeballs(&mut self, delay: Duration) {
+        self.happy_eyeballs = Some(delay);
+    }
+
+    /// Choose which error is replied when a destination resolves to several
+    /// addresses and all of them fail.
+    ///
+    /// Candidates are tried in order and the first one that connects is
+    /// answered with [`Rep::Succeeded`], whatever failed before it. Defaults
+    /// to [`ConnectErrorPolicy::LastError`].
+    pub fn set_connect_reply_on_partial_success(&mut self, policy: ConnectErrorPolicy) {
+        self.connect_error_policy = policy;
+    }
+
+    /// Bound the time spent dialing a `CONNECT` destination.
+    ///
+    /// Applies to each candidate address, or to the whole negotiation with an
+    /// upstream proxy if one is configured. An attempt that runs out of time
+    /// fails with `io::ErrorKind::TimedOut`, answered with [`Rep::TTLExpired`]
+    /// if it is the error reported. Without a timeout, the operating system's
+    /// connect timeout applies.
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.connect_timeout = Some(timeout);
+    }
+
+    /// Reject `CONNECT` requests to private, loopback and link-local destinations.
+    ///
+    /// Domain names are resolved first and refused if *any* of the returned
+    /// addresses is private; the connection is then made to the addresses
+    /// that were checked. Refused requests are answered with
+    /// [`Rep::ConnectionNotAllowed`]. See [`AddrPort::is_private`] for the
+    /// exact ranges.
+    pub fn deny_private_destinations(&mut self) {
+        self.deny_private = true;
+    }
+
+    /// Reject `CONNECT` requests for single-label domain names.
+    ///
+    /// Hostnames without a dot, such as `intranet` or `printer`, are usually
+    /// typos, leaked internal names or abuse on a public proxy. They are
+    /// refused with [`Rep::ConnectionNotAllowed`] before any DNS lookup,
+    /// unless they match one of `exceptions` (case-insensitively). IP
+    /// literals sent as domain names are never refused by this check.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.set_deny_domains_without_dot(vec!["localhost".into()]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_deny_domains_without_dot(&mut self, exceptions: Vec<String>) {
+        self.dotless_domain_exceptions = Some(exceptions);
+    }
+
+    /// Returns `true` if `host` is refused by
+    /// [`Socks5::set_deny_domains_without_dot`].
+    fn is_denied_dotless(&self, host: &str) -> bool {
+        let Some(exceptions) = &self.dotless_domain_exceptions else {
+            return false;
+        };
+
+        !host.trim_end_matches('.').contains('.')
+            && host.parse::<IpAddr>().is_err()
+            && !exceptions.iter().any(|e| e.eq_ignore_ascii_case(host))
+    }
+
+    /// Only allow `CONNECT` requests to destination ports in `ranges`.
+    ///
+    /// Requests to any other port are refused with
+    /// [`Rep::ConnectionNotAllowed`] before the destination is resolved or
+    /// dialed. A single port is written as a one-port range, e.g.
+    /// `443..=443`. Calling this again replaces the previous list.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.set_allowed_ports([80..=80, 443..=443, 8000..=8999]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_allowed_ports(&mut self, ranges: impl IntoIterator<Item = RangeInclusive<u16>>) {
+        self.allowed_ports = Some(ranges.into_iter().collect());
+    }
+
+    /// Refuse `CONNECT` requests to destination ports in `ranges`.
+    ///
+    /// Checked together with [`Socks5::set_allowed_ports`]: a port in both
+    /// lists is refused. Calling this again replaces the previous list.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// // Keep the proxy from being used to relay mail.
+    /// server.set_blocked_ports([25..=25, 465..=465, 587..=587]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_blocked_ports(&mut self, ranges: impl IntoIterator<Item = RangeInclusive<u16>>) {
+        self.blocked_ports = ranges.into_iter().collect();
+    }
+
+    /// Returns `true` if `port` is refused by [`Socks5::set_allowed_ports`]
+    /// or [`Socks5::set_blocked_ports`].
+    fn is_denied_port(&self, port: u16) -> bool {
+        let allowed = self
+            .allowed_ports
+            .as_ref()
+            .is_none_or(|ranges| ranges.iter().any(|r| r.contains(&port)));
+
+        !allowed || self.blocked_ports.iter().any(|r| r.contains(&port))
+    }
+
+    /// Rewrite destinations before they are dialed.
+    ///
+    /// `rewriter` is called with the destination of every `CONNECT` request
+    /// once the request is parsed. Returning `Some` dials that address
+    /// instead, returning `None` keeps the requested one. The destination
+    /// policies, such as [`Socks5::deny_private_destinations`], apply to the
+    /// address that is actually dialed, and `BND.ADDR`/`BND.PORT` in the
+    /// reply is the local address of that connection as usual. Events and
+    /// logs report the destination the client asked for.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::net::Ipv4Addr;
+    /// use simple_socks5::Socks5;
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.set_destination_rewriter(|dst| match dst {
+    ///     AddrPort::Domain(host, _) if host == "internal.test" => {
+    ///         Some(AddrPort::V4(Ipv4Addr::LOCALHOST, 9000))
+    ///     }
+    ///     _ => None,
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_destination_rewriter<F>(&mut self, rewriter: F)
+    where
+        F: Fn(&AddrPort) -> Option<AddrPort> + Send + Sync + 'static,
+    {
+        self.destination_rewriter = Some(Box::new(rewriter));
+    }
+
+    /// Decide per user which destinations they may connect to.
+    ///
+    /// `filter` is called with the client's [`AuthContext`] and the
+    /// requested destination before it is dialed; returning `false`
+    /// refuses the request with [`Rep::ConnectionNotAllowed`]. It runs in
+    /// addition to the server-wide policies. SOCKS4 clients and HTTP
+    /// clients that sent no checked credentials are seen as `NO AUTH`
+    /// without a username. A custom [`ConnectionHandler`] dials on its own
+    /// and is not affected.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.allow_userpass(|_, passwd| passwd == "secret");
+    /// server.set_user_destination_filter(|auth, dst| {
+    ///     auth.username.as_deref() == Some("admin")
+    ///         || matches!(dst, AddrPort::Domain(_, 443))
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_user_destination_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&AuthContext, &AddrPort) -> bool + Send + Sync + 'static,
+    {
+        self.user_destination_filter = Some(Box::new(filter));
+    }
+
+    /// Inspect every SOCKS5 request before it is acted on.
+    ///
+    /// `inspector` is called with the client's [`AuthContext`] and the
+    /// parsed [`ConnRequest`], whatever its command. Returning `Err(rep)`
+    /// answers the request with `rep`, which should be a failure code, and
+    /// ends the connection with [`SocksError::RequestRejected`]; returning
+    /// `Ok(())` lets it proceed.
+    ///
+    /// The inspector runs once the request has passed the checks of
+    /// parsing, strict mode and [`Socks5::enable_command`], and before
+    /// anything else looks at it: the destination rewriter, the port,
+    /// domain and private-address policies, the filter set with
+    /// [`Socks5::set_user_destination_filter`], and a
+    /// [`ConnectionHandler`] all come after it, and only see requests it
+    /// let through. It applies to [`Socks5::run`], [`Socks5::serve`] and
+    /// [`Connection::read_request`](connection::Connection::read_request),
+    /// but not to HTTP `CONNECT` or SOCKS4 clients, which send no SOCKS5
+    /// request.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    /// use simple_socks5::conn::reply::Rep;
+    /// use simple_socks5::conn::request::CMD;
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.allow_userpass_static("alice", "secret");
+    /// server.set_request_inspector(|auth, req| match (&req.cmd, &req.dst) {
+    ///     (CMD::UdpAssociate, _) if auth.username.as_deref() != Some("alice") => {
+    ///         Err(Rep::CommandNotSupported)
+    ///     }
+    ///     (_, AddrPort::V4(_, 25) | AddrPort::V6(_, 25) | AddrPort::Domain(_, 25)) => {
+    ///         Err(Rep::ConnectionNotAllowed)
+    ///     }
+    ///     _ => Ok(()),
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_request_inspector<F>(&mut self, inspector: F)
+    where
+        F: Fn(&AuthContext, &ConnRequest) -> Result<(), Rep> + Send + Sync + 'static,
+    {
+        self.request_inspector = Some(Box::new(inspector));
+    }
+
+    /// Accept requests for `cmd`.
+    ///
+    /// Only `CONNECT` is enabled by default. A request for a disabled
+    /// command is answered with [`Rep::CommandNotSupported`] before it
+    /// reaches [`Socks5::run`]'s relay or a [`ConnectionHandler`], and the
+    /// connection ends with `SocksError::UnsupportedCommand`. `run` can
+    /// serve `UDP ASSOCIATE` once enabled; `BIND` is refused by `run` even
+    /// when enabled and is left to custom handlers.
+    pub fn enable_command(&mut self, cmd: CMD) {
+        if !self.commands.contains(&cmd) {
+            self.commands.push(cmd);
+        }
+    }
+
+    /// Refuse requests for `cmd`, see [`Socks5::enable_command`].
+    pub fn disable_command(&mut self, cmd: CMD) {
+        self.commands.retain(|enabled| *enabled != cmd);
+    }
+
+    /// Enforce the parts of RFC 1928 that are commonly ignored.
+    ///
+    /// In strict mode, a request whose reserved byte (`RSV`) is not `0x00`
+    /// is answered with [`Rep::GeneralFailure`] and the connection ends
+    /// with `SocksError::InvalidReserved`. Some clients in the wild send
+    /// garbage there, so the default is lenient: `RSV` is ignored, which
+    /// keeps those clients working at the cost of not flagging malformed
+    /// or non-compliant ones.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Reject `CONNECT` requests that carry an IP address in the domain name
+    /// field (`ATYP` `0x03`), answering them with
+    /// [`Rep::AddressTypeNotSupported`].
+    ///
+    /// Keeps a blocked address from slipping past IP-based rules, e.g. a
+    /// custom [`ConnectionHandler`] matching on [`AddrPort::V4`], by being
+    /// sent as a name. Besides standard notation, the shorthand forms the
+    /// system resolver also accepts, such as `127.1` or `0x7f000001`, count
+    /// as IP addresses.
+    pub fn set_reject_ip_literals_in_domain_atyp(&mut self, reject: bool) {
+        self.reject_ip_literal_domains = reject;
+    }
+
+    /// Set the address reported as `BND.ADDR`/`BND.PORT` in successful
+    /// `CONNECT` replies, instead of the local address of the outbound
+    /// socket.
+    ///
+    /// The real address reveals the proxy's egress IP, which is often an
+    /// internal one; report a public address, or `0.0.0.0:0`, instead.
+    /// `None`, the default, reports the real address. A custom
+    /// [`ConnectionHandler`] sends its own replies and is not affected.
+    pub fn set_advertised_bind_addr(&mut self, addr: Option<AddrPort>) {
+        self.advertised_bind_addr = addr;
+    }
+
+    /// Set a host name to report as `BND.ADDR` in `UDP ASSOCIATE` replies,
+    /// instead of the IP address of the relay socket.
+    ///
+    /// Useful when clients reach the relay through NAT and only a DNS name
+    /// resolves to the right public address. The reply then has `ATYP`
+    /// `0x03` and still carries the relay socket's port as `BND.PORT`.
+    /// `None`, the default, reports the socket's IP address.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SocksError::InvalidDomain`] if `host` is longer than 255
+    /// bytes, the most the reply can encode.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    /// use simple_socks5::conn::request::CMD;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("0.0.0.0:1080").await?;
+    /// server.allow_no_auth();
+    /// server.enable_command(CMD::UdpAssociate);
+    /// server.set_advertised_udp_host(Some("proxy.example.com".into()))?;
+    /// server.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_advertised_udp_host(&mut self, host: Option<String>) -> Result<(), SocksError> {
+        if let Some(host) = host.as_ref().filter(|host| host.len() > u8::MAX as usize) {
+            return Err(SocksError::InvalidDomain(host.clone()));
+        }
+        self.advertised_udp_host = host;
+        Ok(())
+    }
+
+    /// Set the size of the buffer used for each direction of a relay.
+    ///
+    /// Larger buffers reduce the number of reads and writes for bulk
+    /// transfers at the cost of memory per connection. Defaults to 8 KiB.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SocksError::InvalidBufferSize` if `size` is zero or larger
+    /// than 1 MiB.
+    pub fn set_relay_buffer_size(&mut self, size: usize) -> Result<(), SocksError> {
+        if size == 0 || size > MAX_BUFFER_SIZE {
+            return Err(SocksError::InvalidBufferSize(size));
+        }
+        self.relay.buffer_size = size;
+        Ok(())
+    }
+
+    /// Keep up to `capacity` relay buffers around for reuse by later
+    /// connections.
+    ///
+    /// Every relayed connection needs one buffer per direction (see
+    /// [`Socks5::set_relay_buffer_size`]). With a pool, finished connections
+    /// hand their buffers back instead of freeing them, which takes load off
+    /// the allocator when connections are short-lived and frequent. The pool
+    /// holds at most `capacity` idle buffers. Defaults to 0, which allocates
+    /// per connection.
+    pub fn set_buffer_pool_capacity(&mut self, capacity: usize) {
+        self.buffers.set_capacity(capacity);
+    }
+
+    /// Cap the bytes relayed in each direction of a connection.
+    ///
+    /// `up` limits client-to-target traffic and `down` limits
+    /// target-to-client traffic; `None` leaves a direction unlimited. Once
+    /// either cap is reached, the bytes up to the cap are delivered and the
+    /// connection is closed with [`CloseReason::QuotaExceeded`] naming the
+    /// direction.
+    ///
+    /// [`CloseReason::QuotaExceeded`]: relay::CloseReason::QuotaExceeded
+    pub fn set_direction_quota(&mut self, up: Option<u64>, down: Option<u64>) {
+        self.relay.up_quota = up;
+        self.relay.down_quota = down;
+    }
+
+    /// Limit the throughput of each direction of a connection to
+    /// `bytes_per_sec`.
+    ///
+    /// Every connection is paced on its own, with a token bucket that lets
+    /// bursts of up to a tenth of a second through; the relay waits for the
+    /// bucket to refill rather than spinning. A limit of 0 turns pacing off,
+    /// which is the default.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: u64) {
+        self.relay.rate_limit = (bytes_per_sec > 0).then_some(bytes_per_sec);
+    }
+
+    /// Pick the throughput limit of each connection from the client's
+    /// [`AuthContext`], e.g. from the plan attached with
+    /// [`Socks5::allow_userpass_ctx`].
+    ///
+    /// Returning `Some` replaces the limit set with
+    /// [`Socks5::set_rate_limit`] for that connection, with 0 turning pacing
+    /// off; returning `None` keeps it.
+    pub fn set_user_rate_limit<F>(&mut self, limit: F)
+    where
+        F: Fn(&AuthContext) -> Option<u64> + Send + Sync + 'static,
+    {
+        self.user_rate_limit = Some(Box::new(limit));
+    }
+
+    /// Set the kernel send and receive buffer sizes (`SO_SNDBUF`,
+    /// `SO_RCVBUF`) of client sockets and of outbound `CONNECT` sockets.
+    ///
+    /// On high-latency, high-bandwidth links the buffers must hold a full
+    /// bandwidth-delay product, e.g. 100 Mbit/s over 100 ms needs about
+    /// 1.25 MB, or throughput stays capped by the system defaults. `None`
+    /// leaves a size at the system default.
+    ///
+    /// The sizes are applied to the listener right away, so accepted
+    /// sockets inherit them from the handshake on, and to each outbound
+    /// socket once it is connected.
+    ///
+    /// Linux doubles the requested value to leave room for bookkeeping
+    /// (reading it back shows twice the size) and caps it at
+    /// `net.core.wmem_max` / `net.core.rmem_max`; other platforms apply
+    /// their own limits. Setting a buffer disables the kernel's automatic
+    /// tuning of that buffer for the socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SocksError::Io` if the listener rejects the sizes.
+    pub fn set_socket_buffer_sizes(
+        &mut self,
+        send: Option<usize>,
+        recv: Option<usize>,
+    ) -> Result<(), SocksError> {
+        self.socket_options.send = send;
+        self.socket_options.recv = recv;
+        self.socket_options
+            .apply_buffers(&SockRef::from(&self.listener))?;
+        Ok(())
+    }
+
+    /// Set `TCP_NODELAY` on client and outbound sockets, turning off Nagle's
+    /// algorithm.
+    ///
+    /// Nagle's algorithm holds back small writes, adding latency to
+    /// interactive protocols such as SSH, while a proxy only forwards what
+    /// it reads and gains nothing from it. Defaults to `true`.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.socket_options.nodelay = nodelay;
+    }
+
+    /// Enable TCP keepalive on client and outbound sockets, sending the
+    /// first probe after `idle` without traffic.
+    ///
+    /// Detects peers that vanished without closing the connection, and keeps
+    /// NAT and firewall state alive on idle relays. `None`, the default,
+    /// leaves the system setting in place, which is usually off.
+    pub fn set_keepalive(&mut self, idle: Option<Duration>) {
+        self.socket_options.keepalive = idle;
+    }
+
+    /// Close relays that move no bytes in either direction for `timeout`.
+    ///
+    /// The timer restarts whenever bytes are relayed either way and only
+    /// runs once the relay has started; the handshake and connecting to the
+    /// destination are not covered. On expiry both streams are shut down,
+    /// the relay is reported as
+    /// [`CloseReason::IdleTimeout`](relay::CloseReason::IdleTimeout) and the
+    /// connection ends with `SocksError::IdleTimeout`. Off by default.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.relay.idle_timeout = Some(timeout);
+    }
+
+    /// Bound the time spent flushing and shutting down each side of a relay
+    /// once the other side has closed.
+    ///
+    /// Once one direction has finished, a write in the other one that makes
+    /// no progress for `timeout` ends the relay as if that peer had gone
+    /// away, and so does a shutdown that takes longer. This keeps a peer
+    /// that never drains its socket from holding the connection open
+    /// forever during teardown, while a half-closed transfer to a peer that
+    /// keeps reading runs as long as it needs. Defaults to 5 seconds.
+    pub fn set_close_notify_timeout(&mut self, timeout: Duration) {
+        self.relay.close_timeout = timeout;
+    }
+
+    /// Limit the number of clients served at the same time by
+    /// [`Socks5::run`], [`Socks5::run_with_shutdown`] and [`Socks5::serve`].
+    ///
+    /// Once `max` connections are being handled, new ones are treated
+    /// according to [`Socks5::set_overload_policy`]. Unlimited by default.
+    pub fn set_max_connections(&mut self, max: usize) {
+        self.connection_limit = Some(Arc::new(Semaphore::new(max)));
+    }
+
+    /// Choose whether connections over the limit wait or are rejected.
+    /// Defaults to [`OverloadPolicy::Wait`].
+    pub fn set_overload_policy(&mut self, policy: OverloadPolicy) {
+        self.overload_policy = policy;
+    }
+
+    /// Set the capacity of the channel returned by [`Socks5::events`].
+    ///
+    /// Takes effect for channels created afterwards. Defaults to 1024.
+    pub fn set_event_buffer(&mut self, capacity: usize) {
+        self.event_buffer = capacity.max(1);
+    }
+
+    /// Subscribe to connection lifecycle [`Event`]s.
+    ///
+    /// Events that do not fit into the channel are dropped and counted in
+    /// [`MetricsSnapshot::events_dropped`]. Calling this again replaces the
+    /// previous channel.
+    pub fn events(&mut self) -> mpsc::Receiver<Event> {
+        let (tx, rx) = mpsc::channel(self.event_buffer);
+        self.events = Some(tx);
+        rx
+    }
+
+    /// Publish an event without ever waiting for the consumer.
+    fn emit(&self, event: Event) {
+        if let Some(events) = &self.events
+            && let Err(TrySendError::Full(_)) = events.try_send(event)
+        {
+            self.metrics.record_event_dropped();
+        }
+    }
+
+    /// Set how long in-flight connections may keep running after a shutdown
+    /// signal before they are aborted. Defaults to 30 seconds.
+    pub fn set_shutdown_grace_period(&mut self, grace: Duration) {
+        self.shutdown_grace_period = grace;
+    }
+
+    /// Accept a client TCP connection.
+    ///
+    /// # Returns
+    ///
+    /// A tuple of `(TcpStream, SocketAddr)` representing the connected client.
+    pub async fn accept(&self) -> Result<(TcpStream, SocketAddr), SocksError> {
+        let (stream, addr) = self.listener.accept().await?;
+        self.socket_options.apply(&SockRef::from(&stream))?;
+        Ok((stream, addr))
+    }
+
+    /// Accept the next client, riding out failures that do not break the
+    /// listener.
+    ///
+    /// Failed connections are skipped, and when file descriptors run out
+    /// the loop pauses for [`ACCEPT_BACKOFF`] so that clients can finish
+    /// and free some. Only fatal listener errors are returned.
+    async fn accept_next(&self) -> Result<(TcpStream, SocketAddr), SocksError> {
+        loop {
+            let err = match self.listener.accept().await {
+                Ok((stream, addr)) => match self.socket_options.apply(&SockRef::from(&stream)) {
+                    Ok(()) => return Ok((stream, addr)),
+                    Err(_) => {
+                        trace::debug!(client = %addr, "dropped client, setting socket options failed");
+                        continue;
+                    }
+                },
+                Err(e) => e,
+            };
+
+            match classify_accept_error(&err) {
+                AcceptFailure::Transient => {
+                    trace::debug!(error = %err, "accept failed");
+                }
+                AcceptFailure::Exhausted => {
+                    trace::info!(error = %err, "accept failed, backing off");
+                    time::sleep(ACCEPT_BACKOFF).await;
+                }
+                AcceptFailure::Fatal => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Accept the next client allowed in by the connection limit.
+    ///
+    /// The returned permit, if any, must be held for as long as the client
+    /// is being served.
+    async fn accept_admitted(
+        &self,
+    ) -> Result<(TcpStream, SocketAddr, Option<OwnedSemaphorePermit>), SocksError> {
+        let Some(limit) = &self.connection_limit else {
+            let (stream, addr) = self.accept_next().await?;
+            return Ok((stream, addr, None));
+        };
+
+        loop {
+            match self.overload_policy {
+                OverloadPolicy::Wait => {
+                    // The semaphore is never closed.
+                    let permit = Arc::clone(limit).acquire_owned().await.unwrap();
+                    let (stream, addr) = self.accept_next().await?;
+                    return Ok((stream, addr, Some(permit)));
+                }
+                OverloadPolicy::Reject => {
+                    let (stream, addr) = self.accept_next().await?;
+                    if let Ok(permit) = Arc::clone(limit).try_acquire_owned() {
+                        return Ok((stream, addr, Some(permit)));
+                    }
+                    drop(stream);
+                }
+            }
+        }
+    }
+
+    /// **Testing only: makes the server violate RFC 1928.**
+    ///
+    /// Sends `ver` instead of `0x05` as `VER` of method selections and
+    /// connection replies, and `rsv` instead of `0x00` as `RSV` of connection
+    /// replies, to check how strictly a client validates them. `None` keeps
+    /// the standard value. Only replies the server sends itself are affected,
+    /// not those sent by a [`ConnectionHandler`]. Available with the
+    /// `test-util` feature.
+    #[cfg(feature = "test-util")]
+    pub fn set_reply_overrides(&mut self, ver: Option<u8>, rsv: Option<u8>) {
+        self.reply_overrides = ReplyOverrides { ver, rsv };
+    }
+
+    /// Returns the local address of the server.
+    pub fn local_addr(&self) -> Result<SocketAddr, SocksError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Returns a snapshot of the connections currently being relayed.
+    ///
+    /// Each [`ConnectionInfo`] carries the byte totals so far and a moving
+    /// average of the connection's throughput.
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.registry.snapshot()
+    }
+
+    /// Close every relayed connection that has not moved a byte in either
+    /// direction for at least `idle_for`, keeping active ones untouched.
+    ///
+    /// Each closed relay ends with [`CloseReason::IdleDrained`](relay::CloseReason::IdleDrained).
+    /// Returns the number of connections closed.
+    pub fn drain_idle_connections(&self, idle_for: Duration) -> usize {
+        self.registry.drain_idle(idle_for)
+    }
+
+    /// Returns a snapshot of the server's connection and traffic counters.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Renders the server's counters in the Prometheus text exposition format.
+    ///
+    /// Besides the values in [`MetricsSnapshot`], the output contains the
+    /// number of replies sent per [`Rep`] and of relays ended per
+    /// [`CloseReason`](relay::CloseReason), as labeled series. Serve it from
+    /// any HTTP handler to have the proxy scraped.
+    pub fn metrics_prometheus(&self) -> String {
+        self.metrics.render_prometheus()
+    }
+
+    // --- Protocol helpers ---
+
+    /// Check the first byte the client sent without consuming it.
+    ///
+    /// Waits for at least one byte and returns it if it is the SOCKS5
+    /// version. Anything else is rejected right away, while the bytes stay
+    /// in the stream for another protocol handler to read, which allows
+    /// serving SOCKS5 and other protocols on one port.
+    /// The server performs this check on every client it accepts before
+    /// authenticating it.
+    ///
+    /// # Errors
+    ///
+    /// - `SocksError::UnsupportedVersion` if the first byte is not `0x05`.
+    /// - `SocksError::ClientDisconnected` if the client closes the
+    ///   connection before sending anything.
+    /// - `SocksError::Io` on transport failures.
+    pub async fn peek_version(stream: &TcpStream) -> Result<u8, SocksError> {
+        let mut ver = [0u8; 1];
+        if stream.peek(&mut ver).await? == 0 {
+            return Err(SocksError::ClientDisconnected);
+        }
+        match ver[0] {
+            SOCKS_VERSION => Ok(SOCKS_VERSION),
+            other => Err(SocksError::UnsupportedVersion(other)),
+        }
+    }
+
+    /// Read a SOCKS5 version/method message from the client.
+    ///
+    /// Bytes the client sent after the message stay in the stream, see
+    /// [`VersionMessage::read_from`].
+    pub async fn read_version_message<S>(stream: &mut S) -> Result<VersionMessage, SocksError>
+    where
+        S: AsyncRead + Unpin,
+    {
+        VersionMessage::read_from(stream).await
+    }
+
+    /// Send the server's method selection message.
+    pub async fn send_method_selection<S>(stream: &mut S, method: Method) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let sel = MethodSelection::new(method);
+        stream.write_all(&sel.to_bytes()).await?;
+        Ok(())
+    }
+
+    /// Answer a version message with `NO ACCEPTABLE METHODS` (`0xFF`) and
+    /// shut down the write half of `stream`.
+    ///
+    /// RFC 1928 §3 requires the client to close the connection after this
+    /// answer; shutting down the write half tells it no more data follows.
+    /// The server does the same on its own when no offered method is
+    /// enabled, see [`Socks5::authenticate`].
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::Socks5;
+    /// use tokio::io::AsyncReadExt;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), simple_socks5::error::SocksError> {
+    /// let (mut client, mut server) = tokio::io::duplex(64);
+    /// Socks5::reject_methods(&mut server).await?;
+    ///
+    /// let mut reply = Vec::new();
+    /// client.read_to_end(&mut reply).await?;
+    /// assert_eq!(reply, [0x05, 0xFF]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reject_methods<S>(stream: &mut S) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        Self::send_method_selection(stream, Method::Fixed(FixedMethod::NoAcceptable)).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Read a username/password authentication request from the client.
+    ///
+    /// Bytes the client sent after the request stay in the stream, see
+    /// [`AuthRequest::read_from`].
+    pub async fn read_auth_request<S>(stream: &mut S) -> Result<AuthRequest, SocksError>
+    where
+        S: AsyncRead + Unpin,
+    {
+        AuthRequest::read_from(stream).await
+    }
+
+    /// Send an authentication reply to the client.
+    pub async fn send_auth_reply<S>(stream: &mut S, status: AuthStatus) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let reply = AuthReply::new(status);
+        stream.write_all(&reply.to_bytes()).await?;
+        Ok(())
+    }
+
+    /// Read a SOCKS5 connection request from the client.
+    pub async fn read_conn_request<S>(stream: &mut S) -> Result<ConnRequest, SocksError>
+    where
+        S: AsyncRead + Unpin,
+    {
+        ConnRequest::read_from(stream).await
+    }
+
+    /// Send a connection reply to the client.
+    ///
+    /// The `ATYP` field is derived from `addr` with [`AddrPort::atyp`].
+    pub async fn send_conn_reply<S>(
+        stream: &mut S,
+        rep: Rep,
+        addr: AddrPort,
+    ) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let reply = ConnReply::new(SOCKS_VERSION, rep, 0x00, addr.atyp(), addr);
+        stream.write_all(&reply.to_bytes()?).await?;
+        Ok(())
+    }
+
+    /// Send the successful reply to a `UDP ASSOCIATE` request.
+    ///
+    /// `bound` must be the address of the UDP relay socket the client is to
+    /// send its datagrams to, e.g. from [`UdpAssociation::local_addr`], not
+    /// the destination from the request.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    /// use tokio::net::{TcpStream, UdpSocket};
+    ///
+    /// # async fn run(mut control: TcpStream) -> Result<(), simple_socks5::error::SocksError> {
+    /// let relay = UdpSocket::bind("0.0.0.0:0").await?;
+    /// Socks5::send_udp_associate_reply(&mut control, relay.local_addr()?).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_udp_associate_reply<S>(
+        stream: &mut S,
+        bound: SocketAddr,
+    ) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        Self::send_conn_reply(stream, Rep::Succeeded, AddrPort::from(bound)).await
+    }
+
+    /// Send the connection reply matching `err` to the client.
+    ///
+    /// The reply code comes from [`SocksError::to_rep`], falling back to
+    /// [`Rep::GeneralFailure`] for errors that have no better match, and the
+    /// bound address is left unspecified (`0.0.0.0:0`). Meant for failures
+    /// after the handshake, e.g. in a custom
+    /// [`ConnectionHandler`](handler::ConnectionHandler), so the client gets a
+    /// well-formed reply rather than a bare close.
+    pub async fn send_error_reply<S>(stream: &mut S, err: &SocksError) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let rep = err.to_rep().unwrap_or(Rep::GeneralFailure);
+        Self::send_conn_reply(stream, rep, AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0)).await
+    }
+
+    /// Copy bytes between a client and its target in both directions until
+    /// both have closed.
+    ///
+    /// When one side reaches EOF, the write half of the other is shut down,
+    /// so half-closed protocols keep working. A peer that resets or drops
+    /// the connection ends the relay without an error.
+    ///
+    /// # Returns
+    ///
+    /// The byte totals as `(client_to_target, target_to_client)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SocksError::Io` on transport failures other than a
+    /// disconnect.
+    ///
+    /// On Linux with the `splice` feature, bytes move between the sockets
+    /// with `splice(2)` instead of through a userspace buffer, falling back
+    /// to the buffered copy if the kernel cannot provide the pipes.
+    pub async fn relay(
+        client: &mut TcpStream,
+        target: &mut TcpStream,
+    ) -> Result<(u64, u64), SocksError> {
+        #[cfg(all(target_os = "linux", feature = "splice"))]
+        if let (Ok(up), Ok(down)) = (relay::splice::Pipe::new(), relay::splice::Pipe::new()) {
+            return Ok(relay::splice::copy_bidirectional(client, target, (up, down)).await?);
+        }
+
+        let stats = relay::pump(
+            client,
+            target,
+            &Traffic::new(),
+            &Metrics::default(),
+            &RelayOptions::default(),
+            &BufferPool::default(),
+        )
+        .await?;
+        Ok((stats.client_to_target, stats.target_to_client))
+    }
+
+    /// Bind a UDP socket for `UDP ASSOCIATE`.
+    ///
+    /// The server itself binds on the interface of the control connection,
+    /// see [`UdpAssociation::bind`].
+    ///
+    /// **Actual UDP relay is not implemented yet.**
+    pub async fn bind_udp(addr: &str) -> Result<UdpSocket, SocksError> {
+        let sock = UdpSocket::bind(addr).await?;
+        Ok(sock)
+    }
+
+    /// Perform authentication according to the configured methods.
+    ///
+    /// Selects the first method in the preference order (see
+    /// [`Socks5::set_method_preference`]) that is both enabled and offered
+    /// by the client. If there is none, e.g. a client offering only GSSAPI,
+    /// the client is answered with `NO ACCEPTABLE METHODS` (`0xFF`), the
+    /// write half of `stream` is shut down as RFC 1928 §3 requires, and
+    /// `SocksError::NoAcceptableMethod` is returned. Unlike
+    /// `SocksError::AuthFailed`, that error means the client was refused
+    /// cleanly and needs no further handling. A client offering no methods
+    /// at all gets the same answer, with `SocksError::NoMethodsOffered`.
+    ///
+    /// On success, returns the selected method and, for username/password,
+    /// the username, for per-user logging or policy.
+    ///
+    /// Any stream works, e.g. a TLS stream or one half of
+    /// [`tokio::io::duplex`]. Unlike [`Socks5::peek_version`], a version
+    /// other than `0x05` is only noticed once the version message has been
+    /// read. The stream has no client address, so the limit set with
+    /// [`Socks5::set_auth_rate_limit`] is not applied; use
+    /// [`Socks5::authenticate_from`] for that. The stream must be `Send` so
+    /// that it can be handed to a handler registered with
+    /// [`Socks5::register_method`].
+    ///
+    /// [`Connection`](connection::Connection) runs the same steps over a
+    /// buffered stream.
+    pub async fn authenticate<S>(&self, stream: &mut S) -> Result<AuthContext, SocksError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let selected = self.negotiate(stream).await?;
+        self.authenticate_method(stream, selected, None).await
+    }
+
+    /// Like [`Socks5::authenticate`], counting failed attempts against
+    /// `client` for [`Socks5::set_auth_rate_limit`].
+    pub async fn authenticate_from<S>(
+        &self,
+        stream: &mut S,
+        client: SocketAddr,
+    ) -> Result<AuthContext, SocksError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let selected = self.negotiate(stream).await?;
+        let peer = self.limiter_peer(client);
+        self.authenticate_method(stream, selected, peer).await
+    }
+
+    /// Read the version message and answer it with the selected method, or
+    /// refuse it, see [`Socks5::authenticate`].
+    async fn negotiate<S>(&self, stream: &mut S) -> Result<Method, SocksError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let version_msg = match VersionMessage::read_from(stream).await {
+            Ok(msg) => msg,
+            Err(SocksError::NoMethodsOffered) => {
+                self.refuse_methods(stream).await?;
+                return Err(SocksError::NoMethodsOffered);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let Some(selected) = self
+            .method_preference
+            .iter()
+            .copied()
+            .find(|m| self.is_method_enabled(*m) && version_msg.methods.contains(m))
+        else {
+            self.refuse_methods(stream).await?;
+            return Err(SocksError::NoAcceptableMethod);
+        };
+
+        self.select_method(stream, selected).await?;
+        trace::debug!(method = ?selected, "method selected");
+        Ok(selected)
+    }
+
+    /// The peer IP that failed attempts are counted against, if an auth
+    /// limiter is configured.
+    fn limiter_peer(&self, client: SocketAddr) -> Option<IpAddr> {
+        self.auth_limiter.as_ref().map(|_| client.ip())
+    }
+
+    /// Check a username and password against the validator.
+    ///
+    /// Shared by every front end that accepts username/password
+    /// credentials, so they all honor [`Socks5::set_max_credential_len`] and
+    /// [`Socks5::set_auth_rate_limit`]. Failures are counted against `peer`,
+    /// the client IP from [`Socks5::limiter_peer`].
+    async fn check_userpass(
+        &self,
+        uname: &str,
+        passwd: &str,
+        peer: Option<IpAddr>,
+    ) -> Result<AuthContext, SocksError> {
+        let limited = self.auth_limiter.as_ref().zip(peer);
+        if let Some((limiter, ip)) = limited
+            && limiter.is_blocked(ip)
+        {
+            self.metrics.record_auth_failure();
+            return Err(SocksError::AuthFailed("too many failed attempts".into()));
+        }
+
+        let failure = match (self.max_credential_len, &self.userpass_validator) {
+            (Some(max), _) if uname.len() > max || passwd.len() > max => "credentials too long",
+            (_, Some(validator)) => match self.run_validator(validator, uname, passwd).await? {
+                Some(context) => {
+                    trace::debug!(user = %uname, "credentials accepted");
+                    return Ok(AuthContext {
+                        method: Method::userpass(),
+                        username: Some(uname.to_string()),
+                        context,
+                    });
+                }
+                None => "invalid credentials",
+            },
+            (_, None) => "invalid credentials",
+        };
+
+        self.metrics.record_auth_failure();
+        if let Some((limiter, ip)) = limited {
+            limiter.record_failure(ip);
+        }
+        Err(SocksError::AuthFailed(failure.into()))
+    }
+
+    /// Calls `validator`, on the blocking thread pool if it was installed
+    /// with [`Socks5::allow_userpass_from_file`].
+    async fn run_validator(
+        &self,
+        validator: &UserPassValidator,
+        uname: &str,
+        passwd: &str,
+    ) -> Result<Option<Option<UserContext>>, SocksError> {
+        if !self.userpass_blocking {
+            return Ok(validator(uname, passwd));
+        }
+        let validator = Arc::clone(validator);
+        let (uname, passwd) = (uname.to_string(), passwd.to_string());
+        tokio::task::spawn_blocking(move || validator(&uname, &passwd))
+            .await
+            .map_err(|e| SocksError::Io(io::Error::other(e)))
+    }
+
+    /// Run the sub-negotiation of the `selected` method.
+    ///
+    /// `peer` is the client IP from [`Socks5::limiter_peer`].
+    async fn authenticate_method<S>(
+        &self,
+        stream: &mut S,
+        selected: Method,
+        peer: Option<IpAddr>,
+    ) -> Result<AuthContext, SocksError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        if let Some(handler) = self.custom_method(selected) {
+            let limited = self.auth_limiter.as_ref().zip(peer);
+            if let Some((limiter, ip)) = limited
+                && limiter.is_blocked(ip)
+            {
+                self.metrics.record_auth_failure();
+                return Err(SocksError::AuthFailed("too many failed attempts".into()));
+            }
+
+            return match handler.authenticate(stream).await {
+                Ok(username) => Ok(AuthContext {
+                    method: selected,
+                    username,
+                    context: None,
+                }),
+                Err(e) => {
+                    self.metrics.record_auth_failure();
+                    if let Some((limiter, ip)) = limited {
+                        limiter.record_failure(ip);
+                    }
+                    Err(e)
+                }
+            };
+        }
+
+        match selected {
+            Method::Fixed(FixedMethod::NoAuth) => Ok(AuthContext {
+                method: selected,
+                username: None,
+                context: None,
+            }),
+
+            Method::Fixed(FixedMethod::UsePass) => {
+                let auth_req = AuthRequest::read_from(stream).await?;
+                match self
+                    .check_userpass(&auth_req.uname, &auth_req.passwd, peer)
+                    .await
+                {
+                    Ok(auth) => {
+                        Self::write_auth_reply(stream, AuthStatus::Success).await?;
+                        Ok(auth)
+                    }
+                    Err(e) => {
+                        Self::write_auth_reply(stream, AuthStatus::Failure).await?;
+                        Err(e)
+                    }
+                }
+            }
+
+            _ => {
+                self.metrics.record_auth_failure();
+                Err(SocksError::NoAcceptableMethod)
+            }
+        }
+    }
+
+    /// Open a connection to the destination of a `CONNECT` request.
+    ///
+    /// Dials `dst` directly, or through the upstream proxy if one was
+    /// configured with [`Socks5::set_upstream`]. IPv4-mapped IPv6
+    /// destinations are dialed as plain IPv4 (see [`AddrPort::normalized`]),
+    /// after the rewriter set with [`Socks5::set_destination_rewriter`]
+    /// has been applied.
+    ///
+    /// # Returns
+    ///
+    /// The connected stream and the address to report as `BND.ADDR`/`BND.PORT`:
+    /// the local address of the outbound socket, or the address negotiated by
+    /// the upstream proxy.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::ConnectionNotAllowed`] or
+    ///   [`SocksError::IpLiteralInDomain`] if the destination is refused by
+    ///   policy.
+    /// - [`SocksError::InvalidDomain`] if an HTTP upstream is set and the
+    ///   domain name cannot be sent in a `CONNECT` request line.
+    /// - [`SocksError::DnsResolution`] if the host name cannot be resolved.
+    /// - [`SocksError::ConnectFailed`] if no address of the destination
+    ///   could be dialed.
+    /// - The errors of [`client::connect`] or [`client::connect_http`] when
+    ///   going through an upstream proxy.
+    pub async fn connect(&self, dst: &AddrPort) -> Result<(TcpStream, AddrPort), SocksError> {
+        let dst = &match self.destination_rewriter.as_ref().and_then(|f| f(dst)) {
+            Some(to) => {
+                trace::debug!(dst = %dst, to = %to, "destination rewritten");
+                to.normalized()
+            }
+            None => dst.normalized(),
+        };
+
+        if let Some(UpstreamKind::HttpConnect { .. }) = &self.upstream {
+            client::http_authority(dst)?;
+        }
+        let (AddrPort::V4(_, port) | AddrPort::V6(_, port) | AddrPort::Domain(_, port)) = dst;
+        if self.is_denied_port(*port) {
+            return Err(SocksError::ConnectionNotAllowed(dst.clone()));
+        }
+        if let AddrPort::Domain(host, _) = dst
+            && self.reject_ip_literal_domains
+            && is_ip_literal(host)
+        {
+            return Err(SocksError::IpLiteralInDomain(host.clone()));
+        }
+        if let AddrPort::Domain(host, _) = dst
+            && self.is_denied_dotless(host)
+        {
+            return Err(SocksError::ConnectionNotAllowed(dst.clone()));
+        }
+        let resolved = match dst {
+            AddrPort::Domain(host, port) if self.deny_private || self.upstream.is_none() => {
+                let addrs = self.resolver.resolve(host, *port).await.map_err(|source| {
+                    SocksError::DnsResolution {
+                        host: host.clone(),
+                        source,
+                    }
+                })?;
+                Some(self.address_preference.sort(addrs))
+            }
+            _ => None,
+        };
+
+        if self.deny_private {
+            let private = match &resolved {
+                Some(addrs) => addrs.iter().any(|a| is_private_ip(&a.ip())),
+                None => dst.is_private(),
+            };
+            if private {
+                return Err(SocksError::ConnectionNotAllowed(dst.clone()));
+            }
+        }
+
+        let (target, bnd) = match &self.upstream {
+            Some(UpstreamKind::Socks5 { addr, auth }) => {
+                let credentials = auth.as_ref().map(|(u, p)| (u.as_str(), p.as_str()));
+                self.with_connect_timeout(client::connect(addr, dst, credentials))
+                    .await??
+            }
+            Some(UpstreamKind::HttpConnect { addr, auth }) => {
+                let credentials = auth.as_ref().map(|(u, p)| (u.as_str(), p.as_str()));
+                self.with_connect_timeout(client::connect_http(addr, dst, credentials))
+                    .await??
+            }
+            None => {
+                let (addrs, hostname) = match dst {
+                    AddrPort::V4(ip, port) => (vec![SocketAddr::from((*ip, *port))], None),
+                    AddrPort::V6(ip, port) => (vec![SocketAddr::from((*ip, *port))], None),
+                    AddrPort::Domain(host, _) => (resolved.unwrap_or_default(), Some(host.clone())),
+                };
+
+                let target = self.dial(addrs, hostname).await.map_err(|source| {
+                    SocksError::ConnectFailed {
+                        dst: dst.clone(),
+                        source,
+                    }
+                })?;
+                let bnd = AddrPort::from(target.local_addr()?);
+                (target, bnd)
+            }
+        };
+
+        self.socket_options.apply(&SockRef::from(&target))?;
+        Ok((target, bnd))
+    }
+
+    /// Like [`Socks5::connect`], first checking `dst` against the filter set
+    /// with [`Socks5::set_user_destination_filter`].
+    async fn connect_as(
+        &self,
+        auth: &AuthContext,
+        dst: &AddrPort,
+    ) -> Result<(TcpStream, AddrPort), SocksError> {
+        if let Some(filter) = &self.user_destination_filter
+            && !filter(auth, dst)
+        {
+            return Err(SocksError::ConnectionNotAllowed(dst.clone()));
+        }
+        self.connect(dst).await
+    }
+
+    /// Hand each candidate address to the connector until one connects.
+    ///
+    /// If none does, the error picked by the [`ConnectErrorPolicy`] is returned.
+    async fn dial(
+        &self,
+        addrs: Vec<SocketAddr>,
+        hostname: Option<String>,
+    ) -> io::Result<TcpStream> {
+        if let Some(delay) = self.happy_eyeballs {
+            let attempt = |addr| -> ConnectFuture<'_> {
+                let dst = Destination {
+                    addr,
+                    hostname: hostname.clone(),
+                };
+                Box::pin(async move {
+                    self.with_connect_timeout(self.connector.connect(&dst))
+                        .await?
+                })
+            };
+            return match connector::race(addrs, delay, attempt).await {
+                Ok(stream) => Ok(stream),
+                Err(errors) => Err(self.dial_error(errors)),
+            };
+        }
+
+        let mut errors = Vec::new();
+
+        for addr in addrs {
+            let dst = Destination {
+                addr,
+                hostname: hostname.clone(),
+            };
+            match self
+                .with_connect_timeout(self.connector.connect(&dst))
+                .await
+            {
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(e)) | Err(e) => errors.push(e),
+            }
+        }
+
+        Err(self.dial_error(errors))
+    }
+
+    /// The error to report once every candidate has failed.
+    fn dial_error(&self, errors: Vec<io::Error>) -> io::Error {
+        self.connect_error_policy
+            .select(errors)
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses resolved"))
+    }
+
+    /// Run `fut`, failing with `TimedOut` if the connect timeout elapses first.
+    async fn with_connect_timeout<F: Future>(&self, fut: F) -> io::Result<F::Output> {
+        match self.connect_timeout {
+            Some(limit) => time::timeout(limit, fut)
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out")),
+            None => Ok(fut.await),
+        }
+    }
+
+    /// Serve a single client connection.
+    ///
+    /// Performs authentication, reads the connection request and, for
+    /// `CONNECT`, dials the destination and relays traffic in both directions
+    /// until either side closes. `UDP ASSOCIATE` is served when enabled with
+    /// [`Socks5::enable_command`]; other commands are answered with
+    /// [`Rep::CommandNotSupported`].
+    ///
+    /// Malformed requests and failed connections are answered as with
+    /// [`Socks5::send_error_reply`]: [`Rep::ConnectionNotAllowed`] for
+    /// destinations refused by policy, the upstream's own code when an
+    /// upstream proxy refuses the request, and the closest match for
+    /// transport errors, falling back to [`Rep::GeneralFailure`]. The error
+    /// is returned afterwards.
+    pub async fn handle_client(&self, stream: TcpStream) -> Result<(), SocksError> {
+        let client = stream.peer_addr()?;
+        trace::in_client_span(client, async {
+            let result = self.handle_connection(stream, client).await;
+            #[cfg(feature = "tracing")]
+            match &result {
+                Err(SocksError::ClientDisconnected) => tracing::debug!("client disconnected"),
+                Err(e) => tracing::info!(error = %e, "connection failed"),
+                Ok(()) => {}
+            }
+            result
+        })
+        .await
+    }
+
+    async fn handle_connection(
+        &self,
+        stream: TcpStream,
+        client: SocketAddr,
+    ) -> Result<(), SocksError> {
+        let _active = self.metrics.connection();
+        self.emit(Event::Connected { client });
+
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = &self.tls {
+            let local = stream.local_addr()?;
+            let stream = acceptor.accept(stream).await.map_err(SocksError::Tls)?;
+            trace::debug!("TLS handshake completed");
+            return self.handle_socks5(stream, client, local).await;
+        }
+
+        let mut head = [0u8; 2];
+        let n = stream.peek(&mut head).await?;
+        let head = &head[..n];
+        if self.allow_http_connect && http::is_http(head) {
+            return self.handle_http(stream, client).await;
+        }
+        if self.allow_socks4 && head.first() == Some(&0x04) {
+            return self.handle_socks4(stream, client).await;
+        }
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = &self.tls_on_socks_port
+            && is_tls_record(head)
+        {
+            let local = stream.local_addr()?;
+            let stream = acceptor.accept(stream).await.map_err(SocksError::Tls)?;
+            trace::debug!("TLS handshake completed");
+            return self.handle_socks5(stream, client, local).await;
+        }
+
+        if let Err(e) = Self::peek_version(&stream).await {
+            if let SocksError::UnsupportedVersion(_) = e {
+                self.emit(Event::ProtocolMismatch {
+                    client,
+                    first_bytes: head.to_vec(),
+                });
+            }
+            return Err(e);
+        }
+        let local = stream.local_addr()?;
+        self.handle_socks5(stream, client, local).await
+    }
+
+    /// Serve a SOCKS5 client on any stream, once the transport is set up.
+    ///
+    /// `local` is the server side of the TCP connection, where a
+    /// `UDP ASSOCIATE` socket is bound.
+    async fn handle_socks5<S>(
+        &self,
+        mut stream: S,
+        client: SocketAddr,
+        local: SocketAddr,
+    ) -> Result<(), SocksError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let auth = self.authenticate_from(&mut stream, client).await?;
+
+        let req = self.read_request(&mut stream, &auth).await?;
+        trace::debug!(cmd = %req.cmd, dst = %req.dst, "request received");
+
+        match req.cmd {
+            CMD::Connect => {
+                let (mut target, bnd) = match self.connect_as(&auth, &req.dst).await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        self.reply_error(&mut stream, &e).await?;
+                        return Err(e);
+                    }
+                };
+
+                let bnd = self.advertised_bind_addr.clone().unwrap_or(bnd);
+                self.reply(&mut stream, Rep::Succeeded, bnd).await?;
+                self.relay_connection(&mut stream, &mut target, client, &auth, req.dst)
+                    .await?;
+            }
+
+            CMD::UdpAssociate => {
+                let association = match UdpAssociation::bind_on(local, client, &req.dst).await {
+                    Ok(association) => association,
+                    Err(e) => {
+                        self.reply_error(&mut stream, &e).await?;
+                        return Err(e);
+                    }
+                };
+                let bnd = association.local_addr()?;
+                trace::debug!(bnd = %bnd, "UDP association bound");
+                let bnd = match (&self.advertised_udp_host, bnd) {
+                    (Some(host), AddrPort::V4(_, port) | AddrPort::V6(_, port)) => {
+                        AddrPort::Domain(host.clone(), port)
+                    }
+                    (_, bnd) => bnd,
+                };
+                self.reply(&mut stream, Rep::Succeeded, bnd).await?;
+
+                let audit = AuditEvent::started(client, auth.username, req.dst, CMD::UdpAssociate);
+                self.audit.record(audit.clone()).await;
+
+                // Nothing is relayed yet, so the socket is only held until
+                // the client closes the control connection.
+                udp::run_while_connected(&mut stream, future::pending::<()>()).await;
+                drop(association);
+                self.audit.record(audit.ended(0, 0)).await;
+            }
+
+            _ => {
+                self.reply(
+                    &mut stream,
+                    Rep::CommandNotSupported,
+                    AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Serve an HTTP `CONNECT` request, see [`Socks5::allow_http_connect`].
+    async fn handle_http(
+        &self,
+        mut stream: TcpStream,
+        client: SocketAddr,
+    ) -> Result<(), SocksError> {
+        let req = match http::read_request(&mut stream).await {
+            Ok(req) => req,
+            Err(e) => {
+                let _ = http::send_response(&mut stream, 400).await;
+                return Err(e);
+            }
+        };
+        trace::debug!(method = %req.method, target = %req.target, "HTTP request received");
+
+        if req.method != "CONNECT" {
+            http::send_response(&mut stream, 405).await?;
+            return Err(SocksError::InvalidHttpRequest);
+        }
+
+        // Credentials only name the user when they were checked.
+        let auth = match &req.credentials {
+            Some((uname, passwd)) if self.userpass_validator.is_some() => {
+                self.check_userpass(uname, passwd, self.limiter_peer(client))
+                    .await
+            }
+            _ if self.admits_no_auth() => Ok(AuthContext::no_auth()),
+            _ => {
+                self.metrics.record_auth_failure();
+                Err(SocksError::AuthFailed("invalid proxy credentials".into()))
+            }
+        };
+        let auth = match auth {
+            Ok(auth) => auth,
+            Err(e) => {
+                http::send_response(&mut stream, 407).await?;
+                return Err(e);
+            }
+        };
+
+        let dst = match req.dst() {
+            Ok(dst) => dst,
+            Err(e) => {
+                http::send_response(&mut stream, 400).await?;
+                return Err(e);
+            }
+        };
+
+        let (mut target, _) = match self.connect_as(&auth, &dst).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                let rep = e.to_rep().unwrap_or(Rep::GeneralFailure);
+                http::send_response(&mut stream, http::status_for_rep(rep)).await?;
+                return Err(e);
+            }
+        };
+
+        http::send_response(&mut stream, 200).await?;
+        self.relay_connection(&mut stream, &mut target, client, &auth, dst)
+            .await
+    }
+
+    /// Serve a SOCKS4 request, see [`Socks5::allow_socks4`].
+    async fn handle_socks4(
+        &self,
+        mut stream: TcpStream,
+        client: SocketAddr,
+    ) -> Result<(), SocksError> {
+        let unspecified = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+        let req = match socks4::Request::read_from(&mut stream).await {
+            Ok(req) => req,
+            Err(e) => {
+                let _ =
+                    socks4::send_reply(&mut stream, socks4::Reply::Rejected, &unspecified).await;
+                return Err(e);
+            }
+        };
+        trace::debug!(command = ?req.command, dst = %req.dst, "SOCKS4 request received");
+
+        if !self.admits_no_auth() {
+            self.metrics.record_auth_failure();
+            socks4::send_reply(&mut stream, socks4::Reply::Rejected, &unspecified).await?;
+            return Err(SocksError::AuthFailed("SOCKS4 requires NO AUTH".into()));
+        }
+        let cmd = match req.command {
+            socks4::Command::Connect => CMD::Connect,
+            socks4::Command::Bind => CMD::Bind,
+        };
+        if cmd != CMD::Connect || !self.commands.contains(&cmd) {
+            socks4::send_reply(&mut stream, socks4::Reply::Rejected, &unspecified).await?;
+            return Err(SocksError::UnsupportedCommand(cmd as u8));
+        }
+
+        let auth = AuthContext::no_auth();
+        let (mut target, bnd) = match self.connect_as(&auth, &req.dst).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                socks4::send_reply(&mut stream, socks4::Reply::Rejected, &unspecified).await?;
+                return Err(e);
+            }
+        };
+
+        let bnd = self.advertised_bind_addr.clone().unwrap_or(bnd);
+        socks4::send_reply(&mut stream, socks4::Reply::Granted, &bnd).await?;
+        self.relay_connection(&mut stream, &mut target, client, &auth, req.dst)
+            .await
+    }
+
+    /// Relay between a client and its connected target until both are done.
+    async fn relay_connection<S>(
+        &self,
+        stream: &mut S,
+        target: &mut TcpStream,
+        client: SocketAddr,
+        auth: &AuthContext,
+        dst: AddrPort,
+    ) -> Result<(), SocksError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        trace::info!(dst = %dst, "relay started");
+        let audit = AuditEvent::started(client, auth.username.clone(), dst.clone(), CMD::Connect);
+        self.audit.record(audit.clone()).await;
+        self.emit(Event::RelayStarted {
+            client,
+            dst: dst.clone(),
+        });
+
+        let user_options;
+        let options = match self.user_rate_limit.as_ref().and_then(|limit| limit(auth)) {
+            Some(bytes_per_sec) => {
+                user_options = RelayOptions {
+                    rate_limit: (bytes_per_sec > 0).then_some(bytes_per_sec),
+                    ..self.relay.clone()
+                };
+                &user_options
+            }
+            None => &self.relay,
+        };
+
+        let registration = self.registry.register(client, dst.clone());
+        let stats = relay::pump(
+            stream,
+            target,
+            registration.traffic(),
+            &self.metrics,
+            options,
+            &self.buffers,
+        )
+        .await;
+        let (client_to_target, target_to_client) = registration.traffic().totals();
+        self.audit
+            .record(audit.ended(client_to_target, target_to_client))
+            .await;
+        let stats = stats?;
+        self.metrics.record_close(stats.reason);
+        trace::info!(
+            client_to_target = stats.client_to_target,
+            target_to_client = stats.target_to_client,
+            reason = ?stats.reason,
+            "relay closed"
+        );
+        self.emit(Event::RelayClosed { client, dst, stats });
+
+        match stats.reason {
+            relay::CloseReason::IdleTimeout => Err(SocksError::IdleTimeout),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the connection request, answering malformed ones before failing.
+    ///
+    /// A request that cannot be parsed gets the reply matching the error,
+    /// e.g. [`Rep::AddressTypeNotSupported`] for an unknown `ATYP`, so the
+    /// client is not left waiting. So does a request refused by the
+    /// inspector set with [`Socks5::set_request_inspector`].
+    pub(crate) async fn read_request<S>(
+        &self,
+        stream: &mut S,
+        auth: &AuthContext,
+    ) -> Result<ConnRequest, SocksError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let req = ConnRequest::read_from(stream).await.and_then(|req| {
+            if self.strict {
+                req.validate_reserved()?;
+            }
+            if !self.commands.contains(&req.cmd) {
+                return Err(SocksError::UnsupportedCommand(req.cmd as u8));
+            }
+            if let Some(inspector) = &self.request_inspector {
+                inspector(auth, &req).map_err(SocksError::RequestRejected)?;
+            }
+            Ok(req)
+        });
+        match req {
+            Ok(req) => Ok(req),
+            Err(e) => {
+                // The client may already be gone, in which case there is no
+                // one left to answer.
+                let _ = self.reply_error(stream, &e).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Like [`Socks5::send_error_reply`], counting the reply in the metrics.
+    async fn reply_error<S>(&self, stream: &mut S, err: &SocksError) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let rep = err.to_rep().unwrap_or(Rep::GeneralFailure);
+        self.reply(stream, rep, AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0))
+            .await
+    }
+
+    /// Send a connection reply and count it in the metrics.
+    async fn reply<S>(&self, stream: &mut S, rep: Rep, bnd: AddrPort) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        self.metrics.record_reply(rep);
+        let mut reply = ConnReply::new(SOCKS_VERSION, rep, 0x00, bnd.atyp(), bnd);
+        reply.ver = self.reply_overrides.ver.unwrap_or(reply.ver);
+        reply.rsv = self.reply_overrides.rsv.unwrap_or(reply.rsv);
+        stream.write_all(&reply.to_bytes()?).await?;
+        Ok(())
+    }
+
+    /// Send the method selection, applying the reply overrides.
+    async fn select_method<S>(&self, stream: &mut S, method: Method) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let mut sel = MethodSelection::new(method);
+        sel.ver = self.reply_overrides.ver.unwrap_or(sel.ver);
+        stream.write_all(&sel.to_bytes()).await?;
+        Ok(())
+    }
+
+    /// Like [`Socks5::send_auth_reply`], for any stream.
+    async fn write_auth_reply<S>(stream: &mut S, status: AuthStatus) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        stream.write_all(&AuthReply::new(status).to_bytes()).await?;
+        Ok(())
+    }
+
+    /// Answers `NO ACCEPTABLE METHODS` and closes the write half of `stream`.
+    async fn refuse_methods<S>(&self, stream: &mut S) -> Result<(), SocksError>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        self.metrics.record_auth_failure();
+        self.select_method(stream, Method::Fixed(FixedMethod::NoAcceptable))
+            .await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Run the accept loop forever, serving each client on its own task.
+    ///
+    /// Failures that only affect one pending connection are logged and
+    /// skipped, and running out of file descriptors pauses accepting
+    /// briefly instead of stopping the server. Returns only if the listener
+    /// itself fails.
+    pub async fn run(self) -> Result<(), SocksError> {
+        self.run_with_shutdown(future::pending::<()>()).await?;
+        Ok(())
+    }
+
+    /// Run `n` accept loops on the listener, serving each client on its own
+    /// task.
+    ///
+    /// The loops are spawned as tasks, so on a multi-threaded runtime they
+    /// accept on several worker threads at once, which helps when a single
+    /// loop cannot keep up with new connections. They share the listener
+    /// and every setting, including the connection limit. `n` is raised to
+    /// 1 if it is 0.
+    ///
+    /// All loops still take connections from one socket. To spread load
+    /// over separate sockets instead, e.g. one per process, bind each with
+    /// `SO_REUSEPORT` through [`Socks5::bind_with`].
+    ///
+    /// Returns when a loop fails, like [`Socks5::run`], stopping the others.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("0.0.0.0:1080").await?;
+    /// server.allow_no_auth();
+    /// let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    /// server.run_workers(threads).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_workers(self, n: usize) -> Result<(), SocksError> {
+        let server = Arc::new(self);
+        let mut workers = JoinSet::new();
+        for _ in 0..n.max(1) {
+            workers.spawn(Arc::clone(&server).accept_loop());
+        }
+
+        // Dropping the set on return aborts the remaining loops.
+        match workers.join_next().await {
+            Some(Ok(result)) => result,
+            Some(Err(e)) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Some(Err(_)) | None => Ok(()),
+        }
+    }
+
+    /// Accepts clients and serves each on its own task, until the listener
+    /// fails.
+    async fn accept_loop(self: Arc<Self>) -> Result<(), SocksError> {
+        loop {
+            let (stream, _, permit) = self.accept_admitted().await?;
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                let _permit = permit;
+                let _ = server.handle_client(stream).await;
+            });
+        }
+    }
+
+    /// Run the accept loop forever, delegating parsed requests to `handler`.
+    ///
+    /// Each client is served on its own task: the server negotiates
+    /// authentication and reads the connection request, then passes the
+    /// stream, the client's address, the request and the authenticated
+    /// identity to [`ConnectionHandler::handle`], which is responsible for
+    /// the reply. Requests for commands that are not enabled with
+    /// [`Socks5::enable_command`] are answered by the server and never
+    /// reach the handler.
+    /// Use [`handler::RelayHandler`] for a plain TCP relay.
+    ///
+    /// Returns only if the listener itself fails, see [`Socks5::run`].
+    pub async fn serve<H>(self, handler: H) -> Result<(), SocksError>
+    where
+        H: ConnectionHandler + 'static,
+    {
+        let server = Arc::new(self);
+        let handler = Arc::new(handler);
+
+        loop {
+            let (mut stream, client, permit) = server.accept_admitted().await?;
+            let server = Arc::clone(&server);
+            let handler = Arc::clone(&handler);
+
+            tokio::spawn(trace::in_client_span(client, async move {
+                let _permit = permit;
+                let _active = server.metrics.connection();
+                server.emit(Event::Connected { client });
+
+                Socks5::peek_version(&stream).await?;
+                let auth = server.authenticate_from(&mut stream, client).await?;
+                let req = server.read_request(&mut stream, &auth).await?;
+                trace::debug!(cmd = %req.cmd, dst = %req.dst, "request received");
+
+                handler.handle(stream, client, req, auth).await
+            }));
+        }
+    }
+
+    /// Run the accept loop until `signal` resolves, then drain connections.
+    ///
+    /// Once the signal fires no new connections are accepted. Connections
+    /// already being served get up to the grace period set with
+    /// [`Socks5::set_shutdown_grace_period`] to finish, after which the
+    /// remaining ones are aborted.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use simple_socks5::Socks5;
+    ///
+    /// # async fn run() -> Result<(), simple_socks5::error::SocksError> {
+    /// let mut server = Socks5::bind("127.0.0.1:1080").await?;
+    /// server.allow_no_auth();
+    ///
+    /// let summary = server
+    ///     .run_with_shutdown(async {
+    ///         let _ = tokio::signal::ctrl_c().await;
+    ///     })
+    ///     .await?;
+    /// println!("drained {}, aborted {}", summary.drained, summary.aborted);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_with_shutdown<F>(self, signal: F) -> Result<ShutdownSummary, SocksError>
+    where
+        F: Future,
+    {
+        let server = Arc::new(self);
+        let mut tasks = JoinSet::new();
+        tokio::pin!(signal);
+
+        loop {
+            tokio::select! {
+                _ = &mut signal => break,
+                accepted = server.accept_admitted() => {
+                    let (stream, _, permit) = accepted?;
+                    let server = Arc::clone(&server);
+                    tasks.spawn(async move {
+                        let _permit = permit;
+                        let _ = server.handle_client(stream).await;
+                    });
+                }
+                Some(_) = tasks.join_next(), if !tasks.is_empty() => {}
+            }
+        }
+
+        let mut summary = ShutdownSummary::default();
+        let deadline = tokio::time::sleep(server.shutdown_grace_period);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                joined = tasks.join_next() => match joined {
+                    Some(_) => summary.drained += 1,
+                    None => break,
+                },
+                _ = &mut deadline => break,
+            }
+        }
+
+        summary.aborted = tasks.len();
+        tasks.shutdown().await;
+
+        Ok(summary)
     }
 }
@@ -0,0 +1,214 @@
+//! Server-wide connection and traffic counters.
+//!
+//! Counters are updated with relaxed atomics by the connection handler and
+//! the relay pump, and read through [`Socks5::metrics`](crate::Socks5::metrics)
+//! as a [`MetricsSnapshot`], or rendered for Prometheus through
+//! [`Socks5::metrics_prometheus`](crate::Socks5::metrics_prometheus).
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::conn::reply::Rep;
+use crate::relay::{CloseReason, Direction};
+
+/// Every reply code, in wire order, with its Prometheus label value.
+const REPS: [(Rep, &str); 9] = [
+    (Rep::Succeeded, "succeeded"),
+    (Rep::GeneralFailure, "general_failure"),
+    (Rep::ConnectionNotAllowed, "connection_not_allowed"),
+    (Rep::NetworkUnreachable, "network_unreachable"),
+    (Rep::HostUnreachable, "host_unreachable"),
+    (Rep::ConnectionRefused, "connection_refused"),
+    (Rep::TTLExpired, "ttl_expired"),
+    (Rep::CommandNotSupported, "command_not_supported"),
+    (Rep::AddressTypeNotSupported, "address_type_not_supported"),
+];
+
+/// Every close reason with its Prometheus labels.
+const CLOSE_REASONS: [(CloseReason, &str); 6] = [
+    (CloseReason::ClientClosed, r#"reason="client_closed""#),
+    (CloseReason::TargetClosed, r#"reason="target_closed""#),
+    (
+        CloseReason::QuotaExceeded(Direction::ClientToTarget),
+        r#"reason="quota_exceeded",direction="client_to_target""#,
+    ),
+    (
+        CloseReason::QuotaExceeded(Direction::TargetToClient),
+        r#"reason="quota_exceeded",direction="target_to_client""#,
+    ),
+    (CloseReason::IdleDrained, r#"reason="idle_drained""#),
+    (CloseReason::IdleTimeout, r#"reason="idle_timeout""#),
+];
+
+/// A point-in-time copy of the server's counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Connections currently being handled.
+    pub active_connections: u64,
+    /// Connections handled since the server started.
+    pub total_connections: u64,
+    /// Bytes relayed from clients to their targets.
+    pub bytes_client_to_target: u64,
+    /// Bytes relayed from targets back to their clients.
+    pub bytes_target_to_client: u64,
+    /// Failed authentication attempts.
+    pub auth_failures: u64,
+    /// Events dropped because the [`Socks5::events`](crate::Socks5::events)
+    /// channel was full.
+    pub events_dropped: u64,
+}
+
+/// Atomic counters shared by all connections of a server.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    active_connections: AtomicU64,
+    total_connections: AtomicU64,
+    bytes_client_to_target: AtomicU64,
+    bytes_target_to_client: AtomicU64,
+    auth_failures: AtomicU64,
+    events_dropped: AtomicU64,
+    replies: [AtomicU64; REPS.len()],
+    closes: [AtomicU64; CLOSE_REASONS.len()],
+}
+
+impl Metrics {
+    /// Counts a new connection as active until the returned guard is dropped.
+    pub(crate) fn connection(&self) -> ActiveConnection<'_> {
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ActiveConnection { metrics: self }
+    }
+
+    pub(crate) fn record_bytes(&self, dir: Direction, bytes: usize) {
+        let counter = match dir {
+            Direction::ClientToTarget => &self.bytes_client_to_target,
+            Direction::TargetToClient => &self.bytes_target_to_client,
+        };
+        counter.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_auth_failure(&self) {
+        self.auth_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_event_dropped(&self) {
+        self.events_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_reply(&self, rep: Rep) {
+        self.replies[rep as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_close(&self, reason: CloseReason) {
+        let idx = CLOSE_REASONS
+            .iter()
+            .position(|(r, _)| *r == reason)
+            .unwrap();
+        self.closes[idx].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            bytes_client_to_target: self.bytes_client_to_target.load(Ordering::Relaxed),
+            bytes_target_to_client: self.bytes_target_to_client.load(Ordering::Relaxed),
+            auth_failures: self.auth_failures.load(Ordering::Relaxed),
+            events_dropped: self.events_dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn render_prometheus(&self) -> String {
+        let snap = self.snapshot();
+        let mut out = String::new();
+
+        let mut family = |name: &str, kind: &str, help: &str, samples: &[(&str, u64)]| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{name}{labels} {value}");
+            }
+        };
+
+        family(
+            "socks5_active_connections",
+            "gauge",
+            "Connections currently being handled.",
+            &[("", snap.active_connections)],
+        );
+        family(
+            "socks5_connections_total",
+            "counter",
+            "Connections handled since the server started.",
+            &[("", snap.total_connections)],
+        );
+        family(
+            "socks5_relayed_bytes_total",
+            "counter",
+            "Bytes relayed between clients and targets.",
+            &[
+                (
+                    "{direction=\"client_to_target\"}",
+                    snap.bytes_client_to_target,
+                ),
+                (
+                    "{direction=\"target_to_client\"}",
+                    snap.bytes_target_to_client,
+                ),
+            ],
+        );
+        family(
+            "socks5_auth_failures_total",
+            "counter",
+            "Failed authentication attempts.",
+            &[("", snap.auth_failures)],
+        );
+        family(
+            "socks5_events_dropped_total",
+            "counter",
+            "Lifecycle events dropped because the event channel was full.",
+            &[("", snap.events_dropped)],
+        );
+
+        let replies: Vec<(String, u64)> = REPS
+            .iter()
+            .zip(&self.replies)
+            .map(|((_, label), n)| (format!("{{rep=\"{label}\"}}"), n.load(Ordering::Relaxed)))
+            .collect();
+        let replies: Vec<(&str, u64)> = replies.iter().map(|(l, n)| (l.as_str(), *n)).collect();
+        family(
+            "socks5_replies_total",
+            "counter",
+            "Connection replies sent, by reply code.",
+            &replies,
+        );
+
+        let closes: Vec<(String, u64)> = CLOSE_REASONS
+            .iter()
+            .zip(&self.closes)
+            .map(|((_, label), n)| (format!("{{{label}}}"), n.load(Ordering::Relaxed)))
+            .collect();
+        let closes: Vec<(&str, u64)> = closes.iter().map(|(l, n)| (l.as_str(), *n)).collect();
+        family(
+            "socks5_relays_closed_total",
+            "counter",
+            "Relays that ended, by the side that closed first or the quota reached.",
+            &closes,
+        );
+
+        out
+    }
+}
+
+/// Keeps a connection counted as active while it is alive.
+pub(crate) struct ActiveConnection<'a> {
+    metrics: &'a Metrics,
+}
+
+impl Drop for ActiveConnection<'_> {
+    fn drop(&mut self) {
+        self.metrics
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -10,7 +10,9 @@
 //! These are defined in [RFC 1928, section 3](https://www.rfc-editor.org/rfc/rfc1928#section-3).
 
 use super::method::*;
+use crate::SOCKS_VERSION;
 use crate::error::SocksError;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// Client's version/methods message.
 ///
@@ -50,7 +52,105 @@ impl VersionMessage {
     /// assert_eq!(msg.ver, 0x05);
     /// ```
     pub fn new(methods: Vec<Method>) -> Self {
-        Self { ver: 0x05, methods }
+        Self {
+            ver: SOCKS_VERSION,
+            methods,
+        }
+    }
+
+    /// Serializes this [`VersionMessage`] into its wire format.
+    ///
+    /// Methods are sorted by their byte value and deduplicated before
+    /// writing, and `NMETHODS` is derived from the resulting list.
+    ///
+    /// Returns an error if:
+    /// - the method list is empty
+    /// - more than 255 distinct methods are listed
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::msg::message::VersionMessage;
+    /// use simple_socks5::msg::method::{FixedMethod, Method};
+    ///
+    /// let msg = VersionMessage::new(vec![
+    ///     Method::Fixed(FixedMethod::UsePass),
+    ///     Method::Fixed(FixedMethod::NoAuth),
+    ///     Method::Fixed(FixedMethod::UsePass),
+    /// ]);
+    /// let bytes = msg.to_bytes().unwrap();
+    /// assert_eq!(bytes, [0x05, 0x02, 0x00, 0x02]);
+    ///
+    /// let parsed = VersionMessage::try_from(&bytes[..]).unwrap();
+    /// assert_eq!(
+    ///     parsed.methods,
+    ///     [Method::Fixed(FixedMethod::NoAuth), Method::Fixed(FixedMethod::UsePass)]
+    /// );
+    /// ```
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SocksError> {
+        let mut methods: Vec<u8> = self.methods.iter().map(|m| m.to_u8()).collect();
+        methods.sort_unstable();
+        methods.dedup();
+
+        if methods.is_empty() {
+            return Err(SocksError::NoMethodsOffered);
+        }
+        if methods.len() > 255 {
+            return Err(SocksError::TooManyMethods(methods.len()));
+        }
+
+        let mut buf = Vec::with_capacity(2 + methods.len());
+        buf.push(self.ver);
+        buf.push(methods.len() as u8);
+        buf.extend_from_slice(&methods);
+        Ok(buf)
+    }
+
+    /// Reads exactly one version message from an async stream.
+    ///
+    /// Reads `VER` and `NMETHODS`, then the declared number of methods, so
+    /// bytes a client pipelines after the message, such as the connection
+    /// request, are left in the stream for the next read. A stream that
+    /// ends before the first byte fails with
+    /// [`SocksError::ClientDisconnected`]; one that ends later with the
+    /// same error as parsing the truncated bytes with [`TryFrom`].
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::msg::message::VersionMessage;
+    /// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let (mut client, mut server) = tokio::io::duplex(64);
+    ///
+    /// // Version message and the start of a request in a single write.
+    /// client.write_all(&[0x05, 0x01, 0x00, 0x05, 0x01]).await.unwrap();
+    ///
+    /// let msg = VersionMessage::read_from(&mut server).await.unwrap();
+    /// assert_eq!(msg.methods.len(), 1);
+    ///
+    /// let mut next = [0; 2];
+    /// server.read_exact(&mut next).await.unwrap();
+    /// assert_eq!(next, [0x05, 0x01]);
+    /// # }
+    /// ```
+    pub async fn read_from<R>(reader: &mut R) -> Result<Self, SocksError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut buf = Vec::with_capacity(2 + 255);
+        if (&mut *reader).take(2).read_to_end(&mut buf).await? == 0 {
+            return Err(SocksError::ClientDisconnected);
+        }
+
+        if let [SOCKS_VERSION, nmethods] = buf[..] {
+            (&mut *reader)
+                .take(nmethods as u64)
+                .read_to_end(&mut buf)
+                .await?;
+        }
+
+        Self::try_from(&buf[..])
     }
 }
 
@@ -62,18 +162,31 @@ impl TryFrom<&[u8]> for VersionMessage {
     /// Returns an error if:
     /// - the buffer is shorter than 2 bytes
     /// - the version is not `0x05`
+    /// - no methods are offered (`NMETHODS` is zero)
     /// - the buffer does not contain the declared number of methods
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::error::SocksError;
+    /// use simple_socks5::msg::message::VersionMessage;
+    ///
+    /// let err = VersionMessage::try_from(&[0x05, 0x00][..]).unwrap_err();
+    /// assert!(matches!(err, SocksError::NoMethodsOffered));
+    /// ```
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
         if bytes.len() < 2 {
             return Err(SocksError::VersionMessageTooShort);
         }
 
         let ver = bytes[0];
-        if ver != 0x05 {
+        if ver != SOCKS_VERSION {
             return Err(SocksError::UnsupportedVersion(ver));
         }
 
         let nmethods = bytes[1] as usize;
+        if nmethods == 0 {
+            return Err(SocksError::NoMethodsOffered);
+        }
         if bytes.len() < 2 + nmethods {
             return Err(SocksError::IncompleteVersionMessage);
         }
@@ -125,7 +238,10 @@ impl MethodSelection {
     /// assert_eq!(sel.to_bytes(), [0x05, 0x00]);
     /// ```
     pub fn new(method: Method) -> Self {
-        Self { ver: 0x05, method }
+        Self {
+            ver: SOCKS_VERSION,
+            method,
+        }
     }
 
     /// Serializes this [`MethodSelection`] into a 2-byte array.
@@ -148,7 +264,7 @@ impl TryFrom<&[u8]> for MethodSelection {
         }
 
         let ver = bytes[0];
-        if ver != 0x05 {
+        if ver != SOCKS_VERSION {
             return Err(SocksError::UnsupportedVersion(ver));
         }
 
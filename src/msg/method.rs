@@ -23,6 +23,7 @@ use crate::error::SocksError;
 /// ```
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FixedMethod {
     /// No authentication required (`0x00`).
     NoAuth = 0x00,
@@ -41,6 +42,7 @@ pub enum FixedMethod {
 /// - IANA-assigned methods (`0x03`–`0x7F`)
 /// - Private methods (`0x80`–`0xFE`)
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Method {
     /// A fixed, reserved method defined in the specification.
     Fixed(FixedMethod),
@@ -72,6 +74,52 @@ impl FixedMethod {
 }
 
 impl Method {
+    /// `NO AUTHENTICATION REQUIRED` (`0x00`).
+    pub const fn no_auth() -> Self {
+        Method::Fixed(FixedMethod::NoAuth)
+    }
+
+    /// `USERNAME/PASSWORD` (`0x02`).
+    pub const fn userpass() -> Self {
+        Method::Fixed(FixedMethod::UsePass)
+    }
+
+    /// `GSSAPI` (`0x01`).
+    pub const fn gssapi() -> Self {
+        Method::Fixed(FixedMethod::GssApi)
+    }
+
+    /// Returns `true` for a [`Method::Fixed`] method.
+    pub fn is_fixed(self) -> bool {
+        matches!(self, Method::Fixed(_))
+    }
+
+    /// Returns `true` for a [`Method::IanaAssigned`] method.
+    pub fn is_iana(self) -> bool {
+        matches!(self, Method::IanaAssigned(_))
+    }
+
+    /// Returns `true` for a [`Method::Private`] method.
+    pub fn is_private(self) -> bool {
+        matches!(self, Method::Private(_))
+    }
+
+    /// Returns the [`FixedMethod`] of a fixed method.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::msg::method::{FixedMethod, Method};
+    ///
+    /// assert_eq!(Method::userpass().as_fixed(), Some(FixedMethod::UsePass));
+    /// assert_eq!(Method::Private(0x80).as_fixed(), None);
+    /// ```
+    pub fn as_fixed(self) -> Option<FixedMethod> {
+        match self {
+            Method::Fixed(f) => Some(f),
+            _ => None,
+        }
+    }
+
     /// Converts a [`Method`] into its byte representation.
     pub fn to_u8(self) -> u8 {
         match self {
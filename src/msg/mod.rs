@@ -1,3 +1,2 @@
 pub mod message;
 pub mod method;
-
@@ -19,7 +19,18 @@
 //! ```
 
 use std::fmt;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::{TcpStream, lookup_host};
+use tokio::time;
+
+use crate::ATYP;
+use crate::connector::{self, HAPPY_EYEBALLS_DELAY};
+use crate::error::SocksError;
+use crate::resolver::AddressPreference;
 
 /// Represents a destination address and port.
 ///
@@ -27,7 +38,40 @@ use std::net::{Ipv4Addr, Ipv6Addr};
 /// - An IPv4 address (`ATYP = 0x01`).
 /// - An IPv6 address (`ATYP = 0x04`).
 /// - A domain name (`ATYP = 0x03`), which is represented here as [`AddrPort::Domain`].
+///
+/// With the `serde` feature, an address is (de)serialized in a tagged form
+/// such as `{"type": "domain", "host": "example.com", "port": 443}`, and
+/// domain names are checked as in [`AddrPort::domain`].
+///
+/// # Text form
+///
+/// [`Display`](fmt::Display) writes `host:port`, with IPv6 addresses in
+/// brackets. A domain name is written in double quotes, with `"` and `\`
+/// escaped by a backslash, whenever it would otherwise read back as
+/// something else: when it is empty, parses as an IPv4 address, or contains
+/// `:`, `[`, `]`, `"`, `\` or whitespace. [`FromStr`] accepts that form, so
+/// every address whose domain name fits in 255 bytes reads back unchanged:
+///
+/// ```
+/// use simple_socks5::parse::AddrPort;
+///
+/// for addr in [
+///     AddrPort::V4("1.2.3.4".parse().unwrap(), 80),
+///     AddrPort::V6("2001:db8::1".parse().unwrap(), 443),
+///     AddrPort::Domain("example.com".into(), 8080),
+///     AddrPort::Domain("1.2.3.4".into(), 80),
+///     AddrPort::Domain("odd: \"name\"".into(), 80),
+/// ] {
+///     assert_eq!(addr.to_string().parse::<AddrPort>().unwrap(), addr);
+/// }
+/// assert_eq!(AddrPort::Domain("1.2.3.4".into(), 80).to_string(), "\"1.2.3.4\":80");
+/// ```
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "AddrPortRepr", try_from = "AddrPortRepr")
+)]
 pub enum AddrPort {
     /// An IPv4 address and port.
     V4(Ipv4Addr, u16),
@@ -36,16 +80,406 @@ pub enum AddrPort {
     V6(Ipv6Addr, u16),
 
     /// A domain name and port.
+    ///
+    /// The name must be at most 255 bytes long to be encodable; build it
+    /// with [`AddrPort::domain`] to have that checked.
     Domain(String, u16),
 }
 
+/// The serialized form of [`AddrPort`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum AddrPortRepr {
+    V4 { ip: Ipv4Addr, port: u16 },
+    V6 { ip: Ipv6Addr, port: u16 },
+    Domain { host: String, port: u16 },
+}
+
+#[cfg(feature = "serde")]
+impl From<AddrPort> for AddrPortRepr {
+    fn from(addr: AddrPort) -> Self {
+        match addr {
+            AddrPort::V4(ip, port) => AddrPortRepr::V4 { ip, port },
+            AddrPort::V6(ip, port) => AddrPortRepr::V6 { ip, port },
+            AddrPort::Domain(host, port) => AddrPortRepr::Domain { host, port },
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<AddrPortRepr> for AddrPort {
+    type Error = SocksError;
+
+    fn try_from(repr: AddrPortRepr) -> Result<Self, Self::Error> {
+        match repr {
+            AddrPortRepr::V4 { ip, port } => Ok(AddrPort::V4(ip, port)),
+            AddrPortRepr::V6 { ip, port } => Ok(AddrPort::V6(ip, port)),
+            AddrPortRepr::Domain { host, port } => AddrPort::domain(host, port),
+        }
+    }
+}
+
 impl fmt::Display for AddrPort {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AddrPort::V4(ip, port) => write!(f, "{}:{}", ip, port),
             AddrPort::V6(ip, port) => write!(f, "[{}]:{}", ip, port),
-            AddrPort::Domain(domain, port) => write!(f, "{}:{}", domain, port),
+            AddrPort::Domain(domain, port) if is_plain_host(domain) => {
+                write!(f, "{}:{}", domain, port)
+            }
+            AddrPort::Domain(domain, port) => {
+                f.write_str("\"")?;
+                for c in domain.chars() {
+                    if matches!(c, '"' | '\\') {
+                        f.write_str("\\")?;
+                    }
+                    write!(f, "{c}")?;
+                }
+                write!(f, "\":{}", port)
+            }
+        }
+    }
+}
+
+/// Returns `true` if `host` reads back as the same domain name without
+/// quotes.
+fn is_plain_host(host: &str) -> bool {
+    !host.is_empty()
+        && host.parse::<Ipv4Addr>().is_err()
+        && !host.contains(|c: char| matches!(c, ':' | '[' | ']' | '"' | '\\') || c.is_whitespace())
+}
+
+/// Undoes the escaping of a quoted domain name, or returns `None` if an
+/// escape or quote is out of place.
+fn unquote(quoted: &str) -> Option<String> {
+    let mut name = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                c @ ('"' | '\\') => name.push(c),
+                _ => return None,
+            },
+            '"' => return None,
+            c => name.push(c),
+        }
+    }
+    Some(name)
+}
+
+impl FromStr for AddrPort {
+    type Err = SocksError;
+
+    /// Parses `host:port`, inferring the variant from the host: an IPv4
+    /// address, an IPv6 address in brackets, or else a domain name, quoted
+    /// as described under [Text form](AddrPort#text-form) if needed.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::InvalidAddress`] if the port is missing or invalid,
+    ///   the host is empty, it is an IPv6 address without brackets, or an
+    ///   unquoted domain name contains characters that need quoting.
+    /// - [`SocksError::InvalidDomain`] if the domain name is longer than 255
+    ///   bytes.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// let v4: AddrPort = "1.2.3.4:80".parse().unwrap();
+    /// assert_eq!(v4, AddrPort::V4("1.2.3.4".parse().unwrap(), 80));
+    ///
+    /// let v6: AddrPort = "[::1]:80".parse().unwrap();
+    /// assert_eq!(v6, AddrPort::V6("::1".parse().unwrap(), 80));
+    ///
+    /// let domain: AddrPort = "example.com:443".parse().unwrap();
+    /// assert_eq!(domain, AddrPort::Domain("example.com".into(), 443));
+    ///
+    /// let quoted: AddrPort = r#""my host:1":443"#.parse().unwrap();
+    /// assert_eq!(quoted, AddrPort::Domain("my host:1".into(), 443));
+    ///
+    /// assert!("example.com".parse::<AddrPort>().is_err());
+    /// assert!("::1:80".parse::<AddrPort>().is_err());
+    /// assert!(":80".parse::<AddrPort>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SocksError::InvalidAddress(s.into());
+
+        let (host, port) = s.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse().map_err(|_| invalid())?;
+
+        if let Some(v6) = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            let ip = v6.parse::<Ipv6Addr>().map_err(|_| invalid())?;
+            return Ok(AddrPort::V6(ip, port));
+        }
+        if let Some(quoted) = host.strip_prefix('"').and_then(|h| h.strip_suffix('"')) {
+            let name = unquote(quoted).ok_or_else(invalid)?;
+            return AddrPort::domain(name, port);
+        }
+        if let Ok(ip) = host.parse::<Ipv4Addr>() {
+            return Ok(AddrPort::V4(ip, port));
+        }
+        if !is_plain_host(host) {
+            return Err(invalid());
+        }
+        AddrPort::domain(host, port)
+    }
+}
+
+impl From<SocketAddr> for AddrPort {
+    fn from(addr: SocketAddr) -> Self {
+        match addr.ip() {
+            IpAddr::V4(ip) => AddrPort::V4(ip, addr.port()),
+            IpAddr::V6(ip) => AddrPort::V6(ip, addr.port()),
+        }
+    }
+}
+
+impl From<&AddrPort> for ATYP {
+    fn from(addr: &AddrPort) -> Self {
+        addr.atyp()
+    }
+}
+
+impl AddrPort {
+    /// Returns the address type that encodes this address on the wire.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::ATYP;
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// let dst: AddrPort = "[::1]:443".parse().unwrap();
+    /// assert_eq!(dst.atyp(), ATYP::V6);
+    /// assert_eq!(ATYP::from(&AddrPort::Domain("example.com".into(), 80)), ATYP::DomainName);
+    /// ```
+    pub fn atyp(&self) -> ATYP {
+        match self {
+            AddrPort::V4(_, _) => ATYP::V4,
+            AddrPort::V6(_, _) => ATYP::V6,
+            AddrPort::Domain(_, _) => ATYP::DomainName,
+        }
+    }
+
+    /// Creates an [`AddrPort::Domain`], checking that the name fits the
+    /// one-byte length field of the wire format.
+    ///
+    /// Prefer this over building the variant directly: a longer name cannot
+    /// be serialized, so the error would only surface when the address is
+    /// sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SocksError::InvalidDomain`] if `name` is longer than 255
+    /// bytes.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// let dst = AddrPort::domain("example.com", 443).unwrap();
+    /// assert_eq!(dst.to_string(), "example.com:443");
+    ///
+    /// assert!(AddrPort::domain("x".repeat(256), 443).is_err());
+    /// ```
+    pub fn domain(name: impl Into<String>, port: u16) -> Result<Self, SocksError> {
+        let name = name.into();
+        if name.len() > u8::MAX as usize {
+            return Err(SocksError::InvalidDomain(name));
+        }
+        Ok(AddrPort::Domain(name, port))
+    }
+
+    /// Appends the wire form of the address, `ADDR` followed by `PORT`, to
+    /// `buf`, with a length byte before a domain name.
+    ///
+    /// # Errors
+    ///
+    /// [`SocksError::InvalidDomain`] if the domain name is longer than 255
+    /// bytes.
+    pub(crate) fn encode_into(&self, buf: &mut Vec<u8>) -> Result<(), SocksError> {
+        match self {
+            AddrPort::V4(addr, port) => {
+                buf.extend_from_slice(&addr.octets());
+                buf.extend_from_slice(&port.to_be_bytes());
+            }
+            AddrPort::V6(addr, port) => {
+                buf.extend_from_slice(&addr.octets());
+                buf.extend_from_slice(&port.to_be_bytes());
+            }
+            AddrPort::Domain(name, port) => {
+                let len = u8::try_from(name.len())
+                    .map_err(|_| SocksError::InvalidDomain(name.clone()))?;
+                buf.push(len);
+                buf.extend_from_slice(name.as_bytes());
+                buf.extend_from_slice(&port.to_be_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the address points into a private or local range.
+    ///
+    /// Covers RFC 1918 private, loopback, link-local and unspecified IPv4
+    /// addresses, and loopback, unique local (`fc00::/7`), link-local and
+    /// unspecified IPv6 addresses. IPv4-mapped IPv6 addresses are checked as
+    /// their IPv4 counterpart.
+    ///
+    /// Domains are never considered private here, since that can only be
+    /// decided after DNS resolution.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// assert!(AddrPort::V4("192.168.1.10".parse().unwrap(), 80).is_private());
+    /// assert!(AddrPort::V6("fd00::1".parse().unwrap(), 80).is_private());
+    /// assert!(!AddrPort::V4("1.1.1.1".parse().unwrap(), 80).is_private());
+    /// ```
+    pub fn is_private(&self) -> bool {
+        match self {
+            AddrPort::V4(ip, _) => is_private_ip(&IpAddr::V4(*ip)),
+            AddrPort::V6(ip, _) => is_private_ip(&IpAddr::V6(*ip)),
+            AddrPort::Domain(_, _) => false,
+        }
+    }
+
+    /// Returns `true` if this is an IPv4-mapped IPv6 address (`::ffff:a.b.c.d`).
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// assert!(AddrPort::V6("::ffff:1.2.3.4".parse().unwrap(), 80).is_ipv4_mapped());
+    /// assert!(!AddrPort::V6("::1".parse().unwrap(), 80).is_ipv4_mapped());
+    /// ```
+    pub fn is_ipv4_mapped(&self) -> bool {
+        matches!(self, AddrPort::V6(ip, _) if ip.to_ipv4_mapped().is_some())
+    }
+
+    /// Converts an IPv4-mapped IPv6 address back to [`AddrPort::V4`].
+    ///
+    /// Every other address is returned unchanged. Dialing the mapped form
+    /// fails on hosts where IPv6 sockets are not dual-stack, so the server
+    /// normalizes destinations before connecting.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// let mapped = AddrPort::V6("::ffff:1.2.3.4".parse().unwrap(), 80);
+    /// assert_eq!(mapped.normalized(), AddrPort::V4("1.2.3.4".parse().unwrap(), 80));
+    ///
+    /// let v6 = AddrPort::V6("2001:db8::1".parse().unwrap(), 80);
+    /// assert_eq!(v6.normalized(), v6);
+    /// ```
+    pub fn normalized(&self) -> AddrPort {
+        match self {
+            AddrPort::V6(ip, port) => match ip.to_ipv4_mapped() {
+                Some(v4) => AddrPort::V4(v4, *port),
+                None => self.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Connects to the address, racing IPv6 and IPv4 for domain names.
+    ///
+    /// A domain is resolved with the system resolver and its addresses
+    /// dialed Happy Eyeballs style (RFC 8305): IPv6 first, alternating
+    /// families, each attempt getting a [`HAPPY_EYEBALLS_DELAY`] head start
+    /// before the next begins. The first connection wins and the rest are
+    /// dropped; the winning address is the stream's `peer_addr`. IP
+    /// addresses are dialed directly.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::TimedOut`] if nothing connects within
+    /// `timeout`, with the resolver's error if the lookup fails, or with the
+    /// error of the last address tried if all of them fail.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use simple_socks5::parse::AddrPort;
+    ///
+    /// # async fn run() -> std::io::Result<()> {
+    /// let dst = AddrPort::domain("example.com", 443).unwrap();
+    /// let stream = dst.connect_happy_eyeballs(Duration::from_secs(10)).await?;
+    /// println!("connected to {}", stream.peer_addr()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_happy_eyeballs(&self, timeout: Duration) -> io::Result<TcpStream> {
+        let connect = async {
+            let addrs = match self {
+                AddrPort::V4(ip, port) => vec![SocketAddr::from((*ip, *port))],
+                AddrPort::V6(ip, port) => vec![SocketAddr::from((*ip, *port))],
+                AddrPort::Domain(host, port) => {
+                    let addrs = lookup_host((host.as_str(), *port)).await?.collect();
+                    AddressPreference::Ipv6First.sort(addrs)
+                }
+            };
+            let dial =
+                |addr| -> connector::ConnectFuture<'static> { Box::pin(TcpStream::connect(addr)) };
+            connector::race(addrs, HAPPY_EYEBALLS_DELAY, dial)
+                .await
+                .map_err(|errors| {
+                    errors.into_iter().last().unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no addresses resolved")
+                    })
+                })
+        };
+
+        time::timeout(timeout, connect)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))?
+    }
+}
+
+/// Returns `true` if `host` is an IP address, including the `inet_aton`
+/// shorthands (`127.1`, `0x7f.1`, `2130706433`) that `getaddrinfo` resolves
+/// to an address without a lookup.
+pub(crate) fn is_ip_literal(host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    if host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .is_ok()
+    {
+        return true;
+    }
+
+    let parts: Vec<&str> = host.split('.').collect();
+    parts.len() <= 4
+        && parts.iter().all(|part| {
+            let (digits, radix) = match part.strip_prefix("0x").or(part.strip_prefix("0X")) {
+                Some(hex) => (hex, 16),
+                None if part.len() > 1 && part.starts_with('0') => (&part[1..], 8),
+                None => (*part, 10),
+            };
+            !digits.is_empty() && u32::from_str_radix(digits, radix).is_ok()
+        })
+}
+
+/// Returns `true` if `ip` is in one of the ranges described by
+/// [`AddrPort::is_private`].
+pub(crate) fn is_private_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified()
         }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_private_ip(&IpAddr::V4(v4)),
+            None => {
+                v6.is_loopback()
+                    || v6.is_unique_local()
+                    || v6.is_unicast_link_local()
+                    || v6.is_unspecified()
+            }
+        },
     }
 }
 
@@ -66,7 +500,8 @@ impl Parse {
     ///
     /// Returns `Some((AddrPort, used_bytes))` on success, where `used_bytes` is the
     /// number of bytes consumed. Returns `None` if the buffer is too short or if
-    /// the `atyp` is unsupported (e.g., domain names are not handled here).
+    /// the `atyp` is unsupported (domain names are handled by
+    /// [`Parse::parse_domain`]).
     pub fn parse_ip_port(buf: &[u8], atyp: u8) -> Option<(AddrPort, usize)> {
         match atyp {
             0x01 => {
@@ -99,4 +534,113 @@ impl Parse {
             _ => None,
         }
     }
+
+    /// Parses a length-prefixed domain name and port from a byte slice.
+    ///
+    /// The layout is that of `ATYP = 0x03`: one length byte, that many bytes
+    /// of domain name, then a 2-byte port. Invalid UTF-8 in the name is
+    /// replaced rather than rejected.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Some((AddrPort::Domain, used_bytes))` on success, where
+    /// `used_bytes` is the number of bytes consumed, or `None` if the buffer
+    /// is too short.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::{AddrPort, Parse};
+    ///
+    /// let buf = [3, b'f', b'o', b'o', 0x00, 0x50, 0xAA];
+    /// let (addr, used) = Parse::parse_domain(&buf).unwrap();
+    /// assert_eq!(addr, AddrPort::Domain("foo".into(), 80));
+    /// assert_eq!(used, 6);
+    /// ```
+    pub fn parse_domain(buf: &[u8]) -> Option<(AddrPort, usize)> {
+        let len = *buf.first()? as usize;
+        let used = 1 + len + 2;
+        if buf.len() < used {
+            return None;
+        }
+
+        let domain = String::from_utf8_lossy(&buf[1..1 + len]).into_owned();
+        let port = u16::from_be_bytes([buf[1 + len], buf[2 + len]]);
+        Some((AddrPort::Domain(domain, port), used))
+    }
+
+    /// Reads an address and port of type `atyp` from an async stream.
+    ///
+    /// Reads exactly the bytes the address occupies: 6 for IPv4, 18 for
+    /// IPv6, and for domain names the length byte first, then the name and
+    /// port. Nothing past the port is consumed, so the bytes may arrive split
+    /// across arbitrary segment boundaries.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::ConnRequestTooShort`] if the stream ends early.
+    /// - [`SocksError::Io`] on other transport failures.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::ATYP;
+    /// use simple_socks5::parse::{AddrPort, Parse};
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let (mut client, mut server) = tokio::io::duplex(1);
+    ///
+    /// tokio::spawn(async move {
+    ///     let mut bytes = vec![11];
+    ///     bytes.extend_from_slice(b"example.com");
+    ///     bytes.extend_from_slice(&[0x01, 0xBB]);
+    ///     // Feed the address one byte at a time.
+    ///     for b in bytes {
+    ///         client.write_all(&[b]).await.unwrap();
+    ///     }
+    /// });
+    ///
+    /// let addr = Parse::read_addr_port(&mut server, ATYP::DomainName).await.unwrap();
+    /// assert_eq!(addr, AddrPort::Domain("example.com".into(), 443));
+    /// # }
+    /// ```
+    pub async fn read_addr_port<R>(reader: &mut R, atyp: ATYP) -> Result<AddrPort, SocksError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut buf = Vec::new();
+        let rest = match atyp {
+            ATYP::V4 => 4 + 2,
+            ATYP::V6 => 16 + 2,
+            ATYP::DomainName => {
+                let len = reader.read_u8().await.map_err(truncated)?;
+                buf.push(len);
+                len as usize + 2
+            }
+        };
+
+        let start = buf.len();
+        buf.resize(start + rest, 0);
+        reader
+            .read_exact(&mut buf[start..])
+            .await
+            .map_err(truncated)?;
+
+        let parsed = match atyp {
+            ATYP::V4 => Self::parse_ip_port(&buf, 0x01),
+            ATYP::V6 => Self::parse_ip_port(&buf, 0x04),
+            ATYP::DomainName => Self::parse_domain(&buf),
+        };
+        parsed
+            .map(|(addr, _)| addr)
+            .ok_or(SocksError::ConnRequestTooShort)
+    }
+}
+
+/// Maps an early end of stream to [`SocksError::ConnRequestTooShort`].
+fn truncated(e: io::Error) -> SocksError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => SocksError::ConnRequestTooShort,
+        _ => SocksError::Io(e),
+    }
 }
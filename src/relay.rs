@@ -0,0 +1,690 @@
+//! Bidirectional relay between a client and its destination.
+//!
+//! After a successful `CONNECT`, the server pumps bytes in both directions
+//! until each side has closed its write half. When one source reaches EOF,
+//! the opposite write half is shut down so half-closed protocols keep working.
+//!
+//! The pump tallies bytes per direction and keeps an exponentially-weighted
+//! moving average of the throughput, which can be read while the connection
+//! is still live through [`Socks5::connections`](crate::Socks5::connections).
+//!
+//! Once one direction has finished, a write in the other that stalls for
+//! the close timeout ([`Socks5::set_close_notify_timeout`](crate::Socks5::set_close_notify_timeout))
+//! ends the relay, so a peer that never reads cannot pin it.
+//!
+//! A peer that disappears (EOF, reset or broken pipe) ends the relay cleanly
+//! and is reported through [`RelayStats::reason`] rather than as an error, so
+//! clients that close right after the reply, such as health checks and port
+//! probes, finish with a zero-byte [`RelayStats`].
+//!
+//! Each direction can be capped with a byte quota. The bytes up to the cap
+//! are delivered, then the whole relay is torn down and reported as
+//! [`CloseReason::QuotaExceeded`].
+//!
+//! Connections that have not moved a byte for a while can be closed on
+//! demand with [`Socks5::drain_idle_connections`](crate::Socks5::drain_idle_connections),
+//! which ends them as [`CloseReason::IdleDrained`], or automatically after
+//! [`Socks5::set_idle_timeout`](crate::Socks5::set_idle_timeout), which
+//! ends them as [`CloseReason::IdleTimeout`]. Either way both write halves
+//! are shut down.
+//!
+//! Each direction can also be paced to a byte rate with
+//! [`Socks5::set_rate_limit`](crate::Socks5::set_rate_limit), using a token
+//! bucket that sleeps until enough bytes have accrued.
+//!
+//! On Linux, the `splice` feature lets [`Socks5::relay`](crate::Socks5::relay)
+//! move bytes between the two sockets with `splice(2)`, without copying
+//! them through userspace. The server's own relay keeps the buffered pump,
+//! which the metering and limits above need.
+
+use std::collections::HashMap;
+use std::future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Notify;
+use tokio::time;
+
+#[cfg(all(target_os = "linux", feature = "splice"))]
+pub(crate) mod splice;
+
+use crate::error::SocksError;
+use crate::metrics::Metrics;
+use crate::parse::AddrPort;
+
+/// Time constant of the throughput moving average.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+
+/// Default size of the buffer used for each relay direction.
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Largest buffer size accepted for a relay direction.
+pub(crate) const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Default bound on flushing and shutting down a write half at teardown.
+pub(crate) const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tunables applied to every relay of a server.
+#[derive(Clone)]
+pub(crate) struct RelayOptions {
+    /// Size of the buffer allocated for each direction.
+    pub(crate) buffer_size: usize,
+    /// How long a write half may take to flush and shut down once its
+    /// source has reached EOF, and how long a write may stall once the
+    /// other direction has finished.
+    pub(crate) close_timeout: Duration,
+    /// Maximum bytes relayed from the client to the target.
+    pub(crate) up_quota: Option<u64>,
+    /// Maximum bytes relayed from the target to the client.
+    pub(crate) down_quota: Option<u64>,
+    /// Maximum bytes per second relayed in each direction.
+    pub(crate) rate_limit: Option<u64>,
+    /// How long the relay may go without moving a byte before it is closed.
+    pub(crate) idle_timeout: Option<Duration>,
+}
+
+impl RelayOptions {
+    fn quota(&self, dir: Direction) -> Option<u64> {
+        match dir {
+            Direction::ClientToTarget => self.up_quota,
+            Direction::TargetToClient => self.down_quota,
+        }
+    }
+}
+
+impl Default for RelayOptions {
+    fn default() -> Self {
+        Self {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+            up_quota: None,
+            down_quota: None,
+            rate_limit: None,
+            idle_timeout: None,
+        }
+    }
+}
+
+/// Why a relay ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The client closed its side of the connection first.
+    ClientClosed,
+    /// The target closed its side of the connection first.
+    TargetClosed,
+    /// The byte quota of the given direction was reached.
+    QuotaExceeded(Direction),
+    /// The server closed the connection for being idle.
+    IdleDrained,
+    /// No bytes moved for the idle timeout.
+    IdleTimeout,
+}
+
+/// Byte totals of a finished relay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayStats {
+    /// Bytes copied from the client to the target.
+    pub client_to_target: u64,
+    /// Bytes copied from the target to the client.
+    pub target_to_client: u64,
+    /// Which side ended the relay.
+    pub reason: CloseReason,
+}
+
+/// Snapshot of a connection that is currently being relayed.
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// Identifier unique for the lifetime of the server.
+    pub id: u64,
+    /// Address of the connected client.
+    pub client: SocketAddr,
+    /// Destination requested by the client.
+    pub dst: AddrPort,
+    /// When the relay started.
+    pub started: Instant,
+    /// Bytes copied from the client to the target so far.
+    pub client_to_target: u64,
+    /// Bytes copied from the target to the client so far.
+    pub target_to_client: u64,
+    /// When bytes last moved in either direction, or when the relay started
+    /// if none have yet.
+    pub last_activity: Instant,
+    /// Moving average of the combined throughput, in bytes per second,
+    /// over roughly the last few seconds.
+    pub throughput_bps: f64,
+}
+
+/// Direction of a relayed byte stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// From the client to the target ("upload").
+    ClientToTarget,
+    /// From the target to the client ("download").
+    TargetToClient,
+}
+
+impl Direction {
+    /// The side that closed when this direction's reader hits EOF.
+    fn source_closed(self) -> CloseReason {
+        match self {
+            Direction::ClientToTarget => CloseReason::ClientClosed,
+            Direction::TargetToClient => CloseReason::TargetClosed,
+        }
+    }
+
+    /// The side that closed when this direction's writer fails.
+    fn sink_closed(self) -> CloseReason {
+        match self {
+            Direction::ClientToTarget => CloseReason::TargetClosed,
+            Direction::TargetToClient => CloseReason::ClientClosed,
+        }
+    }
+}
+
+/// Exponentially decaying byte rate.
+///
+/// Over an interval of `dt` seconds the rate decays by `e^(-dt / window)`,
+/// and the bytes recorded at its end add `(1 - e^(-dt / window)) * bytes / dt`,
+/// moving the average towards the interval's own rate. A steady input of
+/// `R` bytes per second therefore converges to `R` whatever the chunk size.
+/// Chunks recorded at the same instant share the weight of the interval
+/// that ended there.
+///
+/// Time is read from Tokio's clock, so it follows a paused test clock.
+struct Ewma {
+    rate: f64,
+    last: time::Instant,
+    /// Weight of a byte in the last interval, `(1 - e^(-dt / window)) / dt`.
+    weight: f64,
+}
+
+impl Ewma {
+    fn new(now: time::Instant) -> Self {
+        Self {
+            rate: 0.0,
+            last: now,
+            // The limit of the weight as `dt` goes to zero.
+            weight: 1.0 / THROUGHPUT_WINDOW.as_secs_f64(),
+        }
+    }
+
+    fn decayed(&self, now: time::Instant) -> f64 {
+        let dt = now.saturating_duration_since(self.last).as_secs_f64();
+        self.rate * (-dt / THROUGHPUT_WINDOW.as_secs_f64()).exp()
+    }
+
+    fn record(&mut self, bytes: usize, now: time::Instant) {
+        let dt = now.saturating_duration_since(self.last).as_secs_f64();
+        if dt > 0.0 {
+            self.rate = self.decayed(now);
+            self.weight = -(-dt / THROUGHPUT_WINDOW.as_secs_f64()).exp_m1() / dt;
+            self.last = now;
+        }
+        self.rate += self.weight * bytes as f64;
+    }
+}
+
+/// Live byte accounting shared between the relay pump and readers.
+pub(crate) struct Traffic {
+    client_to_target: AtomicU64,
+    target_to_client: AtomicU64,
+    rate: Mutex<Ewma>,
+    /// Set once the connection has been asked to close.
+    draining: AtomicBool,
+    /// Wakes the pump to close the connection.
+    drain: Notify,
+}
+
+impl Traffic {
+    pub(crate) fn new() -> Self {
+        Self {
+            client_to_target: AtomicU64::new(0),
+            target_to_client: AtomicU64::new(0),
+            rate: Mutex::new(Ewma::new(time::Instant::now())),
+            draining: AtomicBool::new(false),
+            drain: Notify::new(),
+        }
+    }
+
+    fn record(&self, dir: Direction, bytes: usize) {
+        let counter = match dir {
+            Direction::ClientToTarget => &self.client_to_target,
+            Direction::TargetToClient => &self.target_to_client,
+        };
+        counter.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.rate
+            .lock()
+            .unwrap()
+            .record(bytes, time::Instant::now());
+    }
+
+    pub(crate) fn totals(&self) -> (u64, u64) {
+        (
+            self.client_to_target.load(Ordering::Relaxed),
+            self.target_to_client.load(Ordering::Relaxed),
+        )
+    }
+
+    fn throughput_bps(&self) -> f64 {
+        self.rate.lock().unwrap().decayed(time::Instant::now())
+    }
+
+    /// When bytes last moved, which is the last update of the moving average.
+    fn last_activity(&self) -> time::Instant {
+        self.rate.lock().unwrap().last
+    }
+
+    /// Asks the pump to close the connection. Returns `false` if it was
+    /// asked already.
+    fn close(&self) -> bool {
+        if self.draining.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        // Stores a permit if the pump is not waiting yet.
+        self.drain.notify_one();
+        true
+    }
+}
+
+struct Entry {
+    client: SocketAddr,
+    dst: AddrPort,
+    started: Instant,
+    traffic: Arc<Traffic>,
+}
+
+/// Registry of connections currently being relayed.
+#[derive(Default)]
+pub(crate) struct Registry {
+    next_id: AtomicU64,
+    live: Mutex<HashMap<u64, Entry>>,
+}
+
+impl Registry {
+    /// Adds a connection to the registry until the returned guard is dropped.
+    pub(crate) fn register(&self, client: SocketAddr, dst: AddrPort) -> Registration<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let traffic = Arc::new(Traffic::new());
+        let entry = Entry {
+            client,
+            dst,
+            started: Instant::now(),
+            traffic: Arc::clone(&traffic),
+        };
+        self.live.lock().unwrap().insert(id, entry);
+
+        Registration {
+            registry: self,
+            id,
+            traffic,
+        }
+    }
+
+    /// Returns a snapshot of every live connection.
+    pub(crate) fn snapshot(&self) -> Vec<ConnectionInfo> {
+        self.live
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, e)| {
+                let (client_to_target, target_to_client) = e.traffic.totals();
+                ConnectionInfo {
+                    id: *id,
+                    client: e.client,
+                    dst: e.dst.clone(),
+                    started: e.started,
+                    client_to_target,
+                    target_to_client,
+                    last_activity: e.traffic.last_activity().into_std(),
+                    throughput_bps: e.traffic.throughput_bps(),
+                }
+            })
+            .collect()
+    }
+
+    /// Closes every connection that has not moved a byte for `idle_for`,
+    /// returning how many were closed.
+    pub(crate) fn drain_idle(&self, idle_for: Duration) -> usize {
+        let now = time::Instant::now();
+        self.live
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|e| now.saturating_duration_since(e.traffic.last_activity()) >= idle_for)
+            .filter(|e| e.traffic.close())
+            .count()
+    }
+}
+
+/// Keeps a connection registered while it is being relayed.
+pub(crate) struct Registration<'a> {
+    registry: &'a Registry,
+    id: u64,
+    traffic: Arc<Traffic>,
+}
+
+impl Registration<'_> {
+    pub(crate) fn traffic(&self) -> &Traffic {
+        &self.traffic
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.registry.live.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Free list of relay buffers reused across connections.
+///
+/// With a capacity of zero, the default, every relay direction allocates a
+/// fresh buffer and frees it when the connection ends.
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    capacity: usize,
+    free: Mutex<Vec<Box<[u8]>>>,
+}
+
+impl BufferPool {
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.free.get_mut().unwrap().truncate(capacity);
+    }
+
+    /// Borrows a buffer of `size` bytes until the returned guard is dropped.
+    fn take(&self, size: usize) -> PooledBuf<'_> {
+        let reused = match self.capacity {
+            0 => None,
+            // Buffers of a previous size are dropped rather than resized.
+            _ => self.free.lock().unwrap().pop().filter(|b| b.len() == size),
+        };
+
+        PooledBuf {
+            pool: self,
+            buf: reused.unwrap_or_else(|| vec![0u8; size].into_boxed_slice()),
+        }
+    }
+}
+
+/// A buffer borrowed from a [`BufferPool`], returned to it on drop.
+struct PooledBuf<'a> {
+    pool: &'a BufferPool,
+    buf: Box<[u8]>,
+}
+
+impl Drop for PooledBuf<'_> {
+    fn drop(&mut self) {
+        if self.pool.capacity == 0 {
+            return;
+        }
+        let mut free = self.pool.free.lock().unwrap();
+        if free.len() < self.pool.capacity {
+            free.push(std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+/// State shared by both directions of one relay.
+struct Pump<'a> {
+    traffic: &'a Traffic,
+    metrics: &'a Metrics,
+    opts: &'a RelayOptions,
+    pool: &'a BufferPool,
+    /// The first side seen closing.
+    closed: OnceLock<CloseReason>,
+    /// The first direction seen reaching its quota.
+    quota_hit: OnceLock<Direction>,
+    /// Set once either direction has finished.
+    half_done: AtomicBool,
+    /// Wakes the other direction when one finishes.
+    half_done_notify: Notify,
+}
+
+impl Pump<'_> {
+    /// Marks a direction as finished.
+    fn finish_half(&self) {
+        self.half_done.store(true, Ordering::Relaxed);
+        self.half_done_notify.notify_waiters();
+    }
+
+    /// Completes `close_timeout` after the first direction has finished,
+    /// bounding how long the other one may stall on a peer that never
+    /// reads.
+    async fn teardown_deadline(&self) {
+        let notified = self.half_done_notify.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        if !self.half_done.load(Ordering::Relaxed) {
+            notified.await;
+        }
+        time::sleep(self.opts.close_timeout).await;
+    }
+}
+
+/// Copies bytes in both directions until both sources reach EOF.
+pub(crate) async fn pump<A, B>(
+    client: &mut A,
+    target: &mut B,
+    traffic: &Traffic,
+    metrics: &Metrics,
+    opts: &RelayOptions,
+    pool: &BufferPool,
+) -> Result<RelayStats, SocksError>
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut client_r, mut client_w) = io::split(client);
+    let (mut target_r, mut target_w) = io::split(target);
+    let pump = Pump {
+        traffic,
+        metrics,
+        opts,
+        pool,
+        closed: OnceLock::new(),
+        quota_hit: OnceLock::new(),
+        half_done: AtomicBool::new(false),
+        half_done_notify: Notify::new(),
+    };
+
+    let relay = async {
+        tokio::try_join!(
+            async {
+                let copied = copy_half(
+                    &mut client_r,
+                    &mut target_w,
+                    Direction::ClientToTarget,
+                    &pump,
+                )
+                .await;
+                pump.finish_half();
+                copied
+            },
+            async {
+                let copied = copy_half(
+                    &mut target_r,
+                    &mut client_w,
+                    Direction::TargetToClient,
+                    &pump,
+                )
+                .await;
+                pump.finish_half();
+                copied
+            },
+        )
+    };
+    let copied = tokio::select! {
+        copied = relay => Ok(copied),
+        () = traffic.drain.notified() => Err(CloseReason::IdleDrained),
+        () = idle_timer(traffic, opts.idle_timeout) => Err(CloseReason::IdleTimeout),
+    };
+
+    // A quota ends the relay by failing its direction, which also cancels
+    // the other one, and a drain or idle timeout cancels both; the byte
+    // counts then come from the live tallies.
+    let interrupted = match &copied {
+        Ok(Err(e)) if e.kind() == io::ErrorKind::QuotaExceeded => pump
+            .quota_hit
+            .get()
+            .copied()
+            .map(CloseReason::QuotaExceeded),
+        Ok(_) => None,
+        Err(reason) => Some(*reason),
+    };
+    let (client_to_target, target_to_client) = match copied {
+        Ok(Ok(totals)) => totals,
+        Ok(Err(e)) if e.kind() != io::ErrorKind::QuotaExceeded => return Err(e.into()),
+        _ => traffic.totals(),
+    };
+
+    if interrupted.is_some() {
+        let shutdown = async {
+            let _ = tokio::join!(client_w.shutdown(), target_w.shutdown());
+        };
+        let _ = time::timeout(opts.close_timeout, shutdown).await;
+    }
+
+    let reason = interrupted.unwrap_or_else(|| {
+        pump.closed
+            .get()
+            .copied()
+            .unwrap_or(CloseReason::ClientClosed)
+    });
+
+    Ok(RelayStats {
+        client_to_target,
+        target_to_client,
+        reason,
+    })
+}
+
+/// Completes once no bytes have moved for `limit`, or never without one.
+async fn idle_timer(traffic: &Traffic, limit: Option<Duration>) {
+    let Some(limit) = limit else {
+        return future::pending().await;
+    };
+    loop {
+        let deadline = traffic.last_activity() + limit;
+        if time::Instant::now() >= deadline {
+            return;
+        }
+        time::sleep_until(deadline).await;
+    }
+}
+
+/// Token bucket pacing one direction of a relay.
+///
+/// The bucket holds up to a tenth of a second of traffic, so short bursts
+/// pass unpaced. Taking more than it holds leaves it in debt, and the taker
+/// sleeps until the debt is paid off.
+struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: time::Instant,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec as f64;
+        let burst = (rate / 10.0).max(1.0);
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: time::Instant::now(),
+        }
+    }
+
+    /// Takes `n` bytes from the bucket, waiting for it to refill if needed.
+    async fn acquire(&mut self, n: usize) {
+        let now = time::Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.burst) - n as f64;
+        self.last = now;
+
+        if self.tokens < 0.0 {
+            time::sleep(Duration::from_secs_f64(-self.tokens / self.rate)).await;
+        }
+    }
+}
+
+/// Copies one direction, shutting down the writer when the reader hits EOF.
+///
+/// Once the other direction has finished, a write that stalls for the close
+/// timeout ends this one too, as if the peer had gone away.
+async fn copy_half<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    dir: Direction,
+    pump: &Pump<'_>,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut pooled = pump.pool.take(pump.opts.buffer_size);
+    let buf = &mut pooled.buf;
+    let mut total = 0u64;
+    let quota = pump.opts.quota(dir);
+    let mut limiter = pump.opts.rate_limit.map(RateLimiter::new);
+
+    loop {
+        let read = match reader.read(buf).await {
+            Err(e) if !is_disconnect(&e) => return Err(e),
+            res => res.unwrap_or(0),
+        };
+
+        if read == 0 {
+            let _ = pump.closed.set(dir.source_closed());
+            // A peer that stops reading must not keep the teardown waiting.
+            return match time::timeout(pump.opts.close_timeout, writer.shutdown()).await {
+                Ok(Err(e)) if !is_disconnect(&e) => Err(e),
+                _ => Ok(total),
+            };
+        }
+
+        // Only the part of the chunk that still fits in the quota goes out.
+        let n = quota.map_or(read, |q| read.min((q - total) as usize));
+
+        if let Some(limiter) = &mut limiter {
+            limiter.acquire(n).await;
+        }
+
+        let written = tokio::select! {
+            written = writer.write_all(&buf[..n]) => written,
+            () = pump.teardown_deadline() => Err(io::ErrorKind::TimedOut.into()),
+        };
+        if let Err(e) = written {
+            if !is_disconnect(&e) && e.kind() != io::ErrorKind::TimedOut {
+                return Err(e);
+            }
+            let _ = pump.closed.set(dir.sink_closed());
+            return Ok(total);
+        }
+
+        total += n as u64;
+        pump.traffic.record(dir, n);
+        pump.metrics.record_bytes(dir, n);
+
+        // Stop as soon as the cap is reached, rather than waiting for a
+        // read that a peer sending exactly the quota may never make.
+        if quota == Some(total) {
+            let _ = pump.quota_hit.set(dir);
+            return Err(io::ErrorKind::QuotaExceeded.into());
+        }
+    }
+}
+
+/// Returns `true` for errors that only mean the peer went away.
+fn is_disconnect(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::NotConnected
+    )
+}
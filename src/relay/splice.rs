@@ -0,0 +1,111 @@
+//! Zero-copy relay between two TCP sockets with `splice(2)`.
+//!
+//! Each direction owns a pipe: bytes are spliced from the source socket
+//! into the pipe, then from the pipe into the destination socket, so they
+//! never pass through a userspace buffer.
+
+use socket2::SockRef;
+use std::io;
+use std::net::Shutdown;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+use tokio::io::Interest;
+use tokio::net::TcpStream;
+
+use super::is_disconnect;
+
+/// Most bytes moved per `splice` call, the default capacity of a pipe, so
+/// a chunk always fits into the empty pipe.
+const CHUNK: usize = 64 * 1024;
+
+/// The pipe of one direction.
+pub(crate) struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl Pipe {
+    /// Opens a non-blocking pipe.
+    pub(crate) fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors `pipe2` writes.
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `pipe2` succeeded, so both descriptors are open and owned
+        // by nothing else.
+        unsafe {
+            Ok(Self {
+                read: OwnedFd::from_raw_fd(fds[0]),
+                write: OwnedFd::from_raw_fd(fds[1]),
+            })
+        }
+    }
+}
+
+/// Moves up to `len` bytes from `from` to `to` without blocking.
+fn splice(from: RawFd, to: RawFd, len: usize) -> io::Result<usize> {
+    // SAFETY: both descriptors stay open for the call, and null offsets
+    // are required for sockets and pipes.
+    let n = unsafe {
+        libc::splice(
+            from,
+            ptr::null_mut(),
+            to,
+            ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if n == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// Relays `client` and `target` until both directions have closed, with
+/// the same half-close and disconnect handling as the buffered pump.
+///
+/// Returns the byte totals as `(client_to_target, target_to_client)`.
+pub(crate) async fn copy_bidirectional(
+    client: &TcpStream,
+    target: &TcpStream,
+    pipes: (Pipe, Pipe),
+) -> io::Result<(u64, u64)> {
+    let (up, down) = pipes;
+    tokio::try_join!(
+        copy_half(client, target, &up),
+        copy_half(target, client, &down)
+    )
+}
+
+/// Copies one direction, shutting down the writer when the reader hits EOF.
+async fn copy_half(reader: &TcpStream, writer: &TcpStream, pipe: &Pipe) -> io::Result<u64> {
+    let mut total = 0u64;
+
+    loop {
+        let fill = || splice(reader.as_raw_fd(), pipe.write.as_raw_fd(), CHUNK);
+        let read = match reader.async_io(Interest::READABLE, fill).await {
+            Err(e) if !is_disconnect(&e) => return Err(e),
+            res => res.unwrap_or(0),
+        };
+
+        if read == 0 {
+            return match SockRef::from(writer).shutdown(Shutdown::Write) {
+                Err(e) if !is_disconnect(&e) && e.kind() != io::ErrorKind::NotConnected => Err(e),
+                _ => Ok(total),
+            };
+        }
+
+        let mut pending = read;
+        while pending > 0 {
+            let drain = || splice(pipe.read.as_raw_fd(), writer.as_raw_fd(), pending);
+            match writer.async_io(Interest::WRITABLE, drain).await {
+                Ok(n) => pending -= n,
+                Err(e) if is_disconnect(&e) => return Ok(total),
+                Err(e) => return Err(e),
+            }
+        }
+        total += read as u64;
+    }
+}
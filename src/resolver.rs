@@ -0,0 +1,110 @@
+//! Name resolution for `CONNECT` destinations.
+//!
+//! When a client asks for a domain name, the server looks it up with a
+//! [`Resolver`], orders the addresses by the configured
+//! [`AddressPreference`] and hands them to the
+//! [`Connector`](crate::connector::Connector) one by one.
+//!
+//! ```
+//! use std::net::{Ipv4Addr, SocketAddr};
+//! use simple_socks5::resolver::{ResolveFuture, Resolver};
+//!
+//! /// Sends every name to the same host.
+//! struct Pinned(Ipv4Addr);
+//!
+//! impl Resolver for Pinned {
+//!     fn resolve<'a>(&'a self, _host: &'a str, port: u16) -> ResolveFuture<'a> {
+//!         Box::pin(async move { Ok(vec![SocketAddr::from((self.0, port))]) })
+//!     }
+//! }
+//! ```
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::net::lookup_host;
+
+/// Future returned by [`Resolver::resolve`].
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>>;
+
+/// Resolves domain names of `CONNECT` requests.
+pub trait Resolver: Send + Sync {
+    /// Returns every address of `host`, IPv4 and IPv6 merged in one list,
+    /// with `port` set.
+    ///
+    /// The order does not matter unless the server keeps it with
+    /// [`AddressPreference::System`]; the other preferences reorder it.
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a>;
+}
+
+/// The default [`Resolver`], backed by the operating system (`getaddrinfo`).
+///
+/// A single lookup asks for both families, so the system resolver sends the
+/// A and AAAA queries together rather than one after the other.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move { Ok(lookup_host((host, port)).await?.collect()) })
+    }
+}
+
+/// Order in which the resolved addresses of a destination are dialed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AddressPreference {
+    /// Keep the order returned by the resolver, which for the system
+    /// resolver follows the host's address selection policy (RFC 6724).
+    #[default]
+    System,
+    /// Start with an IPv6 address, then alternate between the families, as
+    /// recommended for Happy Eyeballs (RFC 8305 §4).
+    Ipv6First,
+    /// Start with an IPv4 address, then alternate between the families.
+    Ipv4First,
+}
+
+impl AddressPreference {
+    /// Orders `addrs` by this preference.
+    ///
+    /// The addresses of each family keep their relative order; once one
+    /// family runs out, the rest of the other follows.
+    ///
+    /// ```
+    /// use std::net::SocketAddr;
+    /// use simple_socks5::resolver::AddressPreference;
+    ///
+    /// let addrs: Vec<SocketAddr> = ["10.0.0.1:80", "10.0.0.2:80", "[2001:db8::1]:80"]
+    ///     .iter()
+    ///     .map(|a| a.parse().unwrap())
+    ///     .collect();
+    ///
+    /// let sorted = AddressPreference::Ipv6First.sort(addrs);
+    /// assert_eq!(sorted[0].to_string(), "[2001:db8::1]:80");
+    /// assert_eq!(sorted[1].to_string(), "10.0.0.1:80");
+    /// assert_eq!(sorted[2].to_string(), "10.0.0.2:80");
+    /// ```
+    pub fn sort(self, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let first_v6 = match self {
+            AddressPreference::System => return addrs,
+            AddressPreference::Ipv6First => true,
+            AddressPreference::Ipv4First => false,
+        };
+
+        let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+        let (mut first, mut second) = if first_v6 {
+            (v6.into_iter(), v4.into_iter())
+        } else {
+            (v4.into_iter(), v6.into_iter())
+        };
+
+        let mut sorted = Vec::with_capacity(first.len() + second.len());
+        loop {
+            match (first.next(), second.next()) {
+                (None, None) => return sorted,
+                (a, b) => sorted.extend(a.into_iter().chain(b)),
+            }
+        }
+    }
+}
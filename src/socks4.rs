@@ -0,0 +1,234 @@
+//! SOCKS4 and SOCKS4a `CONNECT` on the SOCKS5 port.
+//!
+//! With [`Socks5::allow_socks4`](crate::Socks5::allow_socks4), a client
+//! whose first byte is `0x04` is served as a SOCKS4 client: the request is
+//! read, the target dialed with the same policy and connector as SOCKS5
+//! requests, and the connection relayed after a granted reply.
+//!
+//! A SOCKS4 request is laid out as
+//!
+//! ```text
+//! +----+----+---------+--------+-----------+------+
+//! | VN | CD | DSTPORT | DSTIP  | USERID    | NULL |
+//! +----+----+---------+--------+-----------+------+
+//! | 1  | 1  |    2    |   4    | variable  |  1   |
+//! +----+----+---------+--------+-----------+------+
+//! ```
+//!
+//! SOCKS4a lets a client that cannot resolve names send `DSTIP` as
+//! `0.0.0.x` with a non-zero `x`, followed by the host name and another
+//! `NULL` after the `USERID`.
+//!
+//! Nothing here is used unless the server enables it.
+
+use std::net::Ipv4Addr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::conn::reply::Rep;
+use crate::error::SocksError;
+use crate::parse::AddrPort;
+
+/// Maximum length of the `USERID` and SOCKS4a host name fields.
+const MAX_FIELD: usize = 255;
+
+/// The command of a SOCKS4 request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// `CD = 1`, open a TCP connection to the destination.
+    Connect,
+    /// `CD = 2`, accept one inbound connection.
+    Bind,
+}
+
+/// A SOCKS4 or SOCKS4a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The requested command.
+    pub command: Command,
+    /// The destination, an [`AddrPort::Domain`] for SOCKS4a requests.
+    pub dst: AddrPort,
+    /// The `USERID` field, often empty.
+    pub user_id: String,
+}
+
+impl Request {
+    /// Serializes the request into its wire format, using the SOCKS4a
+    /// form for domain names.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SocksError::InvalidAddressType`] for IPv6 destinations,
+    /// which SOCKS4 cannot express.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::AddrPort;
+    /// use simple_socks5::socks4::{Command, Request};
+    ///
+    /// let req = Request {
+    ///     command: Command::Connect,
+    ///     dst: AddrPort::Domain("example.com".into(), 80),
+    ///     user_id: String::new(),
+    /// };
+    /// let bytes = req.to_bytes().unwrap();
+    /// assert_eq!(bytes[..8], [0x04, 0x01, 0x00, 0x50, 0, 0, 0, 1]);
+    /// assert_eq!(&bytes[8..], b"\0example.com\0");
+    /// ```
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SocksError> {
+        let cd = match self.command {
+            Command::Connect => 0x01,
+            Command::Bind => 0x02,
+        };
+        let (ip, port, host) = match &self.dst {
+            AddrPort::V4(ip, port) => (*ip, *port, None),
+            AddrPort::Domain(host, port) => (Ipv4Addr::new(0, 0, 0, 1), *port, Some(host)),
+            AddrPort::V6(_, _) => return Err(SocksError::InvalidAddressType(0x04)),
+        };
+
+        let mut buf = vec![0x04, cd];
+        buf.extend_from_slice(&port.to_be_bytes());
+        buf.extend_from_slice(&ip.octets());
+        buf.extend_from_slice(self.user_id.as_bytes());
+        buf.push(0x00);
+        if let Some(host) = host {
+            buf.extend_from_slice(host.as_bytes());
+            buf.push(0x00);
+        }
+        Ok(buf)
+    }
+
+    /// Reads exactly one SOCKS4 or SOCKS4a request from an async stream.
+    ///
+    /// # Errors
+    ///
+    /// - [`SocksError::UnsupportedVersion`] if `VN` is not `0x04`.
+    /// - [`SocksError::UnsupportedCommand`] for an unknown `CD`.
+    /// - [`SocksError::InvalidSocks4Request`] if `USERID` or the host name
+    ///   is longer than 255 bytes or not UTF-8.
+    /// - [`SocksError::Io`] on transport failures, including a stream that
+    ///   ends early.
+    ///
+    /// # Example
+    /// ```
+    /// use simple_socks5::parse::AddrPort;
+    /// use simple_socks5::socks4::Request;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let bytes = b"\x04\x01\x01\xbb\x00\x00\x00\x01alice\0example.com\0";
+    /// let req = Request::read_from(&mut &bytes[..]).await.unwrap();
+    /// assert_eq!(req.dst, AddrPort::Domain("example.com".into(), 443));
+    /// assert_eq!(req.user_id, "alice");
+    /// # }
+    /// ```
+    pub async fn read_from<R>(reader: &mut R) -> Result<Self, SocksError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut head = [0u8; 8];
+        reader.read_exact(&mut head).await?;
+
+        if head[0] != 0x04 {
+            return Err(SocksError::UnsupportedVersion(head[0]));
+        }
+        let command = match head[1] {
+            0x01 => Command::Connect,
+            0x02 => Command::Bind,
+            other => return Err(SocksError::UnsupportedCommand(other)),
+        };
+        let port = u16::from_be_bytes([head[2], head[3]]);
+        let ip = Ipv4Addr::new(head[4], head[5], head[6], head[7]);
+
+        let user_id = read_field(reader).await?;
+        let dst = match ip.octets() {
+            [0, 0, 0, x] if x != 0 => AddrPort::domain(read_field(reader).await?, port)?,
+            _ => AddrPort::V4(ip, port),
+        };
+
+        Ok(Self {
+            command,
+            dst,
+            user_id,
+        })
+    }
+}
+
+/// Reads a `NULL`-terminated string field.
+async fn read_field<R>(reader: &mut R) -> Result<String, SocksError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut field = Vec::new();
+    loop {
+        match reader.read_u8().await? {
+            0x00 => break,
+            _ if field.len() == MAX_FIELD => return Err(SocksError::InvalidSocks4Request),
+            b => field.push(b),
+        }
+    }
+    String::from_utf8(field).map_err(|_| SocksError::InvalidSocks4Request)
+}
+
+/// The result code of a SOCKS4 reply (`CD`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    /// `90`, request granted.
+    Granted,
+    /// `91`, request rejected or failed.
+    Rejected,
+    /// `92`, rejected because the client's identd could not be reached.
+    IdentdUnreachable,
+    /// `93`, rejected because identd reported a different user ID.
+    IdentdMismatch,
+}
+
+impl Reply {
+    /// Returns the byte sent on the wire.
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Reply::Granted => 90,
+            Reply::Rejected => 91,
+            Reply::IdentdUnreachable => 92,
+            Reply::IdentdMismatch => 93,
+        }
+    }
+
+    /// Maps a SOCKS5 reply code to the SOCKS4 result answering a request.
+    ///
+    /// SOCKS4 has a single failure code for everything other than identd
+    /// problems, so every failure maps to [`Reply::Rejected`].
+    ///
+    /// ```
+    /// use simple_socks5::conn::reply::Rep;
+    /// use simple_socks5::socks4::Reply;
+    ///
+    /// assert_eq!(Reply::for_rep(Rep::Succeeded), Reply::Granted);
+    /// assert_eq!(Reply::for_rep(Rep::HostUnreachable), Reply::Rejected);
+    /// ```
+    pub fn for_rep(rep: Rep) -> Self {
+        match rep {
+            Rep::Succeeded => Reply::Granted,
+            _ => Reply::Rejected,
+        }
+    }
+}
+
+/// Sends a SOCKS4 reply.
+///
+/// `bnd` is reported in `DSTPORT`/`DSTIP`, which clients ignore for
+/// `CONNECT`; anything but an IPv4 address is sent as `0.0.0.0:0`.
+pub async fn send_reply<W>(stream: &mut W, reply: Reply, bnd: &AddrPort) -> Result<(), SocksError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let (ip, port) = match bnd {
+        AddrPort::V4(ip, port) => (*ip, *port),
+        _ => (Ipv4Addr::UNSPECIFIED, 0),
+    };
+
+    let mut buf = vec![0x00, reply.to_u8()];
+    buf.extend_from_slice(&port.to_be_bytes());
+    buf.extend_from_slice(&ip.octets());
+    stream.write_all(&buf).await?;
+    Ok(())
+}
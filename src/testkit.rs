@@ -0,0 +1,194 @@
+//! Helpers for end-to-end tests against a real server.
+//!
+//! Available with the `test-util` feature. [`spawn_server`] starts a
+//! [`Socks5`] on an ephemeral loopback port, [`spawn_echo`] a target that
+//! sends back whatever it reads, and [`connect`] performs the full client
+//! handshake and `CONNECT` through the server. [`MockConnector`] replaces
+//! the network on the server's outbound side with a script, so connect-path
+//! behaviour such as fallback between addresses, timeouts and error replies
+//! can be tested deterministically.
+//!
+//! ```
+//! use simple_socks5::testkit;
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), simple_socks5::error::SocksError> {
+//! let proxy = testkit::spawn_server(|server| server.allow_no_auth()).await?;
+//! let echo = testkit::spawn_echo().await?;
+//!
+//! let mut stream = testkit::connect(proxy, echo, None).await?;
+//! stream.write_all(b"ping").await?;
+//! let mut buf = [0; 4];
+//! stream.read_exact(&mut buf).await?;
+//! assert_eq!(&buf, b"ping");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+use crate::Socks5;
+use crate::client;
+use crate::connector::{ConnectFuture, Connector, Destination};
+use crate::error::SocksError;
+use crate::parse::AddrPort;
+
+/// Binds a server to `127.0.0.1:0`, lets `configure` set it up and runs it
+/// in the background.
+///
+/// Returns the address the server listens on. The server runs until the
+/// Tokio runtime shuts down.
+///
+/// # Errors
+///
+/// Returns a `SocksError` if the listener cannot be bound.
+pub async fn spawn_server<F>(configure: F) -> Result<SocketAddr, SocksError>
+where
+    F: FnOnce(&mut Socks5),
+{
+    let mut server = Socks5::bind("127.0.0.1:0").await?;
+    configure(&mut server);
+    let addr = server.local_addr()?;
+    tokio::spawn(server.run());
+    Ok(addr)
+}
+
+/// Starts a TCP echo target on `127.0.0.1:0` and returns its address.
+///
+/// Every accepted connection gets back what it sends until it closes.
+pub async fn spawn_echo() -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+
+    Ok(addr)
+}
+
+/// Connects to `target` through the SOCKS5 server at `proxy`.
+///
+/// Offers `NO AUTH`, or username/password when `credentials` are given, and
+/// returns the stream once the server has answered the `CONNECT` with
+/// [`Rep::Succeeded`](crate::conn::reply::Rep::Succeeded).
+///
+/// # Errors
+///
+/// Same as [`client::connect`]; a refused `CONNECT` is reported as
+/// `SocksError::UpstreamRejected` with the server's reply code.
+pub async fn connect(
+    proxy: SocketAddr,
+    target: SocketAddr,
+    credentials: Option<(&str, &str)>,
+) -> Result<TcpStream, SocksError> {
+    let (stream, _) =
+        client::connect(&proxy.to_string(), &AddrPort::from(target), credentials).await?;
+    Ok(stream)
+}
+
+/// What a [`MockConnector`] does when asked to dial an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockOutcome {
+    /// Connects to this real address instead, e.g. one from [`spawn_echo`].
+    ConnectTo(SocketAddr),
+    /// Fails with an error of this kind.
+    Fail(io::ErrorKind),
+    /// Waits, then does what the inner outcome says.
+    Delay(Duration, Box<MockOutcome>),
+    /// Never completes, like a destination that drops every packet.
+    Hang,
+}
+
+/// A scripted [`Connector`] for deterministic tests of the connect path.
+///
+/// Each destination address is given a [`MockOutcome`] with
+/// [`MockConnector::script`]; addresses without one fail with
+/// `ConnectionRefused`. Clones share the script and the record of dialed
+/// addresses, so a test can keep one clone after handing another to
+/// [`Socks5::set_connector`].
+///
+/// ```
+/// use std::io::ErrorKind;
+/// use simple_socks5::testkit::{self, MockConnector, MockOutcome};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), simple_socks5::error::SocksError> {
+/// let echo = testkit::spawn_echo().await?;
+/// let unreachable = "192.0.2.1:80".parse().unwrap();
+///
+/// let mock = MockConnector::new();
+/// mock.script(unreachable, MockOutcome::Fail(ErrorKind::HostUnreachable));
+/// mock.script(echo, MockOutcome::ConnectTo(echo));
+///
+/// let server_mock = mock.clone();
+/// let proxy = testkit::spawn_server(move |server| {
+///     server.allow_no_auth();
+///     server.set_connector(server_mock);
+/// })
+/// .await?;
+///
+/// assert!(testkit::connect(proxy, unreachable, None).await.is_err());
+/// assert!(testkit::connect(proxy, echo, None).await.is_ok());
+/// assert_eq!(mock.dialed(), [unreachable, echo]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockConnector {
+    script: Arc<Mutex<HashMap<SocketAddr, MockOutcome>>>,
+    dialed: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl MockConnector {
+    /// Creates a connector with an empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets what happens when `addr` is dialed, replacing any earlier outcome.
+    pub fn script(&self, addr: SocketAddr, outcome: MockOutcome) {
+        self.script.lock().unwrap().insert(addr, outcome);
+    }
+
+    /// Returns every address dialed so far, in order.
+    pub fn dialed(&self) -> Vec<SocketAddr> {
+        self.dialed.lock().unwrap().clone()
+    }
+}
+
+impl Connector for MockConnector {
+    fn connect<'a>(&'a self, dst: &'a Destination) -> ConnectFuture<'a> {
+        self.dialed.lock().unwrap().push(dst.addr);
+        let outcome = self.script.lock().unwrap().get(&dst.addr).cloned();
+
+        Box::pin(async move {
+            let mut outcome =
+                outcome.unwrap_or(MockOutcome::Fail(io::ErrorKind::ConnectionRefused));
+            loop {
+                match outcome {
+                    MockOutcome::ConnectTo(addr) => return TcpStream::connect(addr).await,
+                    MockOutcome::Fail(kind) => return Err(kind.into()),
+                    MockOutcome::Delay(delay, next) => {
+                        time::sleep(delay).await;
+                        outcome = *next;
+                    }
+                    MockOutcome::Hang => return future::pending().await,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,43 @@
+//! Optional `tracing` instrumentation.
+//!
+//! With the `tracing` feature enabled, every client is served inside a span
+//! carrying its address, and protocol transitions are emitted as events.
+//! Without it, the macros below expand to nothing.
+
+use std::future::Future;
+use std::net::SocketAddr;
+
+/// Emits a `DEBUG` event when the `tracing` feature is enabled.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)*);
+    };
+}
+
+/// Emits an `INFO` event when the `tracing` feature is enabled.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::info!($($arg)*);
+    };
+}
+
+pub(crate) use {debug, info};
+
+/// Runs `fut` inside a span keyed by the client's address.
+#[cfg(feature = "tracing")]
+pub(crate) fn in_client_span<F: Future>(
+    client: SocketAddr,
+    fut: F,
+) -> impl Future<Output = F::Output> {
+    use tracing::Instrument;
+
+    fut.instrument(tracing::info_span!("socks5", %client))
+}
+
+/// Runs `fut` inside a span keyed by the client's address.
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn in_client_span<F: Future>(_client: SocketAddr, fut: F) -> F {
+    fut
+}
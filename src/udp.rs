@@ -0,0 +1,128 @@
+//! `UDP ASSOCIATE` sockets.
+//!
+//! An association binds a UDP socket on the interface the client reached
+//! over TCP, so a client behind NAT can send datagrams to the address it is
+//! told in the reply. The address in the client's request is the source it
+//! expects to send from; RFC 1928 §6 lets it be all zeros when the client
+//! does not know it yet.
+//!
+//! The association ends when the client closes the TCP connection its
+//! request arrived on (RFC 1928 §7); [`run_while_connected`] ties a relay
+//! task to that connection.
+//!
+//! **Relaying datagrams is not implemented yet.**
+
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::error::SocksError;
+use crate::parse::AddrPort;
+
+/// A UDP socket bound for one `UDP ASSOCIATE` request.
+#[derive(Debug)]
+pub struct UdpAssociation {
+    socket: UdpSocket,
+    client_ip: IpAddr,
+    client_port: Option<u16>,
+}
+
+impl UdpAssociation {
+    /// Binds a socket on the local IP of `control`, the TCP connection the
+    /// request arrived on, with a port picked by the system.
+    ///
+    /// `requested` is the `DST.ADDR`/`DST.PORT` of the request. An
+    /// unspecified IP (`0.0.0.0`, `::`), or a domain name, is replaced with
+    /// the IP of the control connection's peer, and a zero port accepts
+    /// datagrams from any port of that IP.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SocksError::Io`] if the addresses of `control` cannot be
+    /// read or the socket cannot be bound.
+    pub async fn bind(control: &TcpStream, requested: &AddrPort) -> Result<Self, SocksError> {
+        Self::bind_on(control.local_addr()?, control.peer_addr()?, requested).await
+    }
+
+    /// Like [`UdpAssociation::bind`], for a control connection whose local
+    /// and peer addresses are already known, e.g. one wrapped in TLS.
+    pub async fn bind_on(
+        local: SocketAddr,
+        peer: SocketAddr,
+        requested: &AddrPort,
+    ) -> Result<Self, SocksError> {
+        let socket = UdpSocket::bind((local.ip().to_canonical(), 0)).await?;
+
+        let (ip, port) = match requested.normalized() {
+            AddrPort::V4(ip, port) => (Some(IpAddr::V4(ip)), port),
+            AddrPort::V6(ip, port) => (Some(IpAddr::V6(ip)), port),
+            AddrPort::Domain(_, port) => (None, port),
+        };
+        let client_ip = match ip {
+            Some(ip) if !ip.is_unspecified() => ip,
+            _ => peer.ip().to_canonical(),
+        };
+
+        Ok(Self {
+            socket,
+            client_ip,
+            client_port: (port != 0).then_some(port),
+        })
+    }
+
+    /// The address the socket is bound to, to be sent as `BND.ADDR` and
+    /// `BND.PORT` in the reply.
+    pub fn local_addr(&self) -> Result<AddrPort, SocksError> {
+        Ok(AddrPort::from(self.socket.local_addr()?))
+    }
+
+    /// Returns `true` if a datagram from `from` belongs to the client.
+    pub fn accepts(&self, from: SocketAddr) -> bool {
+        from.ip().to_canonical() == self.client_ip
+            && self.client_port.is_none_or(|port| port == from.port())
+    }
+
+    /// The bound socket.
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+}
+
+/// Runs `task` for as long as the client keeps `control` open.
+///
+/// Meant for the relay of a `UDP ASSOCIATE` or `BIND` request, whose
+/// lifetime RFC 1928 ties to the control connection. Once the client closes
+/// `control`, or it fails, `task` is dropped, which cancels it and closes
+/// the sockets it owns, so an abrupt disconnect leaks nothing. Bytes the
+/// client sends on `control` meanwhile are discarded.
+///
+/// Returns the output of `task`, or `None` if the control connection closed
+/// first.
+///
+/// ```no_run
+/// use std::future;
+/// use simple_socks5::udp::run_while_connected;
+/// use tokio::net::TcpStream;
+///
+/// # async fn run(mut control: TcpStream) {
+/// // Hold the association until the client goes away.
+/// run_while_connected(&mut control, future::pending::<()>()).await;
+/// # }
+/// ```
+pub async fn run_while_connected<S, F>(control: &mut S, task: F) -> Option<F::Output>
+where
+    S: AsyncRead + Unpin,
+    F: Future,
+{
+    tokio::select! {
+        output = task => Some(output),
+        () = closed(control) => None,
+    }
+}
+
+/// Completes once the peer closes `control` or the connection fails.
+async fn closed<S: AsyncRead + Unpin>(control: &mut S) {
+    let mut buf = [0u8; 64];
+    while matches!(control.read(&mut buf).await, Ok(n) if n > 0) {}
+}
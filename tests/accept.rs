@@ -0,0 +1,70 @@
+//! Checks that the accept loop survives running out of file descriptors.
+//!
+//! Lowers the process's descriptor limit, so it lives in its own test binary.
+
+#![cfg(unix)]
+
+use std::fs::File;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use simple_socks5::client;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::testkit;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+#[tokio::test]
+async fn server_keeps_accepting_after_running_out_of_descriptors() {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    assert_eq!(
+        unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) },
+        0
+    );
+    let lowered = libc::rlimit {
+        rlim_cur: limit.rlim_cur.min(256),
+        ..limit
+    };
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &lowered) }, 0);
+
+    let proxy = testkit::spawn_server(|server| server.allow_no_auth())
+        .await
+        .unwrap();
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, target.local_addr().unwrap().port());
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = target.accept().await {
+            let _ = stream.write_all(b"hi").await;
+        }
+    });
+
+    // Use up every descriptor but one, which the client connection takes,
+    // so the server's `accept` fails with `EMFILE`.
+    let mut hogs = Vec::new();
+    while let Ok(file) = File::open("/dev/null") {
+        hogs.push(file);
+    }
+    hogs.pop();
+    let stuck = TcpStream::connect(proxy).await.unwrap();
+    time::sleep(Duration::from_millis(300)).await;
+
+    drop(hogs);
+    drop(stuck);
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) }, 0);
+
+    let (mut stream, _) = time::timeout(
+        Duration::from_secs(5),
+        client::connect(&proxy.to_string(), &dst, None),
+    )
+    .await
+    .expect("server stopped accepting")
+    .unwrap();
+    let mut greeting = [0; 2];
+    stream.read_exact(&mut greeting).await.unwrap();
+    assert_eq!(&greeting, b"hi");
+}
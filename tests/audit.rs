@@ -0,0 +1,66 @@
+//! End-to-end checks of the audit sink.
+
+use std::net::Ipv4Addr;
+
+use simple_socks5::audit::{AuditEvent, AuditFuture, AuditSink};
+use simple_socks5::client;
+use simple_socks5::conn::request::CMD;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::testkit;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// Forwards every event to a channel.
+struct Recorder(mpsc::UnboundedSender<AuditEvent>);
+
+impl AuditSink for Recorder {
+    fn record(&self, event: AuditEvent) -> AuditFuture<'_> {
+        let _ = self.0.send(event);
+        Box::pin(async {})
+    }
+}
+
+#[tokio::test]
+async fn relayed_connection_is_recorded_at_start_and_end() {
+    let (tx, mut events) = mpsc::unbounded_channel();
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_userpass(|uname, passwd| uname == "alice" && passwd == "secret");
+        server.set_audit_sink(Recorder(tx));
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, listener.local_addr().unwrap().port());
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(b"pong!").await.unwrap();
+    });
+
+    let (mut stream, _) = client::connect(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap();
+    let client = stream.local_addr().unwrap();
+
+    let start = events.recv().await.unwrap();
+    assert_eq!(start.client, client);
+    assert_eq!(start.user.as_deref(), Some("alice"));
+    assert_eq!(start.dst, dst);
+    assert_eq!(start.command, CMD::Connect);
+    assert_eq!(start.ended, None);
+
+    stream.write_all(b"ping").await.unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    drop(stream);
+
+    let end = events.recv().await.unwrap();
+    assert_eq!(end.started, start.started);
+    assert!(end.ended.unwrap() >= end.started);
+    assert_eq!(end.client_to_target, 4);
+    assert_eq!(end.target_to_client, 5);
+}
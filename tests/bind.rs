@@ -0,0 +1,48 @@
+//! Checks of binding the listener, and of the errors reported when it fails.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use simple_socks5::Socks5;
+use simple_socks5::error::SocksError;
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn taken_port_is_reported_as_addr_in_use() {
+    let first = Socks5::bind("127.0.0.1:0").await.unwrap();
+    let addr = first.local_addr().unwrap().to_string();
+
+    match Socks5::bind(&addr).await {
+        Err(SocksError::AddrInUse { addr: reported, .. }) => assert_eq!(reported, addr),
+        other => panic!("expected AddrInUse, got {:?}", other.err()),
+    }
+}
+
+#[tokio::test]
+async fn privileged_port_is_reported_as_permission_denied() {
+    match Socks5::bind("127.0.0.1:1").await {
+        // Running with the privilege to bind low ports; nothing to check.
+        Ok(_) => {}
+        Err(SocksError::BindPermissionDenied { addr, .. }) => assert_eq!(addr, "127.0.0.1:1"),
+        Err(e) => panic!("expected BindPermissionDenied, got {e:?}"),
+    }
+}
+
+#[tokio::test]
+async fn dual_stack_listener_accepts_both_families() {
+    let server = Socks5::bind_dual_stack(0).unwrap();
+    let port = server.local_addr().unwrap().port();
+
+    let v4 = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+        .await
+        .unwrap();
+    let (_, peer) = server.accept().await.unwrap();
+    assert_eq!(peer.ip(), IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped()));
+    drop(v4);
+
+    let v6 = TcpStream::connect((Ipv6Addr::LOCALHOST, port))
+        .await
+        .unwrap();
+    let (_, peer) = server.accept().await.unwrap();
+    assert_eq!(peer.ip(), IpAddr::V6(Ipv6Addr::LOCALHOST));
+    drop(v6);
+}
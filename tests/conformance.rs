@@ -0,0 +1,332 @@
+//! Conformance checks against the message layouts of RFC 1928.
+//!
+//! Every vector is written out field by field as the RFC draws it, and is
+//! checked in both directions: parsing the bytes gives the expected message
+//! and serializing that message gives the bytes back. Where the RFC leaves
+//! the behavior open, the test says which way this crate goes.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use simple_socks5::conn::reply::{ConnReply, Rep};
+use simple_socks5::conn::request::{CMD, ConnRequest};
+use simple_socks5::error::SocksError;
+use simple_socks5::msg::message::{MethodSelection, VersionMessage};
+use simple_socks5::msg::method::Method;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::{ATYP, Socks5};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+
+/// Checks that `bytes` parse as `request` and that `request` serializes
+/// to `bytes`.
+fn request_matches(bytes: &[u8], request: ConnRequest) {
+    assert_eq!(ConnRequest::try_from(bytes).unwrap(), request);
+    assert_eq!(request.to_bytes().unwrap(), bytes);
+}
+
+/// Checks that `bytes` parse as `reply` and that `reply` serializes to
+/// `bytes`.
+fn reply_matches(bytes: &[u8], reply: ConnReply) {
+    assert_eq!(ConnReply::try_from(bytes).unwrap(), reply);
+    assert_eq!(reply.to_bytes().unwrap(), bytes);
+}
+
+// ===== §3 Procedure for TCP-based clients =====
+
+#[test]
+fn version_identifier_message() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05,             // VER
+        0x03,             // NMETHODS
+        0x00, 0x01, 0x02, // METHODS: NO AUTH, GSSAPI, USERNAME/PASSWORD
+    ];
+    let msg = VersionMessage::new(vec![
+        Method::no_auth(),
+        Method::gssapi(),
+        Method::userpass(),
+    ]);
+
+    assert_eq!(VersionMessage::try_from(&bytes[..]).unwrap(), msg);
+    assert_eq!(msg.to_bytes().unwrap(), bytes);
+}
+
+/// The RFC does not say whether METHODS may repeat. Parsing keeps the list
+/// as sent; serializing sorts it and drops duplicates.
+#[test]
+fn repeated_methods_are_kept_when_parsed_and_dropped_when_sent() {
+    let bytes = [0x05, 0x03, 0x02, 0x00, 0x02];
+    let msg = VersionMessage::try_from(&bytes[..]).unwrap();
+
+    assert_eq!(
+        msg.methods,
+        [Method::userpass(), Method::no_auth(), Method::userpass()]
+    );
+    assert_eq!(msg.to_bytes().unwrap(), [0x05, 0x02, 0x00, 0x02]);
+}
+
+#[test]
+fn method_selection_message() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05, // VER
+        0x00, // METHOD: NO AUTHENTICATION REQUIRED
+    ];
+    let msg = MethodSelection::new(Method::no_auth());
+
+    assert_eq!(MethodSelection::try_from(&bytes[..]).unwrap(), msg);
+    assert_eq!(msg.to_bytes(), bytes);
+}
+
+#[test]
+fn method_codes() {
+    for (byte, method) in [
+        (0x00, Method::no_auth()),
+        (0x01, Method::gssapi()),
+        (0x02, Method::userpass()),
+    ] {
+        assert_eq!(Method::from_u8(byte).unwrap(), method);
+        assert_eq!(method.to_u8(), byte);
+    }
+    assert!(Method::from_u8(0x03).unwrap().is_iana());
+    assert!(Method::from_u8(0x80).unwrap().is_private());
+}
+
+// ===== §4 Requests =====
+
+#[test]
+fn ipv4_connect_request() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05,                   // VER
+        0x01,                   // CMD: CONNECT
+        0x00,                   // RSV
+        0x01,                   // ATYP: IP V4 address
+        0xC0, 0x00, 0x02, 0x01, // DST.ADDR: 192.0.2.1
+        0x00, 0x50,             // DST.PORT: 80
+    ];
+    let dst = AddrPort::V4(Ipv4Addr::new(192, 0, 2, 1), 80);
+
+    request_matches(
+        &bytes,
+        ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::V4, dst),
+    );
+}
+
+#[test]
+fn ipv6_connect_request() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05, 0x01, 0x00,
+        0x04,                                           // ATYP: IP V6 address
+        0x20, 0x01, 0x0D, 0xB8, 0x00, 0x00, 0x00, 0x00, // DST.ADDR: 2001:db8::1
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x01, 0xBB,                                     // DST.PORT: 443
+    ];
+    let dst = AddrPort::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 443);
+
+    request_matches(
+        &bytes,
+        ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::V6, dst),
+    );
+}
+
+/// The domain name is prefixed by its length and has no terminating NUL.
+#[test]
+fn domain_connect_request() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05, 0x01, 0x00,
+        0x03,                                     // ATYP: DOMAINNAME
+        0x0B,                                     // name length: 11
+        b'e', b'x', b'a', b'm', b'p', b'l', b'e', // DST.ADDR: "example.com"
+        b'.', b'c', b'o', b'm',
+        0x1F, 0x90,                               // DST.PORT: 8080
+    ];
+    let dst = AddrPort::Domain("example.com".into(), 8080);
+
+    request_matches(
+        &bytes,
+        ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::DomainName, dst),
+    );
+}
+
+#[test]
+fn command_codes() {
+    for (byte, cmd) in [
+        (0x01, CMD::Connect),
+        (0x02, CMD::Bind),
+        (0x03, CMD::UdpAssociate),
+    ] {
+        let bytes = [0x05, byte, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+        let dst = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+        request_matches(&bytes, ConnRequest::new(0x05, cmd, 0x00, ATYP::V4, dst));
+    }
+
+    let bytes = [0x05, 0x04, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+    assert!(matches!(
+        ConnRequest::try_from(&bytes[..]),
+        Err(SocksError::UnsupportedCommand(0x04))
+    ));
+}
+
+/// `0x02` is not assigned to any address type.
+#[test]
+fn unassigned_address_type_is_rejected() {
+    let bytes = [0x05, 0x01, 0x00, 0x02, 0, 0, 0, 0, 0, 0];
+    let err = ConnRequest::try_from(&bytes[..]).unwrap_err();
+
+    assert!(matches!(err, SocksError::InvalidAddressType(0x02)));
+    assert_eq!(err.to_rep(), Some(Rep::AddressTypeNotSupported));
+}
+
+/// RSV "must be" `0x00`, but the RFC does not say what to do otherwise.
+/// Parsing keeps RSV as sent and leaves the decision to the caller.
+#[test]
+fn reserved_byte_is_kept_as_sent() {
+    let bytes = [0x05, 0x01, 0xAA, 0x01, 127, 0, 0, 1, 0x00, 0x50];
+    let req = ConnRequest::try_from(&bytes[..]).unwrap();
+
+    assert_eq!(req.rsv, 0xAA);
+    assert_eq!(req.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn request_with_another_version_is_rejected() {
+    let bytes = [0x04, 0x01, 0x00, 0x01, 127, 0, 0, 1, 0x00, 0x50];
+
+    assert!(matches!(
+        ConnRequest::try_from(&bytes[..]),
+        Err(SocksError::UnsupportedVersion(0x04))
+    ));
+}
+
+/// The RFC does not forbid an empty name. It parses as an empty domain,
+/// which resolving later rejects.
+#[test]
+fn empty_domain_name_is_parsed() {
+    let bytes = [0x05, 0x01, 0x00, 0x03, 0x00, 0x00, 0x50];
+    let req = ConnRequest::try_from(&bytes[..]).unwrap();
+
+    assert_eq!(req.dst, AddrPort::Domain(String::new(), 80));
+}
+
+/// Parsing a request from a slice stops after DST.PORT; whatever follows
+/// belongs to the next phase.
+#[test]
+fn bytes_after_the_request_are_ignored() {
+    let bytes = [0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1, 0x00, 0x50, 0xDE, 0xAD];
+    let req = ConnRequest::try_from(&bytes[..]).unwrap();
+
+    assert_eq!(req.to_bytes().unwrap(), bytes[..10]);
+}
+
+// ===== §6 Replies =====
+
+#[test]
+fn ipv6_reply() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05,                                           // VER
+        0x00,                                           // REP: succeeded
+        0x00,                                           // RSV
+        0x04,                                           // ATYP: IP V6 address
+        0xFE, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // BND.ADDR: fe80::1
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0xC3, 0x50,                                     // BND.PORT: 50000
+    ];
+    let bnd = AddrPort::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1), 50000);
+
+    reply_matches(
+        &bytes,
+        ConnReply::new(0x05, Rep::Succeeded, 0x00, ATYP::V6, bnd),
+    );
+}
+
+#[test]
+fn ipv4_reply() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05, 0x00, 0x00,
+        0x01,                   // ATYP: IP V4 address
+        0x0A, 0x00, 0x00, 0x01, // BND.ADDR: 10.0.0.1
+        0x04, 0x38,             // BND.PORT: 1080
+    ];
+    let bnd = AddrPort::V4(Ipv4Addr::new(10, 0, 0, 1), 1080);
+
+    reply_matches(
+        &bytes,
+        ConnReply::new(0x05, Rep::Succeeded, 0x00, ATYP::V4, bnd),
+    );
+}
+
+#[test]
+fn domain_reply() {
+    #[rustfmt::skip]
+    let bytes = [
+        0x05, 0x00, 0x00,
+        0x03,                               // ATYP: DOMAINNAME
+        0x09,                               // name length: 9
+        b'p', b'r', b'o', b'x', b'y', b'.', // BND.ADDR: "proxy.lan"
+        b'l', b'a', b'n',
+        0x04, 0x38,                         // BND.PORT: 1080
+    ];
+    let bnd = AddrPort::Domain("proxy.lan".into(), 1080);
+
+    reply_matches(
+        &bytes,
+        ConnReply::new(0x05, Rep::Succeeded, 0x00, ATYP::DomainName, bnd),
+    );
+}
+
+#[test]
+fn reply_codes() {
+    for (byte, rep) in [
+        (0x00, Rep::Succeeded),
+        (0x01, Rep::GeneralFailure),
+        (0x02, Rep::ConnectionNotAllowed),
+        (0x03, Rep::NetworkUnreachable),
+        (0x04, Rep::HostUnreachable),
+        (0x05, Rep::ConnectionRefused),
+        (0x06, Rep::TTLExpired),
+        (0x07, Rep::CommandNotSupported),
+        (0x08, Rep::AddressTypeNotSupported),
+    ] {
+        let bytes = [0x05, byte, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+        let bnd = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+        reply_matches(&bytes, ConnReply::new(0x05, rep, 0x00, ATYP::V4, bnd));
+    }
+
+    let bytes = [0x05, 0x09, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+    assert!(matches!(
+        ConnReply::try_from(&bytes[..]),
+        Err(SocksError::InvalidReplyCode(0x09))
+    ));
+}
+
+/// The RFC only defines BND.ADDR and BND.PORT for successful replies.
+/// Failure replies keep the full layout with an all-zero IPv4 address, so
+/// clients that always read ten bytes stay in sync.
+#[tokio::test]
+async fn failure_reply_carries_an_unspecified_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+
+    let err = SocksError::UnsupportedCommand(0x02);
+    Socks5::send_error_reply(&mut server, &err).await.unwrap();
+    drop(server);
+
+    let mut bytes = Vec::new();
+    client.read_to_end(&mut bytes).await.unwrap();
+    #[rustfmt::skip]
+    let expected = [
+        0x05, 0x07, 0x00, // VER, REP: command not supported, RSV
+        0x01,             // ATYP: IP V4 address
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ];
+    assert_eq!(bytes, expected);
+}
@@ -0,0 +1,225 @@
+//! Replies to `CONNECT` requests whose destination cannot be dialed.
+
+use simple_socks5::Socks5;
+use simple_socks5::client;
+use simple_socks5::conn::reply::Rep;
+use simple_socks5::connector::{ConnectErrorPolicy, ConnectFuture, Connector, Destination};
+use simple_socks5::error::SocksError;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::resolver::{ResolveFuture, Resolver};
+use simple_socks5::testkit::{self, MockConnector, MockOutcome};
+use std::io::{Error, ErrorKind};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Per-candidate errors in dialing order, mixing every ranked kind.
+fn mixed() -> Vec<Error> {
+    vec![
+        Error::from(ErrorKind::NetworkUnreachable),
+        Error::from(ErrorKind::TimedOut),
+        Error::from(ErrorKind::ConnectionRefused),
+        Error::from(ErrorKind::HostUnreachable),
+        Error::other("no route"),
+    ]
+}
+
+fn pick(policy: ConnectErrorPolicy, errors: Vec<Error>) -> ErrorKind {
+    policy.select(errors).unwrap().kind()
+}
+
+#[test]
+fn first_and_last_follow_dialing_order() {
+    assert_eq!(
+        pick(ConnectErrorPolicy::FirstError, mixed()),
+        ErrorKind::NetworkUnreachable
+    );
+    assert_eq!(
+        pick(ConnectErrorPolicy::LastError, mixed()),
+        ErrorKind::Other
+    );
+}
+
+#[test]
+fn most_specific_ranks_error_kinds() {
+    assert_eq!(
+        pick(ConnectErrorPolicy::MostSpecific, mixed()),
+        ErrorKind::ConnectionRefused
+    );
+
+    let without_refused = vec![
+        Error::from(ErrorKind::TimedOut),
+        Error::from(ErrorKind::NetworkUnreachable),
+        Error::from(ErrorKind::HostUnreachable),
+    ];
+    assert_eq!(
+        pick(ConnectErrorPolicy::MostSpecific, without_refused),
+        ErrorKind::HostUnreachable
+    );
+
+    let unranked = vec![Error::other("first"), Error::other("second")];
+    let picked = ConnectErrorPolicy::MostSpecific.select(unranked).unwrap();
+    assert_eq!(picked.to_string(), "second");
+}
+
+#[test]
+fn no_candidates_selects_nothing() {
+    assert!(ConnectErrorPolicy::FirstError.select(Vec::new()).is_none());
+    assert!(
+        ConnectErrorPolicy::MostSpecific
+            .select(Vec::new())
+            .is_none()
+    );
+}
+
+/// Fails every candidate with the same error kind.
+struct Failing(ErrorKind);
+
+impl Connector for Failing {
+    fn connect<'a>(&'a self, _dst: &'a Destination) -> ConnectFuture<'a> {
+        Box::pin(async move { Err(Error::from(self.0)) })
+    }
+}
+
+#[tokio::test]
+async fn selected_error_is_replied() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_connector(Failing(ErrorKind::HostUnreachable));
+        server.set_connect_reply_on_partial_success(ConnectErrorPolicy::MostSpecific);
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let dst = AddrPort::Domain("localhost".into(), 9);
+    let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::HostUnreachable)
+    ));
+}
+
+#[tokio::test]
+async fn hung_dial_times_out_with_ttl_expired() {
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, 9);
+    let mock = MockConnector::new();
+    mock.script((Ipv4Addr::LOCALHOST, 9).into(), MockOutcome::Hang);
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_connector(mock);
+        server.set_connect_timeout(Duration::from_millis(100));
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(err, SocksError::UpstreamRejected(Rep::TTLExpired)));
+}
+
+#[tokio::test]
+async fn refused_dial_is_answered_with_connection_refused() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_connect_timeout(Duration::from_secs(5));
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    // Bind and drop a listener to find a port nothing listens on.
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::from(closed.local_addr().unwrap());
+    drop(closed);
+
+    let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::ConnectionRefused)
+    ));
+}
+
+#[tokio::test]
+async fn oversized_domain_is_rejected_before_contacting_the_proxy() {
+    // Nothing listens here, so reaching the proxy would fail with an I/O error.
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = closed.local_addr().unwrap().to_string();
+    drop(closed);
+
+    let dst = AddrPort::Domain("x".repeat(256), 80);
+    let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(err, SocksError::InvalidDomain(name) if name == "x".repeat(256)));
+}
+
+#[tokio::test]
+async fn egress_bind_sets_the_source_address() {
+    let egress = Ipv4Addr::new(127, 0, 0, 2);
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_egress_bind(egress.into());
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::from(target.local_addr().unwrap());
+    let (_stream, bnd) = client::connect(&proxy, &dst, None).await.unwrap();
+
+    let (_, source) = target.accept().await.unwrap();
+    assert_eq!(source.ip(), egress);
+    assert_eq!(bnd, AddrPort::from(source));
+}
+
+#[tokio::test]
+async fn egress_bind_of_another_family_is_a_general_failure() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_egress_bind(Ipv6Addr::LOCALHOST.into());
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::from(target.local_addr().unwrap());
+    let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::GeneralFailure)
+    ));
+}
+
+/// Fails every lookup.
+struct Unresolvable;
+
+impl Resolver for Unresolvable {
+    fn resolve<'a>(&'a self, _host: &'a str, _port: u16) -> ResolveFuture<'a> {
+        Box::pin(async { Err(Error::from(ErrorKind::NotFound)) })
+    }
+}
+
+#[tokio::test]
+async fn lookup_and_dial_failures_are_told_apart() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.set_resolver(Unresolvable);
+
+    let dst = AddrPort::Domain("nowhere.example".into(), 80);
+    let err = server.connect(&dst).await.unwrap_err();
+    assert!(matches!(
+        &err,
+        SocksError::DnsResolution { host, source }
+            if host == "nowhere.example" && source.kind() == ErrorKind::NotFound
+    ));
+
+    server.set_connector(Failing(ErrorKind::ConnectionRefused));
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, 9);
+    let err = server.connect(&dst).await.unwrap_err();
+    assert!(matches!(
+        &err,
+        SocksError::ConnectFailed { dst: failed, source }
+            if *failed == dst && source.kind() == ErrorKind::ConnectionRefused
+    ));
+    assert_eq!(err.to_rep(), Some(Rep::ConnectionRefused));
+}
@@ -0,0 +1,113 @@
+//! Checks of the buffered handshake in `Connection`.
+
+use simple_socks5::auth::request::AuthRequest;
+use simple_socks5::conn::request::{CMD, ConnRequest};
+use simple_socks5::connection::Connection;
+use simple_socks5::error::SocksError;
+use simple_socks5::msg::method::Method;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::{ATYP, Socks5};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Version message, credentials and a `CONNECT` request, followed by
+/// `payload`.
+fn handshake(payload: &[u8]) -> Vec<u8> {
+    let dst = AddrPort::Domain("example.com".into(), 443);
+    let mut bytes = vec![0x05, 0x01, 0x02];
+    bytes.extend(AuthRequest::new("alice".into(), "secret".into()).to_bytes());
+    bytes.extend(
+        ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::DomainName, dst)
+            .to_bytes()
+            .unwrap(),
+    );
+    bytes.extend(payload);
+    bytes
+}
+
+/// A server accepting `alice`/`secret`, with one client connected to it.
+async fn accepted() -> (Socks5, Connection, TcpStream) {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_userpass(|u, p| u == "alice" && p == "secret");
+    let client = TcpStream::connect(server.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (stream, _) = server.accept().await.unwrap();
+    (server, Connection::new(stream), client)
+}
+
+#[tokio::test]
+async fn whole_handshake_in_one_write() {
+    let (server, mut conn, mut client) = accepted().await;
+    client.write_all(&handshake(b"early")).await.unwrap();
+
+    assert_eq!(conn.negotiate(&server).await.unwrap(), Method::userpass());
+    let auth = conn.authenticate(&server).await.unwrap();
+    assert_eq!(auth.username.as_deref(), Some("alice"));
+    let req = conn.read_request(&server).await.unwrap();
+    assert_eq!(req.dst, AddrPort::Domain("example.com".into(), 443));
+
+    let (_, early) = conn.into_parts();
+    assert_eq!(early, b"early");
+
+    let mut replies = [0; 4];
+    client.read_exact(&mut replies).await.unwrap();
+    assert_eq!(replies, [0x05, 0x02, 0x01, 0x00]);
+}
+
+#[tokio::test]
+async fn handshake_one_byte_at_a_time() {
+    let (server, mut conn, mut client) = accepted().await;
+    client.set_nodelay(true).unwrap();
+    let bytes = handshake(&[]);
+    let feed = tokio::spawn(async move {
+        for b in bytes {
+            client.write_all(&[b]).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        client
+    });
+
+    let req = conn.read_request(&server).await.unwrap();
+    assert_eq!(req.cmd, CMD::Connect);
+    assert!(conn.buffered().is_empty());
+    feed.await.unwrap();
+}
+
+#[tokio::test]
+async fn steps_cannot_be_repeated() {
+    let (server, mut conn, mut client) = accepted().await;
+    client.write_all(&handshake(&[])).await.unwrap();
+
+    conn.negotiate(&server).await.unwrap();
+    assert!(matches!(
+        conn.negotiate(&server).await,
+        Err(SocksError::HandshakeOutOfOrder)
+    ));
+    conn.read_request(&server).await.unwrap();
+    assert!(matches!(
+        conn.authenticate(&server).await,
+        Err(SocksError::HandshakeOutOfOrder)
+    ));
+}
+
+#[tokio::test]
+async fn failed_authentication_ends_the_handshake() {
+    let (server, mut conn, mut client) = accepted().await;
+    let mut bytes = vec![0x05, 0x01, 0x02];
+    bytes.extend(AuthRequest::new("alice".into(), "guess".into()).to_bytes());
+    client.write_all(&bytes).await.unwrap();
+
+    assert!(matches!(
+        conn.authenticate(&server).await,
+        Err(SocksError::AuthFailed(_))
+    ));
+    assert!(matches!(
+        conn.read_request(&server).await,
+        Err(SocksError::HandshakeOutOfOrder)
+    ));
+
+    let mut replies = [0; 4];
+    client.read_exact(&mut replies).await.unwrap();
+    assert_eq!(replies, [0x05, 0x02, 0x01, 0x01]);
+}
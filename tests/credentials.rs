@@ -0,0 +1,108 @@
+//! End-to-end checks of username/password logins against a credential
+//! file.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use simple_socks5::auth::store::CredentialStore;
+use simple_socks5::client;
+use simple_socks5::error::SocksError;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::testkit;
+
+/// Hashes `passwd` with cheap parameters so the tests stay fast.
+fn hash(passwd: &str) -> String {
+    let config = argon2::Config {
+        mem_cost: 64,
+        time_cost: 1,
+        ..argon2::Config::rfc9106_low_mem()
+    };
+    argon2::hash_encoded(passwd.as_bytes(), b"saltsalt", &config).unwrap()
+}
+
+/// Writes `contents` to a file unique to `name` in the temp directory.
+fn credential_file(name: &str, contents: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("simple-socks5-{}-{name}.txt", std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn logins_are_checked_against_the_file_and_reloaded() {
+    let path = credential_file(
+        "reload",
+        &format!(
+            "# users\nalice:{}\n\nbob:{}\n",
+            hash("secret"),
+            hash("hunter2")
+        ),
+    );
+    let mut store = None;
+    let proxy = testkit::spawn_server(|server| {
+        store = Some(server.allow_userpass_from_file(&path).unwrap());
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let store = store.unwrap();
+    assert_eq!(store.len(), 2);
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    client::connect(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap();
+    for (uname, passwd) in [("alice", "hunter2"), ("carol", "secret")] {
+        let err = client::connect(&proxy, &dst, Some((uname, passwd)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SocksError::AuthFailed(_)));
+    }
+
+    fs::write(&path, format!("carol:{}\n", hash("secret"))).unwrap();
+    store.reload().unwrap();
+    client::connect(&proxy, &dst, Some(("carol", "secret")))
+        .await
+        .unwrap();
+    let err = client::connect(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SocksError::AuthFailed(_)));
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn malformed_line_is_reported_and_reload_keeps_the_old_entries() {
+    let path = credential_file("malformed", &format!("alice:{}\n", hash("secret")));
+    let store = CredentialStore::load(&path).unwrap();
+
+    fs::write(&path, format!("alice:{}\nbob:hunter2\n", hash("secret"))).unwrap();
+    assert!(matches!(
+        store.reload(),
+        Err(SocksError::InvalidCredentialFile(2))
+    ));
+    assert!(store.verify("alice", "secret"));
+    assert!(matches!(
+        CredentialStore::load(&path),
+        Err(SocksError::InvalidCredentialFile(2))
+    ));
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn unknown_users_pay_for_a_hash_and_are_rejected() {
+    let path = credential_file("unknown", &format!("alice:{}\n", hash("secret")));
+    let store = CredentialStore::load(&path).unwrap();
+
+    // The dummy hash is of the empty password, and must not let it in.
+    for passwd in ["", "secret"] {
+        let started = Instant::now();
+        assert!(!store.verify("mallory", passwd));
+        assert!(started.elapsed() >= Duration::from_millis(1));
+    }
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,35 @@
+//! End-to-end checks of the lifecycle event channel.
+
+use simple_socks5::Socks5;
+use simple_socks5::event::Event;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn full_channel_drops_and_counts_events() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    server.set_event_buffer(1);
+    let mut events = server.events();
+    let addr = server.local_addr().unwrap();
+
+    let served = tokio::spawn(async move {
+        for _ in 0..3 {
+            let (stream, _) = server.accept().await.unwrap();
+            let _ = server.handle_client(stream).await;
+        }
+        server
+    });
+
+    for _ in 0..3 {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut selection = [0u8; 2];
+        client.read_exact(&mut selection).await.unwrap();
+    }
+    let server = served.await.unwrap();
+
+    assert!(matches!(events.recv().await, Some(Event::Connected { .. })));
+    assert!(events.try_recv().is_err());
+    assert_eq!(server.metrics().events_dropped, 2);
+}
@@ -0,0 +1,134 @@
+//! Property tests feeding the message parsers untrusted input.
+//!
+//! Every parser must return `Ok` or `Err` for any byte slice, never panic,
+//! and read back exactly what the matching serializer wrote.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use proptest::prelude::*;
+use simple_socks5::auth::reply::AuthReply;
+use simple_socks5::auth::request::AuthRequest;
+use simple_socks5::conn::reply::{ConnReply, Rep};
+use simple_socks5::conn::request::{CMD, ConnRequest};
+use simple_socks5::msg::message::{MethodSelection, VersionMessage};
+use simple_socks5::msg::method::Method;
+use simple_socks5::parse::AddrPort;
+use tokio::runtime::Builder;
+
+/// Addresses of every type, with domain names of up to 255 bytes.
+fn addr_port() -> impl Strategy<Value = AddrPort> {
+    prop_oneof![
+        any::<(u32, u16)>().prop_map(|(ip, port)| AddrPort::V4(Ipv4Addr::from(ip), port)),
+        any::<(u128, u16)>().prop_map(|(ip, port)| AddrPort::V6(Ipv6Addr::from(ip), port)),
+        ("[a-z0-9.-]{0,255}", any::<u16>()).prop_map(|(host, port)| AddrPort::Domain(host, port)),
+    ]
+}
+
+/// Addresses of every type, with domain names of any characters, biased
+/// towards those that need quoting in the text form.
+fn text_addr_port() -> impl Strategy<Value = AddrPort> {
+    let c = prop_oneof![
+        any::<char>(),
+        prop::sample::select(vec![':', '[', ']', '"', '\\', ' ', '.', '1']),
+    ];
+    prop_oneof![
+        addr_port(),
+        (prop::collection::vec(c, 0..=63), any::<u16>())
+            .prop_map(|(host, port)| AddrPort::Domain(host.into_iter().collect(), port)),
+    ]
+}
+
+/// Byte slices biased towards the start of a well-formed message, so the
+/// parsers get past their first checks.
+fn message_bytes(first: u8) -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..300),
+        prop::collection::vec(any::<u8>(), 0..300).prop_map(move |mut bytes| {
+            bytes.insert(0, first);
+            bytes
+        }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn conn_request_parser_never_panics(bytes in message_bytes(0x05)) {
+        let _ = ConnRequest::try_from(&bytes[..]);
+    }
+
+    #[test]
+    fn conn_reply_parser_never_panics(bytes in message_bytes(0x05)) {
+        let _ = ConnReply::try_from(&bytes[..]);
+    }
+
+    #[test]
+    fn version_message_parser_never_panics(bytes in message_bytes(0x05)) {
+        let _ = VersionMessage::try_from(&bytes[..]);
+        let _ = MethodSelection::try_from(&bytes[..]);
+    }
+
+    #[test]
+    fn auth_request_parser_never_panics(bytes in message_bytes(0x01)) {
+        let _ = AuthRequest::try_from(&bytes[..]);
+        let _ = AuthReply::try_from(&bytes[..]);
+    }
+
+    #[test]
+    fn stream_readers_never_panic(bytes in message_bytes(0x05)) {
+        let rt = Builder::new_current_thread().build().unwrap();
+        rt.block_on(async {
+            let _ = ConnRequest::read_from(&mut &bytes[..]).await;
+            let _ = VersionMessage::read_from(&mut &bytes[..]).await;
+            let _ = AuthRequest::read_from(&mut &bytes[..]).await;
+        });
+    }
+
+    #[test]
+    fn conn_request_round_trips(
+        cmd in prop::sample::select(vec![CMD::Connect, CMD::Bind, CMD::UdpAssociate]),
+        dst in addr_port(),
+    ) {
+        let request = ConnRequest::new(0x05, cmd, 0x00, dst.atyp(), dst);
+        prop_assert_eq!(ConnRequest::try_from(&request.to_bytes().unwrap()[..]).unwrap(), request);
+    }
+
+    #[test]
+    fn conn_reply_round_trips(
+        rep in prop::sample::select(vec![
+            Rep::Succeeded,
+            Rep::GeneralFailure,
+            Rep::ConnectionNotAllowed,
+            Rep::NetworkUnreachable,
+            Rep::HostUnreachable,
+            Rep::ConnectionRefused,
+            Rep::TTLExpired,
+            Rep::CommandNotSupported,
+            Rep::AddressTypeNotSupported,
+        ]),
+        bnd in addr_port(),
+    ) {
+        let reply = ConnReply::new(0x05, rep, 0x00, bnd.atyp(), bnd);
+        prop_assert_eq!(ConnReply::try_from(&reply.to_bytes().unwrap()[..]).unwrap(), reply);
+    }
+
+    #[test]
+    fn addr_port_text_form_round_trips(dst in text_addr_port()) {
+        prop_assert_eq!(dst.to_string().parse::<AddrPort>().unwrap(), dst);
+    }
+
+    /// Serialization sorts and deduplicates the methods, so only such lists
+    /// come back unchanged.
+    #[test]
+    fn version_message_round_trips(methods in prop::collection::btree_set(any::<u8>(), 1..=255)) {
+        let methods: Vec<Method> = methods.into_iter().map(|b| Method::from_u8(b).unwrap()).collect();
+        let msg = VersionMessage::new(methods);
+        let bytes = msg.to_bytes().unwrap();
+        prop_assert_eq!(VersionMessage::try_from(&bytes[..]).unwrap(), msg);
+    }
+
+    #[test]
+    fn auth_request_round_trips(uname in "\\PC{1,60}", passwd in "\\PC{1,60}") {
+        let request = AuthRequest::new(uname, passwd);
+        prop_assert_eq!(AuthRequest::try_from(&request.to_bytes()[..]).unwrap(), request);
+    }
+}
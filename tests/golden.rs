@@ -0,0 +1,441 @@
+//! Golden wire-format vectors for every message type.
+//!
+//! Each test serializes a constructed message and compares it to hardcoded
+//! bytes, then parses the same bytes back and compares the result to the
+//! constructed value. A failure here means the encoding on the wire changed.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use simple_socks5::ATYP;
+use simple_socks5::auth::reply::{AuthReply, AuthStatus};
+use simple_socks5::auth::request::AuthRequest;
+use simple_socks5::conn::reply::{ConnReply, Rep};
+use simple_socks5::conn::request::{CMD, ConnRequest};
+use simple_socks5::error::SocksError;
+use simple_socks5::msg::message::{MethodSelection, VersionMessage};
+use simple_socks5::msg::method::{FixedMethod, Method};
+use simple_socks5::parse::AddrPort;
+
+const NO_AUTH: Method = Method::Fixed(FixedMethod::NoAuth);
+const USE_PASS: Method = Method::Fixed(FixedMethod::UsePass);
+
+#[test]
+fn version_message_no_auth() {
+    let golden = [0x05, 0x01, 0x00];
+    let msg = VersionMessage::new(vec![NO_AUTH]);
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(VersionMessage::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn version_message_no_auth_and_userpass() {
+    let golden = [0x05, 0x02, 0x00, 0x02];
+    let msg = VersionMessage::new(vec![NO_AUTH, USE_PASS]);
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(VersionMessage::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn method_selection_userpass() {
+    let golden = [0x05, 0x02];
+    let msg = MethodSelection::new(USE_PASS);
+
+    assert_eq!(msg.to_bytes(), golden);
+    assert_eq!(MethodSelection::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn method_selection_no_acceptable() {
+    let golden = [0x05, 0xFF];
+    let msg = MethodSelection::new(Method::Fixed(FixedMethod::NoAcceptable));
+
+    assert_eq!(msg.to_bytes(), golden);
+    assert_eq!(MethodSelection::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn userpass_auth_request() {
+    let golden = [
+        0x01, 0x05, b'a', b'l', b'i', b'c', b'e', 0x06, b's', b'e', b'c', b'r', b'e', b't',
+    ];
+    let msg = AuthRequest::new("alice".into(), "secret".into());
+
+    assert_eq!(msg.to_bytes(), golden);
+    assert_eq!(AuthRequest::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn userpass_auth_request_round_trips_at_max_length() {
+    let msg = AuthRequest::new("u".repeat(255), "p".repeat(255));
+    let bytes = msg.to_bytes();
+
+    assert_eq!(bytes.len(), 513);
+    assert_eq!(AuthRequest::try_from(&bytes[..]).unwrap(), msg);
+}
+
+#[test]
+fn userpass_auth_request_rejects_empty_fields() {
+    let empty_user = [0x01, 0x00, 0x01, b'p'];
+    let empty_pass = [0x01, 0x01, b'u', 0x00];
+
+    assert!(matches!(
+        AuthRequest::try_from(&empty_user[..]),
+        Err(SocksError::AuthFailed(_))
+    ));
+    assert!(matches!(
+        AuthRequest::try_from(&empty_pass[..]),
+        Err(SocksError::AuthFailed(_))
+    ));
+}
+
+#[test]
+fn userpass_auth_reply_success() {
+    let golden = [0x01, 0x00];
+    let msg = AuthReply::new(AuthStatus::Success);
+
+    assert_eq!(msg.to_bytes(), golden);
+    assert_eq!(AuthReply::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn userpass_auth_reply_failure() {
+    let golden = [0x01, 0x01];
+    let msg = AuthReply::new(AuthStatus::Failure);
+
+    assert_eq!(msg.to_bytes(), golden);
+    assert_eq!(AuthReply::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn userpass_auth_reply_failure_code() {
+    let golden = [0x01, 0x02];
+    let msg = AuthReply::new(AuthStatus::FailureCode(0x02));
+
+    assert_eq!(msg.to_bytes(), golden);
+    assert_eq!(AuthReply::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_request_ipv4() {
+    let golden = [0x05, 0x01, 0x00, 0x01, 192, 168, 1, 10, 0x00, 0x50];
+    let msg = ConnRequest::new(
+        0x05,
+        CMD::Connect,
+        0x00,
+        ATYP::V4,
+        AddrPort::V4(Ipv4Addr::new(192, 168, 1, 10), 80),
+    );
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(ConnRequest::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_request_ipv6() {
+    let golden = [
+        0x05, 0x01, 0x00, 0x04, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+        0x01, 0xBB,
+    ];
+    let msg = ConnRequest::new(
+        0x05,
+        CMD::Connect,
+        0x00,
+        ATYP::V6,
+        AddrPort::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 443),
+    );
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(ConnRequest::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_request_domain() {
+    let golden = [
+        0x05, 0x01, 0x00, 0x03, 0x0B, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o',
+        b'm', 0x01, 0xBB,
+    ];
+    let msg = ConnRequest::new(
+        0x05,
+        CMD::Connect,
+        0x00,
+        ATYP::DomainName,
+        AddrPort::Domain("example.com".into(), 443),
+    );
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(ConnRequest::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_reply_ipv4() {
+    let golden = [0x05, 0x00, 0x00, 0x01, 10, 0, 0, 1, 0x04, 0x38];
+    let msg = ConnReply::new(
+        0x05,
+        Rep::Succeeded,
+        0x00,
+        ATYP::V4,
+        AddrPort::V4(Ipv4Addr::new(10, 0, 0, 1), 1080),
+    );
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(ConnReply::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_reply_ipv6() {
+    let golden = [
+        0x05, 0x00, 0x00, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x04, 0x38,
+    ];
+    let msg = ConnReply::new(
+        0x05,
+        Rep::Succeeded,
+        0x00,
+        ATYP::V6,
+        AddrPort::V6(Ipv6Addr::LOCALHOST, 1080),
+    );
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(ConnReply::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_reply_domain() {
+    let golden = [
+        0x05, 0x00, 0x00, 0x03, 0x09, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't', 0x04,
+        0x38,
+    ];
+    let msg = ConnReply::new(
+        0x05,
+        Rep::Succeeded,
+        0x00,
+        ATYP::DomainName,
+        AddrPort::Domain("localhost".into(), 1080),
+    );
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(ConnReply::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_reply_failure() {
+    let golden = [0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0x00, 0x00];
+    let msg = ConnReply::new(
+        0x05,
+        Rep::ConnectionRefused,
+        0x00,
+        ATYP::V4,
+        AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0),
+    );
+
+    assert_eq!(msg.to_bytes().unwrap(), golden);
+    assert_eq!(ConnReply::try_from(&golden[..]).unwrap(), msg);
+}
+
+#[test]
+fn connect_reply_round_trips_every_rep_and_atyp() {
+    let reps = [
+        Rep::Succeeded,
+        Rep::GeneralFailure,
+        Rep::ConnectionNotAllowed,
+        Rep::NetworkUnreachable,
+        Rep::HostUnreachable,
+        Rep::ConnectionRefused,
+        Rep::TTLExpired,
+        Rep::CommandNotSupported,
+        Rep::AddressTypeNotSupported,
+    ];
+    let bnds = [
+        (ATYP::V4, AddrPort::V4(Ipv4Addr::new(192, 0, 2, 7), 65535)),
+        (ATYP::V6, AddrPort::V6("2001:db8::7".parse().unwrap(), 1)),
+        (ATYP::DomainName, AddrPort::Domain("a".into(), 0)),
+        (ATYP::DomainName, AddrPort::Domain("x".repeat(255), 8080)),
+    ];
+
+    for rep in reps {
+        for (atyp, bnd) in &bnds {
+            let msg = ConnReply::new(0x05, rep, 0x00, *atyp, bnd.clone());
+            let bytes = msg.to_bytes().unwrap();
+            assert_eq!(ConnReply::try_from(&bytes[..]).unwrap(), msg, "{msg}");
+        }
+    }
+}
+
+#[test]
+fn domain_constructor_enforces_the_length_byte() {
+    let longest = AddrPort::domain("x".repeat(255), 80).unwrap();
+    let req = ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::DomainName, longest);
+    let bytes = req.to_bytes().unwrap();
+    assert_eq!(bytes[4], 255);
+    assert_eq!(bytes.len(), 4 + 1 + 255 + 2);
+
+    assert!(matches!(
+        AddrPort::domain("x".repeat(256), 80),
+        Err(SocksError::InvalidDomain(name)) if name == "x".repeat(256)
+    ));
+
+    // Built directly, the variant still cannot reach the wire.
+    let long = AddrPort::Domain("x".repeat(256), 80);
+    let req = ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::DomainName, long.clone());
+    assert!(matches!(req.to_bytes(), Err(SocksError::InvalidDomain(_))));
+    let reply = ConnReply::new(0x05, Rep::Succeeded, 0x00, ATYP::DomainName, long);
+    assert!(matches!(
+        reply.to_bytes(),
+        Err(SocksError::InvalidDomain(_))
+    ));
+}
+
+#[test]
+fn addr_port_text_form() {
+    let longest = "x".repeat(255);
+    let cases = [
+        (
+            AddrPort::V4(Ipv4Addr::new(1, 2, 3, 4), 80),
+            "1.2.3.4:80".to_string(),
+        ),
+        (AddrPort::V6(Ipv6Addr::LOCALHOST, 443), "[::1]:443".into()),
+        (
+            AddrPort::Domain("example.com".into(), 0),
+            "example.com:0".into(),
+        ),
+        (
+            AddrPort::Domain(longest.clone(), 80),
+            format!("{longest}:80"),
+        ),
+        (
+            AddrPort::Domain("1.2.3.4".into(), 80),
+            r#""1.2.3.4":80"#.into(),
+        ),
+        (AddrPort::Domain("::1".into(), 80), r#""::1":80"#.into()),
+        (AddrPort::Domain("[::1]".into(), 80), r#""[::1]":80"#.into()),
+        (AddrPort::Domain("a b\t".into(), 80), "\"a b\t\":80".into()),
+        (
+            AddrPort::Domain(r#"q"\"#.into(), 80),
+            r#""q\"\\":80"#.into(),
+        ),
+        (AddrPort::Domain(String::new(), 80), r#""":80"#.into()),
+    ];
+    for (addr, text) in cases {
+        assert_eq!(addr.to_string(), text);
+        assert_eq!(text.parse::<AddrPort>().unwrap(), addr, "{text}");
+    }
+
+    // Leading zeros and brackets without a scope are accepted on input.
+    assert_eq!(
+        "[2001:0db8:0000::0001]:80".parse::<AddrPort>().unwrap(),
+        AddrPort::V6("2001:db8::1".parse().unwrap(), 80)
+    );
+    for bad in [
+        "[fe80::1%eth0]:80",
+        "[::1]",
+        "::1:80",
+        "a b:80",
+        r#""a"b":80"#,
+        r#""a\x":80"#,
+        r#""a:80"#,
+    ] {
+        assert!(
+            matches!(bad.parse::<AddrPort>(), Err(SocksError::InvalidAddress(_))),
+            "{bad}"
+        );
+    }
+    assert!(matches!(
+        format!("\"{}\":80", "x".repeat(256)).parse::<AddrPort>(),
+        Err(SocksError::InvalidDomain(_))
+    ));
+}
+
+#[test]
+fn connect_reply_with_unknown_rep_is_rejected() {
+    let bytes = [0x05, 0x09, 0x00, 0x01, 10, 0, 0, 1, 0x04, 0x38];
+
+    assert!(matches!(
+        ConnReply::try_from(&bytes[..]),
+        Err(SocksError::InvalidReplyCode(0x09))
+    ));
+}
+
+#[test]
+fn truncated_connect_reply_is_too_short() {
+    let full = [
+        &[0x05, 0x00, 0x00, 0x01, 10, 0, 0, 1, 0x04, 0x38][..],
+        &[
+            0x05, 0x00, 0x00, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x04, 0x38,
+        ][..],
+        &[0x05, 0x00, 0x00, 0x03, 0x03, b'f', b'o', b'o', 0x04, 0x38][..],
+    ];
+
+    for reply in full {
+        for len in 0..reply.len() {
+            assert!(
+                matches!(
+                    ConnReply::try_from(&reply[..len]),
+                    Err(SocksError::ReplyTooShort)
+                ),
+                "{:02x?}",
+                &reply[..len]
+            );
+        }
+    }
+}
+
+#[test]
+fn connect_reply_display_lists_every_field() {
+    let msg = ConnReply::new(
+        0x05,
+        Rep::HostUnreachable,
+        0x00,
+        ATYP::V4,
+        AddrPort::V4(Ipv4Addr::new(10, 0, 0, 1), 1080),
+    );
+
+    assert_eq!(
+        msg.to_string(),
+        "SOCKS5 Reply {\n  REP : host unreachable\n  ATYP: IPv4\n  BND : 10.0.0.1:1080\n  VER : 5\n  RSV : 0\n}"
+    );
+}
+
+#[test]
+fn command_constructors_derive_the_address_type() {
+    let dsts = [
+        (ATYP::V4, AddrPort::V4(Ipv4Addr::new(192, 0, 2, 7), 80)),
+        (ATYP::V6, AddrPort::V6(Ipv6Addr::LOCALHOST, 443)),
+        (
+            ATYP::DomainName,
+            AddrPort::Domain("example.com".into(), 8080),
+        ),
+    ];
+
+    for (atyp, dst) in &dsts {
+        let built = [
+            (CMD::Connect, ConnRequest::connect(dst.clone())),
+            (CMD::Bind, ConnRequest::bind(dst.clone())),
+            (CMD::UdpAssociate, ConnRequest::udp_associate(dst.clone())),
+        ];
+        for (cmd, req) in built {
+            assert_eq!(req, ConnRequest::new(0x05, cmd, 0x00, *atyp, dst.clone()));
+            assert_eq!(
+                ConnRequest::try_from(&req.to_bytes().unwrap()[..]).unwrap(),
+                req
+            );
+        }
+    }
+}
+
+#[test]
+fn truncated_connect_request_domain_reports_what_arrived() {
+    let full = [0x05, 0x01, 0x00, 0x03, 0x03, b'f', b'o', b'o', 0x00, 0x50];
+    let expected = ["", "", "f", "fo", "foo", "foo"];
+
+    for (len, name) in (4..full.len()).zip(expected) {
+        assert!(
+            matches!(
+                ConnRequest::try_from(&full[..len]),
+                Err(SocksError::InvalidDomain(partial)) if partial == name
+            ),
+            "{:02x?}",
+            &full[..len]
+        );
+    }
+}
@@ -0,0 +1,519 @@
+//! End-to-end checks of method negotiation.
+
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use simple_socks5::auth::custom::{MethodFuture, MethodHandler, MethodStream};
+use simple_socks5::auth::request::AuthRequest;
+use simple_socks5::client;
+use simple_socks5::conn::reply::Rep;
+use simple_socks5::conn::request::{CMD, ConnRequest, MAX_REQUEST_LEN};
+use simple_socks5::error::SocksError;
+use simple_socks5::handler::RelayHandler;
+use simple_socks5::msg::method::{FixedMethod, Method};
+use simple_socks5::parse::AddrPort;
+use simple_socks5::testkit;
+use simple_socks5::{ATYP, Socks5};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn only_gssapi_offered_is_answered_with_no_acceptable() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    let addr = server.local_addr().unwrap();
+
+    let handled = tokio::spawn(async move {
+        let (stream, _) = server.accept().await.unwrap();
+        server.handle_client(stream).await
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x01]).await.unwrap();
+
+    let mut reply = Vec::new();
+    client.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x05, 0xFF]);
+
+    let result = handled.await.unwrap();
+    assert!(matches!(result, Err(SocksError::NoAcceptableMethod)));
+}
+
+#[tokio::test]
+async fn authenticate_closes_after_no_acceptable() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    let addr = server.local_addr().unwrap();
+
+    let (done, hold) = tokio::sync::oneshot::channel::<()>();
+    let handled = tokio::spawn(async move {
+        let (mut stream, _) = server.accept().await.unwrap();
+        let result = server.authenticate(&mut stream).await;
+        // Keep the socket open: the client must see EOF from the shutdown.
+        let _ = hold.await;
+        result
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x01]).await.unwrap();
+
+    let mut reply = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut reply))
+        .await
+        .expect("connection left open")
+        .unwrap();
+    assert_eq!(reply, [0x05, 0xFF]);
+
+    drop(done);
+    let result = handled.await.unwrap();
+    assert!(matches!(result, Err(SocksError::NoAcceptableMethod)));
+}
+
+#[tokio::test]
+async fn non_socks_traffic_is_rejected_without_consuming_it() {
+    let server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+    let (mut stream, _) = server.accept().await.unwrap();
+    let result = Socks5::peek_version(&stream).await;
+    assert!(matches!(result, Err(SocksError::UnsupportedVersion(b'G'))));
+
+    let mut line = [0; 16];
+    stream.read_exact(&mut line).await.unwrap();
+    assert_eq!(&line, b"GET / HTTP/1.1\r\n");
+}
+
+#[tokio::test]
+async fn maximum_length_credentials_are_accepted() {
+    let user = "u".repeat(255);
+    let pass = "p".repeat(255);
+
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    let (expected_user, expected_pass) = (user.clone(), pass.clone());
+    server.allow_userpass(move |u, p| u == expected_user && p == expected_pass);
+    let addr = server.local_addr().unwrap();
+
+    let handled = tokio::spawn(async move {
+        let (mut stream, _) = server.accept().await.unwrap();
+        server.authenticate(&mut stream).await
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+    let mut selection = [0; 2];
+    client.read_exact(&mut selection).await.unwrap();
+    assert_eq!(selection, [0x05, 0x02]);
+
+    let request = AuthRequest::new(user, pass).to_bytes();
+    client.write_all(&request).await.unwrap();
+    let mut reply = [0; 2];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x01, 0x00]);
+
+    let auth = handled.await.unwrap().unwrap();
+    assert_eq!(auth.method, Method::Fixed(FixedMethod::UsePass));
+    assert_eq!(auth.username, Some("u".repeat(255)));
+}
+
+#[tokio::test]
+async fn over_long_username_is_refused_without_calling_the_validator() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    let counted = Arc::clone(&calls);
+    server.allow_userpass(move |_, _| {
+        counted.fetch_add(1, Ordering::Relaxed);
+        true
+    });
+    server.set_max_credential_len(64);
+    let addr = server.local_addr().unwrap();
+
+    let handled = tokio::spawn(async move {
+        let (mut stream, _) = server.accept().await.unwrap();
+        server.authenticate(&mut stream).await
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+    let mut selection = [0; 2];
+    client.read_exact(&mut selection).await.unwrap();
+    assert_eq!(selection, [0x05, 0x02]);
+
+    let request = AuthRequest::new("u".repeat(65), "secret".into()).to_bytes();
+    client.write_all(&request).await.unwrap();
+    let mut reply = [0; 2];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x01, 0x01]);
+
+    assert!(matches!(
+        handled.await.unwrap(),
+        Err(SocksError::AuthFailed(_))
+    ));
+    assert_eq!(calls.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn preferred_userpass_is_selected_over_no_auth() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    server.allow_userpass(|_, _| true);
+    server.set_method_preference(vec![Method::userpass(), Method::no_auth()]);
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = server.accept().await.unwrap();
+        server.authenticate(&mut stream).await
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x02, 0x00, 0x02]).await.unwrap();
+    let mut selection = [0; 2];
+    client.read_exact(&mut selection).await.unwrap();
+    assert_eq!(selection, [0x05, 0x02]);
+}
+
+#[tokio::test]
+async fn no_auth_context_has_no_username() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    let addr = server.local_addr().unwrap();
+
+    let handled = tokio::spawn(async move {
+        let (mut stream, _) = server.accept().await.unwrap();
+        server.authenticate(&mut stream).await
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut selection = [0; 2];
+    client.read_exact(&mut selection).await.unwrap();
+
+    let auth = handled.await.unwrap().unwrap();
+    assert_eq!(auth.method, Method::Fixed(FixedMethod::NoAuth));
+    assert_eq!(auth.username, None);
+}
+
+#[tokio::test]
+async fn static_credentials_accept_only_the_exact_pair() {
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let proxy = testkit::spawn_server(|server| server.allow_userpass_static("alice", "secret"))
+        .await
+        .unwrap()
+        .to_string();
+
+    client::connect(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap();
+    for (uname, passwd) in [("alice", "secre"), ("alice", "secret!"), ("alic", "secret")] {
+        let err = client::connect(&proxy, &dst, Some((uname, passwd)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SocksError::AuthFailed(_)));
+    }
+}
+
+#[tokio::test]
+async fn validator_is_not_called_once_the_failure_limit_is_reached() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let proxy = testkit::spawn_server(|server| {
+        let counted = Arc::clone(&calls);
+        server.allow_userpass(move |_, _| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            false
+        });
+        server.set_auth_rate_limit(3, Duration::from_secs(60));
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, 9);
+    for _ in 0..6 {
+        let err = client::connect(&proxy, &dst, Some(("alice", "guess")))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SocksError::AuthFailed(_)));
+    }
+
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn pipelined_handshake_is_read_message_by_message() {
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let addr =
+        testkit::spawn_server(|server| server.allow_userpass(|u, p| u == "alice" && p == "secret"))
+            .await
+            .unwrap();
+
+    // Version message, credentials, request and payload in a single write.
+    let mut bytes = vec![0x05, 0x01, 0x02];
+    bytes.extend(AuthRequest::new("alice".into(), "secret".into()).to_bytes());
+    bytes.extend(
+        ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::V4, dst)
+            .to_bytes()
+            .unwrap(),
+    );
+    bytes.extend(b"ping");
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&bytes).await.unwrap();
+
+    let mut replies = [0; 2 + 2 + 10];
+    client.read_exact(&mut replies).await.unwrap();
+    assert_eq!(replies[..4], [0x05, 0x02, 0x01, 0x00]);
+    assert_eq!(replies[4..6], [0x05, 0x00]);
+
+    let mut echoed = [0; 4];
+    client.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"ping");
+}
+
+#[tokio::test]
+async fn longest_domain_request_is_read_with_pipelined_data() {
+    let local = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let addr = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        // Nothing resolves a 253-byte name, so send it to the local target.
+        server.set_destination_rewriter(move |_| Some(local.clone()));
+    })
+    .await
+    .unwrap();
+
+    let label = "a".repeat(63);
+    let name = format!("{label}.{label}.{label}.{}", "a".repeat(61));
+    assert_eq!(name.len(), 253);
+    let dst = AddrPort::domain(name, 80).unwrap();
+    let request = ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::DomainName, dst)
+        .to_bytes()
+        .unwrap();
+    assert_eq!(request.len(), 4 + 1 + 253 + 2);
+
+    let mut bytes = vec![0x05, 0x01, 0x00];
+    bytes.extend(request);
+    bytes.extend(vec![b'x'; MAX_REQUEST_LEN]);
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&bytes).await.unwrap();
+
+    let mut replies = [0; 2 + 10];
+    client.read_exact(&mut replies).await.unwrap();
+    assert_eq!(replies[2..4], [0x05, 0x00]);
+
+    let mut echoed = vec![0; MAX_REQUEST_LEN];
+    client.read_exact(&mut echoed).await.unwrap();
+    assert!(echoed.iter().all(|b| *b == b'x'));
+}
+
+/// Sends a `CONNECT` to a local listener with `RSV = 0x01` and returns the
+/// reply code.
+async fn reply_to_nonzero_rsv(strict: bool) -> u8 {
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let addr = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_strict(strict);
+    })
+    .await
+    .unwrap();
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let request = ConnRequest::new(0x05, CMD::Connect, 0x01, ATYP::V4, dst);
+    client
+        .write_all(&request.to_bytes().unwrap())
+        .await
+        .unwrap();
+
+    let mut replies = [0; 2 + 10];
+    client.read_exact(&mut replies).await.unwrap();
+    replies[3]
+}
+
+#[tokio::test]
+async fn nonzero_rsv_is_accepted_unless_strict() {
+    assert_eq!(reply_to_nonzero_rsv(false).await, 0x00);
+    assert_eq!(reply_to_nonzero_rsv(true).await, 0x01);
+}
+
+/// Sends a request for `cmd` to a server running [`RelayHandler`] and
+/// returns the reply code.
+async fn reply_to_command(cmd: CMD, configure: impl FnOnce(&mut Socks5)) -> u8 {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    configure(&mut server);
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.serve(RelayHandler));
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, 9);
+    let request = ConnRequest::new(0x05, cmd, 0x00, ATYP::V4, dst);
+    client
+        .write_all(&request.to_bytes().unwrap())
+        .await
+        .unwrap();
+
+    let mut replies = [0; 2 + 10];
+    client.read_exact(&mut replies).await.unwrap();
+    replies[3]
+}
+
+#[tokio::test]
+async fn disabled_commands_are_not_supported() {
+    assert_eq!(reply_to_command(CMD::UdpAssociate, |_| {}).await, 0x07);
+    assert_eq!(reply_to_command(CMD::Bind, |_| {}).await, 0x07);
+
+    let no_connect = |server: &mut Socks5| server.disable_command(CMD::Connect);
+    assert_eq!(reply_to_command(CMD::Connect, no_connect).await, 0x07);
+
+    // Once enabled, the handler answers instead, here refusing it too.
+    let bind = |server: &mut Socks5| server.enable_command(CMD::Bind);
+    assert_eq!(reply_to_command(CMD::Bind, bind).await, 0x07);
+}
+
+#[tokio::test]
+async fn handshake_runs_over_an_in_memory_stream() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_userpass(|uname, passwd| uname == "alice" && passwd == "secret");
+    let (mut client, mut stream) = tokio::io::duplex(64);
+
+    let handled = tokio::spawn(async move {
+        let auth = server.authenticate(&mut stream).await?;
+        let req = Socks5::read_conn_request(&mut stream).await?;
+        let bnd = AddrPort::V4(Ipv4Addr::LOCALHOST, 1080);
+        Socks5::send_conn_reply(&mut stream, Rep::Succeeded, bnd).await?;
+        Ok::<_, SocksError>((auth, req))
+    });
+
+    client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+    let auth = AuthRequest::new("alice".into(), "secret".into());
+    client.write_all(&auth.to_bytes()).await.unwrap();
+    let dst = AddrPort::domain("example.com", 443).unwrap();
+    let request = ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::DomainName, dst.clone());
+    client
+        .write_all(&request.to_bytes().unwrap())
+        .await
+        .unwrap();
+
+    let mut replies = [0; 2 + 2 + 10];
+    client.read_exact(&mut replies).await.unwrap();
+    assert_eq!(replies[..4], [0x05, 0x02, 0x01, 0x00]);
+    assert_eq!(replies[4..8], [0x05, 0x00, 0x00, 0x01]);
+
+    let (auth, req) = handled.await.unwrap().unwrap();
+    assert_eq!(auth.username.as_deref(), Some("alice"));
+    assert_eq!(req.dst, dst);
+}
+
+#[tokio::test]
+async fn silent_disconnects_are_reported_as_such() {
+    let server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    let addr = server.local_addr().unwrap();
+
+    drop(TcpStream::connect(addr).await.unwrap());
+    let (stream, _) = server.accept().await.unwrap();
+    let result = server.handle_client(stream).await;
+    assert!(matches!(result, Err(SocksError::ClientDisconnected)));
+
+    let empty: &[u8] = &[];
+    assert!(matches!(
+        AuthRequest::read_from(&mut &empty[..]).await,
+        Err(SocksError::ClientDisconnected)
+    ));
+    assert!(matches!(
+        ConnRequest::read_from(&mut &empty[..]).await,
+        Err(SocksError::ClientDisconnected)
+    ));
+    // A message cut short is still a protocol error.
+    assert!(matches!(
+        ConnRequest::read_from(&mut &[0x05, 0x01][..]).await,
+        Err(SocksError::ConnRequestTooShort)
+    ));
+    assert!(matches!(
+        ConnRequest::read_from(&mut &[0x05, 0x01, 0x00, 0x03, 11, b'e'][..]).await,
+        Err(SocksError::ConnRequestTooShort)
+    ));
+}
+
+#[tokio::test]
+async fn reply_cut_short_is_reported_as_too_short() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        stream.write_all(&[0x05, 0x00]).await.unwrap();
+        let mut request = [0; 10];
+        stream.read_exact(&mut request).await.unwrap();
+        // Announce an 11-byte name, then hang up after three of them.
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x03, 11, b'e', b'x', b'a'])
+            .await
+            .unwrap();
+    });
+
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, 80);
+    let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(err, SocksError::ReplyTooShort));
+}
+
+/// Private method: the client sends a one-byte key, answered with `0x00`
+/// and authenticated as `"keyholder"` if it matches, `0x01` otherwise.
+struct SharedKey(u8);
+
+impl MethodHandler for SharedKey {
+    fn authenticate<'a>(&'a self, stream: &'a mut dyn MethodStream) -> MethodFuture<'a> {
+        Box::pin(async move {
+            if stream.read_u8().await? != self.0 {
+                stream.write_all(&[0x01]).await?;
+                return Err(SocksError::AuthFailed("wrong key".into()));
+            }
+            stream.write_all(&[0x00]).await?;
+            Ok(Some("keyholder".into()))
+        })
+    }
+}
+
+#[tokio::test]
+async fn registered_method_runs_its_subnegotiation() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    server.register_method(Method::Private(0x80), SharedKey(0x2A));
+    let server = Arc::new(server);
+
+    for (key, status) in [(0x2A, 0x00), (0x07, 0x01)] {
+        let (mut client, mut stream) = tokio::io::duplex(64);
+        let server = Arc::clone(&server);
+        let handled = tokio::spawn(async move { server.authenticate(&mut stream).await });
+
+        client.write_all(&[0x05, 0x01, 0x80, key]).await.unwrap();
+        let mut replies = [0; 3];
+        client.read_exact(&mut replies).await.unwrap();
+        assert_eq!(replies, [0x05, 0x80, status]);
+
+        let auth = handled.await.unwrap();
+        if status == 0x00 {
+            let auth = auth.unwrap();
+            assert_eq!(auth.method, Method::Private(0x80));
+            assert_eq!(auth.username.as_deref(), Some("keyholder"));
+        } else {
+            assert!(matches!(auth, Err(SocksError::AuthFailed(_))));
+        }
+    }
+
+    let (mut client, mut stream) = tokio::io::duplex(64);
+    let handled = tokio::spawn(async move { server.authenticate(&mut stream).await });
+    client.write_all(&[0x05, 0x02, 0x80, 0x00]).await.unwrap();
+    let mut selection = [0; 2];
+    client.read_exact(&mut selection).await.unwrap();
+    assert_eq!(selection, [0x05, 0x00]);
+    assert_eq!(handled.await.unwrap().unwrap().method, Method::no_auth());
+}
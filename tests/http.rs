@@ -0,0 +1,202 @@
+//! End-to-end checks of HTTP `CONNECT` served on the SOCKS5 port.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use simple_socks5::conn::reply::Rep;
+use simple_socks5::error::SocksError;
+use simple_socks5::msg::method::Method;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::{UpstreamKind, client, testkit};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+async fn round_trip(mut stream: TcpStream) {
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn http_and_socks5_share_the_port() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_http_connect();
+        server.allow_no_auth();
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let (stream, _) = client::connect_http(&proxy, &dst, None).await.unwrap();
+    round_trip(stream).await;
+
+    let (stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    round_trip(stream).await;
+}
+
+#[tokio::test]
+async fn http_credentials_are_checked() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_http_connect();
+        server.allow_userpass(|u, p| u == "alice" && p == "secret");
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let err = client::connect_http(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(err, SocksError::AuthFailed(_)));
+
+    let err = client::connect_http(&proxy, &dst, Some(("alice", "guess")))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SocksError::AuthFailed(_)));
+
+    let (stream, _) = client::connect_http(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap();
+    round_trip(stream).await;
+}
+
+#[tokio::test]
+async fn http_needs_credentials_when_the_preference_requires_userpass() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_http_connect();
+        server.allow_no_auth();
+        server.allow_userpass(|u, p| u == "alice" && p == "secret");
+        server.set_method_preference(vec![Method::userpass()]);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let err = client::connect_http(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(err, SocksError::AuthFailed(_)));
+
+    let (stream, _) = client::connect_http(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap();
+    round_trip(stream).await;
+}
+
+#[tokio::test]
+async fn http_retries_are_locked_out_by_the_auth_limiter() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let seen = calls.clone();
+    let proxy = testkit::spawn_server(move |server| {
+        server.allow_http_connect();
+        server.allow_userpass(move |u, p| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            u == "alice" && p == "secret"
+        });
+        server.set_auth_rate_limit(2, Duration::from_secs(60));
+        server.set_max_credential_len(16);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    // Over-long credentials count as failures without reaching the validator.
+    let long = "x".repeat(17);
+    for passwd in [long.as_str(), "guess"] {
+        let err = client::connect_http(&proxy, &dst, Some(("alice", passwd)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SocksError::AuthFailed(_)));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Locked out now, even with the right password and over SOCKS5.
+    let err = client::connect_http(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SocksError::AuthFailed(_)));
+    let err = client::connect(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SocksError::AuthFailed(_)));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn http_refusals_map_to_status_codes() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_http_connect();
+        server.allow_no_auth();
+        server.deny_private_destinations();
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let err = client::connect_http(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::ConnectionNotAllowed)
+    ));
+
+    let mut stream = TcpStream::connect(&proxy).await.unwrap();
+    stream
+        .write_all(b"GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 405 "), "{response}");
+}
+
+#[tokio::test]
+async fn http_is_not_served_unless_enabled() {
+    let proxy = testkit::spawn_server(|server| server.allow_no_auth())
+        .await
+        .unwrap()
+        .to_string();
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let err = client::connect_http(&proxy, &dst, None).await.unwrap_err();
+    assert!(!matches!(err, SocksError::UpstreamRejected(_)), "{err}");
+}
+
+#[tokio::test]
+async fn crlf_in_a_domain_never_reaches_the_http_upstream() {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap().to_string();
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_upstream(UpstreamKind::HttpConnect {
+            addr: upstream_addr.clone(),
+            auth: Some(("svc".into(), "key".into())),
+        });
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let name = "example.com:80 HTTP/1.1\r\nX-Injected: 1\r\n\r\nCONNECT 10.0.0.1";
+    let dst = AddrPort::Domain(name.into(), 80);
+    let connect = client::connect(&proxy, &dst, None);
+    let err = time::timeout(Duration::from_secs(5), connect)
+        .await
+        .expect("the request was forwarded upstream")
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::GeneralFailure)
+    ));
+
+    let err = client::connect_http(&upstream_addr, &dst, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SocksError::InvalidDomain(n) if n == name));
+
+    let accepted = time::timeout(Duration::from_millis(100), upstream.accept()).await;
+    assert!(accepted.is_err(), "the upstream was dialed");
+}
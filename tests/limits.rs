@@ -0,0 +1,70 @@
+//! End-to-end checks of the connection limit.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use simple_socks5::{OverloadPolicy, testkit};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Connects and sends a greeting, leaving the client waiting on the server.
+async fn greet(proxy: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    stream
+}
+
+/// Reads the method selection, or `None` if it does not arrive in time.
+async fn selection(stream: &mut TcpStream) -> Option<[u8; 2]> {
+    let mut buf = [0u8; 2];
+    match timeout(Duration::from_millis(200), stream.read_exact(&mut buf)).await {
+        Ok(Ok(_)) => Some(buf),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn third_connection_waits_for_a_free_slot() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_max_connections(2);
+        server.set_overload_policy(OverloadPolicy::Wait);
+    })
+    .await
+    .unwrap();
+
+    let mut first = greet(proxy).await;
+    let mut second = greet(proxy).await;
+    assert_eq!(selection(&mut first).await, Some([0x05, 0x00]));
+    assert_eq!(selection(&mut second).await, Some([0x05, 0x00]));
+
+    let mut third = greet(proxy).await;
+    assert_eq!(selection(&mut third).await, None);
+
+    drop(first);
+    assert_eq!(selection(&mut third).await, Some([0x05, 0x00]));
+}
+
+#[tokio::test]
+async fn third_connection_is_rejected() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_max_connections(2);
+        server.set_overload_policy(OverloadPolicy::Reject);
+    })
+    .await
+    .unwrap();
+
+    let mut first = greet(proxy).await;
+    let mut second = greet(proxy).await;
+    assert_eq!(selection(&mut first).await, Some([0x05, 0x00]));
+    assert_eq!(selection(&mut second).await, Some([0x05, 0x00]));
+
+    let mut third = greet(proxy).await;
+    let mut rest = Vec::new();
+    let closed = timeout(Duration::from_millis(200), third.read_to_end(&mut rest)).await;
+    // Closed without a method selection; a reset counts as closed.
+    assert!(closed.is_ok());
+    assert!(rest.is_empty());
+}
@@ -0,0 +1,222 @@
+//! End-to-end checks of the server's destination policies.
+//!
+//! Each test starts a server on an ephemeral port and drives it with the
+//! crate's own SOCKS5 client.
+
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use simple_socks5::conn::reply::Rep;
+use simple_socks5::error::SocksError;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::{client, testkit};
+
+#[tokio::test]
+async fn dotless_domain_is_refused_unless_excepted() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_deny_domains_without_dot(vec!["localhost".into()]);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let port = testkit::spawn_echo().await.unwrap().port();
+
+    let bare = AddrPort::Domain("intranet".into(), port);
+    let err = client::connect(&proxy, &bare, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::ConnectionNotAllowed)
+    ));
+
+    let excepted = AddrPort::Domain("localhost".into(), port);
+    assert!(client::connect(&proxy, &excepted, None).await.is_ok());
+}
+
+/// Starts a server that rejects IP addresses sent as domain names.
+async fn strict_atyp_proxy() -> String {
+    testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_reject_ip_literals_in_domain_atyp(true);
+    })
+    .await
+    .unwrap()
+    .to_string()
+}
+
+async fn refused_as_address_type(proxy: &str, host: &str, port: u16) {
+    let dst = AddrPort::Domain(host.into(), port);
+    let err = client::connect(proxy, &dst, None).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            SocksError::UpstreamRejected(Rep::AddressTypeNotSupported)
+        ),
+        "{host} was not refused: {err}"
+    );
+}
+
+#[tokio::test]
+async fn ipv4_literal_as_domain_is_refused() {
+    let proxy = strict_atyp_proxy().await;
+    let port = testkit::spawn_echo().await.unwrap().port();
+
+    refused_as_address_type(&proxy, "127.0.0.1", port).await;
+    refused_as_address_type(&proxy, "127.1", port).await;
+    refused_as_address_type(&proxy, "0x7f000001", port).await;
+
+    let name = AddrPort::Domain("localhost".into(), port);
+    assert!(client::connect(&proxy, &name, None).await.is_ok());
+    let ip = AddrPort::V4(Ipv4Addr::LOCALHOST, port);
+    assert!(client::connect(&proxy, &ip, None).await.is_ok());
+}
+
+#[tokio::test]
+async fn ipv6_literal_as_domain_is_refused() {
+    let proxy = strict_atyp_proxy().await;
+
+    refused_as_address_type(&proxy, "::1", 80).await;
+    refused_as_address_type(&proxy, "[::1]", 80).await;
+    refused_as_address_type(&proxy, "::ffff:127.0.0.1", 80).await;
+}
+
+#[tokio::test]
+async fn destination_ports_follow_the_allow_and_block_lists() {
+    let allowed = testkit::spawn_echo().await.unwrap().port();
+    let blocked = testkit::spawn_echo().await.unwrap().port();
+    let unlisted = testkit::spawn_echo().await.unwrap().port();
+
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_allowed_ports([allowed..=allowed, blocked..=blocked]);
+        server.set_blocked_ports([blocked..=blocked]);
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, allowed);
+    assert!(client::connect(&proxy, &dst, None).await.is_ok());
+
+    for port in [blocked, unlisted] {
+        let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, port);
+        let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+        assert!(
+            matches!(err, SocksError::UpstreamRejected(Rep::ConnectionNotAllowed)),
+            "port {port} was not refused: {err}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn rewritten_destination_is_dialed_and_still_checked() {
+    let redirected = testkit::spawn_echo().await.unwrap().port();
+
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_blocked_ports([1..=1]);
+        server.set_destination_rewriter(move |dst| match dst {
+            AddrPort::Domain(host, _) if host == "internal.test" => {
+                Some(AddrPort::V4(Ipv4Addr::LOCALHOST, redirected))
+            }
+            AddrPort::Domain(host, _) if host == "blocked.test" => {
+                Some(AddrPort::V4(Ipv4Addr::LOCALHOST, 1))
+            }
+            _ => None,
+        });
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let dst = AddrPort::Domain("internal.test".into(), 80);
+    let (_, bnd) = client::connect(&proxy, &dst, None).await.unwrap();
+    assert!(matches!(bnd, AddrPort::V4(ip, port) if ip.is_loopback() && port != 0));
+
+    let dst = AddrPort::Domain("blocked.test".into(), 80);
+    let err = client::connect(&proxy, &dst, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::ConnectionNotAllowed)
+    ));
+}
+
+/// Per-user data returned by the validator.
+struct Plan {
+    may_proxy: bool,
+}
+
+#[tokio::test]
+async fn user_context_reaches_the_destination_filter() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_userpass_ctx(|uname, passwd| match (uname, passwd) {
+            ("alice", "secret") => Some(Plan { may_proxy: true }),
+            ("bob", "secret") => Some(Plan { may_proxy: false }),
+            _ => None,
+        });
+        server.set_user_destination_filter(|auth, _| {
+            auth.user_context::<Plan>()
+                .is_some_and(|plan| plan.may_proxy)
+        });
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let dst = AddrPort::V4(
+        Ipv4Addr::LOCALHOST,
+        testkit::spawn_echo().await.unwrap().port(),
+    );
+
+    assert!(
+        client::connect(&proxy, &dst, Some(("alice", "secret")))
+            .await
+            .is_ok()
+    );
+
+    let err = client::connect(&proxy, &dst, Some(("bob", "secret")))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::ConnectionNotAllowed)
+    ));
+
+    let err = client::connect(&proxy, &dst, Some(("carol", "secret")))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SocksError::AuthFailed(_)));
+}
+
+#[tokio::test]
+async fn inspector_runs_before_the_other_policies_with_the_user() {
+    let port = testkit::spawn_echo().await.unwrap().port();
+    let rewrites = Arc::new(AtomicUsize::new(0));
+
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_userpass(|_, passwd| passwd == "secret");
+        server.set_request_inspector(|auth, req| match auth.username.as_deref() {
+            Some("alice") if req.dst == AddrPort::Domain("internal.test".into(), 80) => Ok(()),
+            _ => Err(Rep::TTLExpired),
+        });
+        let counted = Arc::clone(&rewrites);
+        server.set_destination_rewriter(move |_| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            Some(AddrPort::V4(Ipv4Addr::LOCALHOST, port))
+        });
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let dst = AddrPort::Domain("internal.test".into(), 80);
+
+    client::connect(&proxy, &dst, Some(("alice", "secret")))
+        .await
+        .unwrap();
+
+    let err = client::connect(&proxy, &dst, Some(("bob", "secret")))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SocksError::UpstreamRejected(Rep::TTLExpired)));
+    assert_eq!(rewrites.load(Ordering::Relaxed), 1);
+}
@@ -0,0 +1,518 @@
+//! End-to-end checks of the relay between client and target.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use simple_socks5::error::SocksError;
+use simple_socks5::event::Event;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::relay::{CloseReason, RelayStats};
+use simple_socks5::{Socks5, client, testkit};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::time;
+
+/// Starts a target that sends `payload`, then reports everything it read
+/// until EOF.
+async fn target(payload: &'static [u8]) -> (AddrPort, oneshot::Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = AddrPort::from(listener.local_addr().unwrap());
+    let (tx, rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let _ = stream.write_all(payload).await;
+        let mut received = Vec::new();
+        let _ = stream.read_to_end(&mut received).await;
+        let _ = tx.send(received);
+    });
+
+    (addr, rx)
+}
+
+#[tokio::test]
+async fn upload_quota_cuts_client_to_target() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_direction_quota(Some(10), None);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let (dst, received) = target(b"").await;
+
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    stream.write_all(&[0xAA; 25]).await.unwrap();
+
+    assert_eq!(received.await.unwrap(), [0xAA; 10]);
+    // The proxy closes the client side too; a reset counts as closed.
+    let mut rest = Vec::new();
+    let _ = stream.read_to_end(&mut rest).await;
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn sending_exactly_the_quota_ends_the_relay() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_direction_quota(Some(10), None);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let (dst, received) = target(b"").await;
+
+    // The client stays connected after the last byte it is allowed.
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    stream.write_all(&[0xAA; 10]).await.unwrap();
+
+    let received = time::timeout(Duration::from_secs(5), received)
+        .await
+        .expect("the relay outlived the quota");
+    assert_eq!(received.unwrap(), [0xAA; 10]);
+    let mut rest = Vec::new();
+    let closed = time::timeout(Duration::from_secs(5), stream.read_to_end(&mut rest)).await;
+    assert!(closed.is_ok(), "the client side was left open");
+}
+
+#[tokio::test]
+async fn download_quota_cuts_target_to_client() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_direction_quota(None, Some(10));
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let (dst, received) = target(&[0xBB; 25]).await;
+
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    let mut downloaded = Vec::new();
+    stream.read_to_end(&mut downloaded).await.unwrap();
+
+    assert_eq!(downloaded, [0xBB; 10]);
+    assert!(received.await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn quota_on_one_direction_leaves_the_other_unlimited() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_direction_quota(None, Some(4));
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let (dst, received) = target(b"").await;
+
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    stream.write_all(&[0xCC; 64]).await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    assert_eq!(received.await.unwrap(), [0xCC; 64]);
+}
+
+#[tokio::test]
+async fn response_follows_the_clients_half_close() {
+    let proxy = testkit::spawn_server(|server| server.allow_no_auth())
+        .await
+        .unwrap()
+        .to_string();
+
+    // Answers only once the request has ended, like HTTP/1.0 without a
+    // length or a `wc`-style service.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::from(listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        stream.read_to_end(&mut request).await.unwrap();
+        let response = format!("{} bytes", request.len());
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    stream.write_all(&[0x42; 1000]).await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    let mut response = String::new();
+    time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .expect("response never arrived")
+        .unwrap();
+    assert_eq!(response, "1000 bytes");
+}
+
+#[tokio::test]
+async fn drain_closes_only_idle_connections() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    let proxy = server.local_addr().unwrap().to_string();
+    let server = Arc::new(server);
+
+    let accepting = Arc::clone(&server);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = accepting.accept().await {
+            let server = Arc::clone(&accepting);
+            tokio::spawn(async move { server.handle_client(stream).await });
+        }
+    });
+
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+    let (mut idle, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    let (mut active, _) = client::connect(&proxy, &dst, None).await.unwrap();
+
+    time::sleep(Duration::from_millis(300)).await;
+    let mut byte = [0; 1];
+    active.write_all(b"x").await.unwrap();
+    active.read_exact(&mut byte).await.unwrap();
+
+    assert_eq!(server.drain_idle_connections(Duration::from_millis(200)), 1);
+
+    let mut rest = Vec::new();
+    time::timeout(Duration::from_secs(5), idle.read_to_end(&mut rest))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(rest.is_empty());
+    assert!(
+        server
+            .metrics_prometheus()
+            .contains(r#"socks5_relays_closed_total{reason="idle_drained"} 1"#)
+    );
+
+    active.write_all(b"y").await.unwrap();
+    active.read_exact(&mut byte).await.unwrap();
+    assert_eq!(&byte, b"y");
+    assert_eq!(server.connections().len(), 1);
+}
+
+#[tokio::test]
+async fn client_closing_right_after_the_reply_ends_quietly() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    let mut events = server.events();
+    let proxy = server.local_addr().unwrap().to_string();
+    let served = tokio::spawn(async move {
+        let (stream, _) = server.accept().await.unwrap();
+        server.handle_client(stream).await
+    });
+
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+    let (stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    drop(stream);
+
+    time::timeout(Duration::from_secs(5), served)
+        .await
+        .expect("relay did not end")
+        .unwrap()
+        .unwrap();
+    let stats = loop {
+        match events.recv().await.unwrap() {
+            Event::RelayClosed { stats, .. } => break stats,
+            _ => continue,
+        }
+    };
+    assert_eq!(
+        stats,
+        RelayStats {
+            client_to_target: 0,
+            target_to_client: 0,
+            reason: CloseReason::ClientClosed,
+        }
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn throughput_converges_to_a_steady_rate() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    let proxy = server.local_addr().unwrap().to_string();
+    let server = Arc::new(server);
+
+    let accepting = Arc::clone(&server);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = accepting.accept().await {
+            let server = Arc::clone(&accepting);
+            tokio::spawn(async move { server.handle_client(stream).await });
+        }
+    });
+
+    // 1000 bytes every 100 ms, echoed back: 20 kB/s through the relay.
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    let mut echoed = [0; 1000];
+    let throughput = || server.connections()[0].throughput_bps;
+    for tick in 1..=500 {
+        time::sleep(Duration::from_millis(100)).await;
+        stream.write_all(&[0x42; 1000]).await.unwrap();
+        stream.read_exact(&mut echoed).await.unwrap();
+
+        // Within one 5 s window the average has covered most of the way.
+        if tick == 50 {
+            assert!(
+                (12_000.0..18_000.0).contains(&throughput()),
+                "{}",
+                throughput()
+            );
+        }
+    }
+    // After ten windows, and read right after a chunk, it is within 0.5%.
+    assert!(
+        (19_900.0..20_100.0).contains(&throughput()),
+        "{}",
+        throughput()
+    );
+}
+
+#[tokio::test]
+async fn teardown_gives_up_on_a_client_that_never_reads() {
+    // A target that streams until the relay drops it.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::from(listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        while stream.write_all(&[0x42; 64 * 1024]).await.is_ok() {}
+    });
+
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    server.set_close_notify_timeout(Duration::from_millis(200));
+    let proxy = server.local_addr().unwrap().to_string();
+    let served = tokio::spawn(async move {
+        let (stream, _) = server.accept().await.unwrap();
+        server.handle_client(stream).await
+    });
+
+    // The client finishes its upload but never reads the download, so the
+    // relay ends up stuck writing to it once the socket buffers fill.
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    time::timeout(Duration::from_secs(2), served)
+        .await
+        .expect("teardown did not finish")
+        .unwrap()
+        .unwrap();
+    drop(stream);
+}
+
+/// Sends 1000 bytes through a server with relay buffers of `size` bytes,
+/// paced to 1000 bytes per second, and returns the sizes of the reads the
+/// target sees once the bucket's initial burst of 100 bytes has passed.
+///
+/// With the clock paused, every paced write lands at its own instant, so
+/// each read returns exactly one chunk the relay read from the client.
+async fn paced_chunks(size: usize) -> Vec<usize> {
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dst = AddrPort::from(target.local_addr().unwrap());
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_relay_buffer_size(size).unwrap();
+        server.set_rate_limit(1000);
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    let (mut target, _) = target.accept().await.unwrap();
+    stream.write_all(&[0x42; 1000]).await.unwrap();
+
+    let mut buf = [0; 1000];
+    let (mut total, mut chunks) = (0, Vec::new());
+    while total < 1000 {
+        let n = target.read(&mut buf).await.unwrap();
+        if total >= 100 {
+            chunks.push(n);
+        }
+        total += n;
+    }
+    chunks
+}
+
+#[tokio::test(start_paused = true)]
+async fn relay_reads_at_most_the_buffer_size() {
+    assert_eq!(paced_chunks(10).await, [10; 90]);
+    assert_eq!(paced_chunks(50).await, [50; 18]);
+}
+
+/// Returns both ends of a loopback TCP connection.
+async fn pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let near = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (far, _) = listener.accept().await.unwrap();
+    (near, far)
+}
+
+#[tokio::test]
+async fn relay_reports_bytes_per_direction() {
+    let (mut client, mut proxy_client) = pair().await;
+    let (mut proxy_target, mut target) = pair().await;
+    let relay =
+        tokio::spawn(async move { Socks5::relay(&mut proxy_client, &mut proxy_target).await });
+
+    client.write_all(b"request").await.unwrap();
+    client.shutdown().await.unwrap();
+    let mut received = Vec::new();
+    target.read_to_end(&mut received).await.unwrap();
+    assert_eq!(received, b"request");
+
+    // The client's EOF only closed one direction; the reply still flows.
+    target.write_all(b"response!").await.unwrap();
+    drop(target);
+    let mut reply = Vec::new();
+    client.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(reply, b"response!");
+
+    assert_eq!(relay.await.unwrap().unwrap(), (7, 9));
+}
+
+#[tokio::test]
+async fn relay_moves_large_transfers_intact() {
+    let (mut client, mut proxy_client) = pair().await;
+    let (mut proxy_target, mut target) = pair().await;
+    let relay =
+        tokio::spawn(async move { Socks5::relay(&mut proxy_client, &mut proxy_target).await });
+
+    // Several times any relay buffer or pipe, in a pattern that shows
+    // reordered or dropped chunks.
+    let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let sent = payload.clone();
+    let writer = tokio::spawn(async move {
+        client.write_all(&sent).await.unwrap();
+        client.shutdown().await.unwrap();
+        client
+    });
+
+    let mut received = Vec::new();
+    target.read_to_end(&mut received).await.unwrap();
+    assert!(received == payload);
+    drop(target);
+    drop(writer.await.unwrap());
+
+    assert_eq!(relay.await.unwrap().unwrap(), (payload.len() as u64, 0));
+}
+
+#[tokio::test]
+async fn user_rate_limit_overrides_the_server_limit() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_userpass_ctx(|uname, _| match uname {
+            "slow" => Some(100_000u64),
+            _ => None,
+        });
+        server.set_user_rate_limit(|auth| auth.user_context::<u64>().copied());
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let (dst, received) = target(b"").await;
+
+    let (mut stream, _) = client::connect(&proxy, &dst, Some(("slow", "x")))
+        .await
+        .unwrap();
+    let started = time::Instant::now();
+    stream.write_all(&[0x42; 60_000]).await.unwrap();
+    stream.shutdown().await.unwrap();
+    let received = received.await.unwrap();
+
+    assert_eq!(received.len(), 60_000);
+    assert!(
+        started.elapsed() >= Duration::from_millis(450),
+        "{:?}",
+        started.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn rate_limit_paces_the_relay() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_rate_limit(100_000);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let (dst, received) = target(b"").await;
+
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    let started = time::Instant::now();
+    stream.write_all(&[0x42; 60_000]).await.unwrap();
+    stream.shutdown().await.unwrap();
+    let received = received.await.unwrap();
+
+    // A tenth of a second passes as a burst; the other 50 kB take 0.5 s.
+    assert_eq!(received.len(), 60_000);
+    assert!(
+        started.elapsed() >= Duration::from_millis(450),
+        "{:?}",
+        started.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn idle_timeout_closes_a_silent_connection() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    server.set_idle_timeout(Duration::from_millis(200));
+    let proxy = server.local_addr().unwrap().to_string();
+    let server = Arc::new(server);
+
+    let accepting = Arc::clone(&server);
+    let (done, result) = oneshot::channel();
+    tokio::spawn(async move {
+        let (stream, _) = accepting.accept().await.unwrap();
+        let _ = done.send(accepting.handle_client(stream).await);
+    });
+
+    let (dst, received) = target(b"").await;
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+
+    // Nothing is sent after the reply; both sides see EOF once the timer
+    // fires.
+    let mut rest = Vec::new();
+    time::timeout(Duration::from_secs(5), stream.read_to_end(&mut rest))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(rest.is_empty());
+    assert!(received.await.unwrap().is_empty());
+
+    let result = result.await.unwrap();
+    assert!(matches!(result, Err(SocksError::IdleTimeout)), "{result:?}");
+    assert!(
+        server
+            .metrics_prometheus()
+            .contains(r#"socks5_relays_closed_total{reason="idle_timeout"} 1"#)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn several_accept_loops_serve_concurrent_clients() {
+    let dst = AddrPort::from(testkit::spawn_echo().await.unwrap());
+
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.allow_no_auth();
+    let proxy = server.local_addr().unwrap().to_string();
+    tokio::spawn(server.run_workers(4));
+
+    let clients = (0..32u8).map(|i| {
+        let (proxy, dst) = (proxy.clone(), dst.clone());
+        tokio::spawn(async move {
+            let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+            stream.write_all(&[i; 64]).await.unwrap();
+            let mut echoed = [0; 64];
+            stream.read_exact(&mut echoed).await.unwrap();
+            assert_eq!(echoed, [i; 64]);
+        })
+    });
+    for client in clients.collect::<Vec<_>>() {
+        time::timeout(Duration::from_secs(5), client)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
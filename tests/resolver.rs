@@ -0,0 +1,143 @@
+//! Ordering of resolved addresses before they are dialed.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use simple_socks5::client;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::resolver::{AddressPreference, ResolveFuture, Resolver};
+use simple_socks5::testkit::{self, MockConnector, MockOutcome};
+use tokio::net::TcpListener;
+use tokio::time;
+
+/// Two addresses per family, IPv4 first as a resolver might return them.
+const ADDRS: [&str; 4] = [
+    "192.0.2.1:80",
+    "192.0.2.2:80",
+    "[2001:db8::1]:80",
+    "[2001:db8::2]:80",
+];
+
+fn addrs() -> Vec<SocketAddr> {
+    ADDRS.iter().map(|a| a.parse().unwrap()).collect()
+}
+
+fn sorted(preference: AddressPreference) -> Vec<String> {
+    preference
+        .sort(addrs())
+        .iter()
+        .map(SocketAddr::to_string)
+        .collect()
+}
+
+#[test]
+fn families_alternate_starting_with_the_preferred_one() {
+    assert_eq!(
+        sorted(AddressPreference::Ipv6First),
+        [
+            "[2001:db8::1]:80",
+            "192.0.2.1:80",
+            "[2001:db8::2]:80",
+            "192.0.2.2:80"
+        ]
+    );
+    assert_eq!(
+        sorted(AddressPreference::Ipv4First),
+        [
+            "192.0.2.1:80",
+            "[2001:db8::1]:80",
+            "192.0.2.2:80",
+            "[2001:db8::2]:80"
+        ]
+    );
+    assert_eq!(sorted(AddressPreference::System), ADDRS);
+}
+
+#[test]
+fn leftover_addresses_of_one_family_come_last() {
+    let mut addrs = addrs();
+    addrs.push("192.0.2.3:80".parse().unwrap());
+
+    let sorted = AddressPreference::Ipv6First.sort(addrs);
+    assert_eq!(sorted.len(), 5);
+    assert_eq!(sorted[3].to_string(), "192.0.2.2:80");
+    assert_eq!(sorted[4].to_string(), "192.0.2.3:80");
+}
+
+/// Resolves every name to [`ADDRS`].
+struct Fixed;
+
+impl Resolver for Fixed {
+    fn resolve<'a>(&'a self, _host: &'a str, _port: u16) -> ResolveFuture<'a> {
+        Box::pin(async { Ok(addrs()) })
+    }
+}
+
+#[tokio::test]
+async fn server_dials_both_families_in_preference_order() {
+    let mock = MockConnector::new();
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_resolver(Fixed);
+        server.set_address_preference(AddressPreference::Ipv6First);
+        server.set_connector(mock.clone());
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let dst = AddrPort::Domain("dual.example".into(), 80);
+    assert!(client::connect(&proxy, &dst, None).await.is_err());
+
+    let dialed = mock.dialed();
+    let families: Vec<bool> = dialed.iter().map(SocketAddr::is_ipv6).collect();
+    assert_eq!(families, [true, false, true, false]);
+    assert_eq!(dialed, AddressPreference::Ipv6First.sort(addrs()));
+}
+
+#[tokio::test]
+async fn happy_eyeballs_falls_back_without_waiting_for_ipv6() {
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    // IPv6 never answers, IPv4 reaches the local target.
+    let mock = MockConnector::new();
+    for addr in addrs() {
+        let outcome = match addr {
+            SocketAddr::V6(_) => MockOutcome::Hang,
+            SocketAddr::V4(_) => MockOutcome::ConnectTo(target.local_addr().unwrap()),
+        };
+        mock.script(addr, outcome);
+    }
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_resolver(Fixed);
+        server.set_address_preference(AddressPreference::Ipv6First);
+        server.set_connector(mock);
+        server.set_happy_eyeballs(Duration::from_millis(50));
+    })
+    .await
+    .unwrap()
+    .to_string();
+
+    let dst = AddrPort::Domain("dual.example".into(), 80);
+    let connect = client::connect(&proxy, &dst, None);
+    let (_stream, bnd) = time::timeout(Duration::from_secs(5), connect)
+        .await
+        .expect("IPv6 attempt held up the connection")
+        .unwrap();
+
+    let (_, source) = target.accept().await.unwrap();
+    assert_eq!(bnd, AddrPort::from(source));
+}
+
+#[tokio::test]
+async fn connect_happy_eyeballs_reaches_a_listening_family() {
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = target.local_addr().unwrap().port();
+
+    let dst = AddrPort::Domain("localhost".into(), port);
+    let stream = dst
+        .connect_happy_eyeballs(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), target.local_addr().unwrap());
+}
@@ -0,0 +1,47 @@
+//! Checks of the `serde` representations of protocol types.
+
+use std::net::Ipv4Addr;
+
+use serde_json::json;
+use simple_socks5::ATYP;
+use simple_socks5::conn::request::{CMD, ConnRequest};
+use simple_socks5::parse::AddrPort;
+
+#[test]
+fn domain_round_trips_through_json() {
+    let addr = AddrPort::Domain("example.com".into(), 443);
+
+    let value = serde_json::to_value(&addr).unwrap();
+    assert_eq!(
+        value,
+        json!({ "type": "domain", "host": "example.com", "port": 443 })
+    );
+    assert_eq!(serde_json::from_value::<AddrPort>(value).unwrap(), addr);
+}
+
+#[test]
+fn ip_addresses_serialize_readably() {
+    let v4 = AddrPort::V4(Ipv4Addr::new(192, 0, 2, 1), 80);
+    assert_eq!(
+        serde_json::to_value(&v4).unwrap(),
+        json!({ "type": "v4", "ip": "192.0.2.1", "port": 80 })
+    );
+
+    let v6: AddrPort = serde_json::from_str(r#"{"type":"v6","ip":"::1","port":8080}"#).unwrap();
+    assert_eq!(v6, AddrPort::V6("::1".parse().unwrap(), 8080));
+}
+
+#[test]
+fn oversized_domain_is_rejected() {
+    let value = json!({ "type": "domain", "host": "x".repeat(256), "port": 80 });
+    assert!(serde_json::from_value::<AddrPort>(value).is_err());
+}
+
+#[test]
+fn request_round_trips_through_json() {
+    let dst = AddrPort::Domain("example.com".into(), 80);
+    let req = ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::DomainName, dst);
+
+    let json = serde_json::to_string(&req).unwrap();
+    assert_eq!(serde_json::from_str::<ConnRequest>(&json).unwrap(), req);
+}
@@ -0,0 +1,57 @@
+//! Checks of the options set on client and outbound sockets.
+
+use std::time::Duration;
+
+use simple_socks5::error::SocksError;
+use simple_socks5::{BindOptions, Socks5};
+use socket2::SockRef;
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn accepted_streams_have_nodelay_by_default() {
+    let server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    let _client = TcpStream::connect(server.local_addr().unwrap())
+        .await
+        .unwrap();
+
+    let (stream, _) = server.accept().await.unwrap();
+    assert!(stream.nodelay().unwrap());
+    assert!(!SockRef::from(&stream).keepalive().unwrap());
+}
+
+#[tokio::test]
+async fn nodelay_and_keepalive_are_configurable() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    server.set_nodelay(false);
+    server.set_keepalive(Some(Duration::from_secs(30)));
+    let _client = TcpStream::connect(server.local_addr().unwrap())
+        .await
+        .unwrap();
+
+    let (stream, _) = server.accept().await.unwrap();
+    assert!(!stream.nodelay().unwrap());
+
+    let socket = SockRef::from(&stream);
+    assert!(socket.keepalive().unwrap());
+    #[cfg(target_os = "linux")]
+    assert_eq!(
+        socket.tcp_keepalive_time().unwrap(),
+        Duration::from_secs(30)
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn reuse_port_lets_two_servers_share_an_address() {
+    let options = BindOptions {
+        reuse_port: true,
+        ..BindOptions::default()
+    };
+    let first = Socks5::bind_with("127.0.0.1:0", options).await.unwrap();
+    let addr = first.local_addr().unwrap().to_string();
+
+    let err = Socks5::bind(&addr).await.err().unwrap();
+    assert!(matches!(err, SocksError::AddrInUse { .. }));
+
+    Socks5::bind_with(&addr, options).await.unwrap();
+}
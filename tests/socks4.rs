@@ -0,0 +1,129 @@
+//! End-to-end checks of SOCKS4 and SOCKS4a served on the SOCKS5 port.
+
+use std::net::Ipv4Addr;
+
+use simple_socks5::msg::method::Method;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::socks4::{Command, Request};
+use simple_socks5::{client, testkit};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Sends a SOCKS4 `CONNECT` for `dst` and returns the stream with the
+/// reply's `CD` byte.
+async fn connect4(proxy: &str, dst: AddrPort) -> (TcpStream, u8) {
+    let req = Request {
+        command: Command::Connect,
+        dst,
+        user_id: "alice".into(),
+    };
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    stream.write_all(&req.to_bytes().unwrap()).await.unwrap();
+
+    let mut reply = [0; 8];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[0], 0x00);
+    (stream, reply[1])
+}
+
+async fn round_trip(stream: &mut TcpStream) {
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn socks4_and_socks4a_are_relayed() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_socks4();
+        server.allow_no_auth();
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let port = testkit::spawn_echo().await.unwrap().port();
+
+    let (mut stream, cd) = connect4(&proxy, AddrPort::V4(Ipv4Addr::LOCALHOST, port)).await;
+    assert_eq!(cd, 90);
+    round_trip(&mut stream).await;
+
+    let (mut stream, cd) = connect4(&proxy, AddrPort::Domain("localhost".into(), port)).await;
+    assert_eq!(cd, 90);
+    round_trip(&mut stream).await;
+
+    let dst = AddrPort::V4(Ipv4Addr::LOCALHOST, port);
+    let (mut stream, _) = client::connect(&proxy, &dst, None).await.unwrap();
+    round_trip(&mut stream).await;
+}
+
+#[tokio::test]
+async fn socks4_is_refused_without_no_auth() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_socks4();
+        server.allow_userpass(|_, _| true);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let port = testkit::spawn_echo().await.unwrap().port();
+
+    let (_, cd) = connect4(&proxy, AddrPort::V4(Ipv4Addr::LOCALHOST, port)).await;
+    assert_eq!(cd, 91);
+}
+
+#[tokio::test]
+async fn socks4_is_refused_when_the_preference_requires_userpass() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_socks4();
+        server.allow_no_auth();
+        server.allow_userpass(|_, _| true);
+        server.set_method_preference(vec![Method::userpass()]);
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let port = testkit::spawn_echo().await.unwrap().port();
+
+    let (_, cd) = connect4(&proxy, AddrPort::V4(Ipv4Addr::LOCALHOST, port)).await;
+    assert_eq!(cd, 91);
+}
+
+#[tokio::test]
+async fn policy_refusals_are_rejected() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_socks4();
+        server.allow_no_auth();
+        server.deny_private_destinations();
+    })
+    .await
+    .unwrap()
+    .to_string();
+    let port = testkit::spawn_echo().await.unwrap().port();
+
+    let (_, cd) = connect4(&proxy, AddrPort::V4(Ipv4Addr::LOCALHOST, port)).await;
+    assert_eq!(cd, 91);
+}
+
+#[tokio::test]
+async fn socks4_is_not_served_unless_enabled() {
+    let proxy = testkit::spawn_server(|server| server.allow_no_auth())
+        .await
+        .unwrap()
+        .to_string();
+
+    let req = Request {
+        command: Command::Connect,
+        dst: AddrPort::V4(
+            Ipv4Addr::LOCALHOST,
+            testkit::spawn_echo().await.unwrap().port(),
+        ),
+        user_id: String::new(),
+    };
+    let mut stream = TcpStream::connect(&proxy).await.unwrap();
+    stream.write_all(&req.to_bytes().unwrap()).await.unwrap();
+
+    let mut reply = Vec::new();
+    let _ = stream.read_to_end(&mut reply).await;
+    assert!(reply.is_empty());
+}
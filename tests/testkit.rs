@@ -0,0 +1,255 @@
+//! Example of an end-to-end test written with the `test-util` helpers.
+
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use simple_socks5::client;
+use simple_socks5::conn::reply::Rep;
+use simple_socks5::conn::request::{CMD, ConnRequest};
+use simple_socks5::connector::ConnectErrorPolicy;
+use simple_socks5::error::SocksError;
+use simple_socks5::parse::AddrPort;
+use simple_socks5::resolver::{ResolveFuture, Resolver};
+use simple_socks5::testkit;
+use simple_socks5::testkit::{MockConnector, MockOutcome};
+use simple_socks5::{ATYP, Socks5};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn echo_round_trips_through_the_proxy() {
+    let proxy = testkit::spawn_server(|server| server.allow_no_auth())
+        .await
+        .unwrap();
+    let echo = testkit::spawn_echo().await.unwrap();
+
+    let mut stream = testkit::connect(proxy, echo, None).await.unwrap();
+    let payload: Vec<u8> = (0..=255).cycle().take(64 * 1024).collect();
+    let (mut reader, mut writer) = stream.split();
+
+    let send = async {
+        writer.write_all(&payload).await.unwrap();
+        writer.shutdown().await.unwrap();
+    };
+    let mut received = Vec::new();
+    let recv = reader.read_to_end(&mut received);
+    let (_, read) = tokio::join!(send, recv);
+
+    read.unwrap();
+    assert_eq!(received, payload);
+}
+
+#[tokio::test]
+async fn credentials_are_checked_by_the_server() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_userpass(|user, pass| user == "alice" && pass == "secret")
+    })
+    .await
+    .unwrap();
+    let echo = testkit::spawn_echo().await.unwrap();
+
+    assert!(
+        testkit::connect(proxy, echo, Some(("alice", "secret")))
+            .await
+            .is_ok()
+    );
+    assert!(
+        testkit::connect(proxy, echo, Some(("alice", "wrong")))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn policy_refusals_reach_the_client() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.deny_private_destinations();
+    })
+    .await
+    .unwrap();
+    let echo = testkit::spawn_echo().await.unwrap();
+
+    let err = testkit::connect(proxy, echo, None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        SocksError::UpstreamRejected(Rep::ConnectionNotAllowed)
+    ));
+}
+
+#[tokio::test]
+async fn reply_overrides_change_ver_and_rsv() {
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_reply_overrides(Some(0x04), Some(0xAA));
+    })
+    .await
+    .unwrap();
+    let echo = testkit::spawn_echo().await.unwrap();
+
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut selection = [0; 2];
+    stream.read_exact(&mut selection).await.unwrap();
+    assert_eq!(selection, [0x04, 0x00]);
+
+    let dst = AddrPort::from(echo);
+    let request = ConnRequest::new(0x05, CMD::Connect, 0x00, ATYP::V4, dst);
+    stream
+        .write_all(&request.to_bytes().unwrap())
+        .await
+        .unwrap();
+    let mut reply = [0; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..4], [0x04, 0x00, 0xAA, 0x01]);
+}
+
+#[tokio::test]
+async fn advertised_bind_addr_replaces_the_real_one() {
+    let echo = testkit::spawn_echo().await.unwrap();
+    let plain = testkit::spawn_server(|server| server.allow_no_auth())
+        .await
+        .unwrap();
+    let hidden = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_advertised_bind_addr(Some(AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0)));
+    })
+    .await
+    .unwrap();
+    let dst = AddrPort::from(echo);
+
+    let (_, bnd) = client::connect(&plain.to_string(), &dst, None)
+        .await
+        .unwrap();
+    assert!(matches!(bnd, AddrPort::V4(ip, port) if ip.is_loopback() && port != 0));
+
+    let (_, bnd) = client::connect(&hidden.to_string(), &dst, None)
+        .await
+        .unwrap();
+    assert_eq!(bnd, AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0));
+}
+
+#[tokio::test]
+async fn client_reads_a_domain_bind_addr() {
+    let echo = testkit::spawn_echo().await.unwrap();
+    let proxy = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.set_advertised_bind_addr(Some(AddrPort::Domain("proxy.lan".into(), 1080)));
+    })
+    .await
+    .unwrap();
+
+    let (_, bnd) = client::connect(&proxy.to_string(), &AddrPort::from(echo), None)
+        .await
+        .unwrap();
+    assert_eq!(bnd, AddrPort::Domain("proxy.lan".into(), 1080));
+}
+
+/// Resolves every name to the same candidate addresses.
+struct Candidates(Vec<SocketAddr>);
+
+impl Resolver for Candidates {
+    fn resolve<'a>(&'a self, _host: &'a str, _port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move { Ok(self.0.clone()) })
+    }
+}
+
+const FIRST: &str = "192.0.2.1:80";
+const SECOND: &str = "192.0.2.2:80";
+const THIRD: &str = "[2001:db8::3]:80";
+
+fn addr(a: &str) -> SocketAddr {
+    a.parse().unwrap()
+}
+
+/// Starts a server dialing through `mock`, resolving every name to
+/// [`FIRST`], [`SECOND`] and [`THIRD`].
+async fn mocked_proxy(mock: &MockConnector, configure: impl FnOnce(&mut Socks5)) -> SocketAddr {
+    let mock = mock.clone();
+    testkit::spawn_server(move |server| {
+        server.allow_no_auth();
+        server.set_connector(mock);
+        server.set_resolver(Candidates(vec![addr(FIRST), addr(SECOND), addr(THIRD)]));
+        configure(server);
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn later_candidate_is_used_after_earlier_ones_fail() {
+    let echo = testkit::spawn_echo().await.unwrap();
+    let mock = MockConnector::new();
+    let slow_refusal = MockOutcome::Fail(ErrorKind::ConnectionRefused);
+    mock.script(
+        addr(FIRST),
+        MockOutcome::Delay(Duration::from_millis(50), Box::new(slow_refusal)),
+    );
+    mock.script(addr(SECOND), MockOutcome::Fail(ErrorKind::HostUnreachable));
+    mock.script(addr(THIRD), MockOutcome::ConnectTo(echo));
+    let proxy = mocked_proxy(&mock, |_| {}).await;
+
+    let dst = AddrPort::Domain("multi.example".into(), 80);
+    let (mut stream, _) = client::connect(&proxy.to_string(), &dst, None)
+        .await
+        .unwrap();
+    stream.write_all(b"hello").await.unwrap();
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+
+    assert_eq!(&buf, b"hello");
+    assert_eq!(mock.dialed(), [addr(FIRST), addr(SECOND), addr(THIRD)]);
+}
+
+#[tokio::test]
+async fn hung_candidates_time_out_and_fall_through() {
+    let echo = testkit::spawn_echo().await.unwrap();
+    let mock = MockConnector::new();
+    mock.script(addr(FIRST), MockOutcome::Hang);
+    mock.script(addr(SECOND), MockOutcome::ConnectTo(echo));
+    let proxy = mocked_proxy(&mock, |server| {
+        server.set_connect_timeout(Duration::from_millis(50));
+    })
+    .await;
+
+    let dst = AddrPort::Domain("multi.example".into(), 80);
+    assert!(
+        client::connect(&proxy.to_string(), &dst, None)
+            .await
+            .is_ok()
+    );
+    assert_eq!(mock.dialed(), [addr(FIRST), addr(SECOND)]);
+}
+
+#[tokio::test]
+async fn error_reply_follows_the_aggregation_policy() {
+    let mock = MockConnector::new();
+    mock.script(addr(FIRST), MockOutcome::Hang);
+    mock.script(
+        addr(SECOND),
+        MockOutcome::Fail(ErrorKind::NetworkUnreachable),
+    );
+    mock.script(addr(THIRD), MockOutcome::Fail(ErrorKind::HostUnreachable));
+    let dst = AddrPort::Domain("multi.example".into(), 80);
+
+    for (policy, expected) in [
+        (ConnectErrorPolicy::FirstError, Rep::TTLExpired),
+        (ConnectErrorPolicy::LastError, Rep::HostUnreachable),
+        (ConnectErrorPolicy::MostSpecific, Rep::HostUnreachable),
+    ] {
+        let proxy = mocked_proxy(&mock, |server| {
+            server.set_connect_timeout(Duration::from_millis(20));
+            server.set_connect_reply_on_partial_success(policy);
+        })
+        .await;
+
+        let err = client::connect(&proxy.to_string(), &dst, None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, SocksError::UpstreamRejected(rep) if rep == expected),
+            "{policy:?}: {err}"
+        );
+    }
+}
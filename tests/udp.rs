@@ -0,0 +1,215 @@
+//! Checks of the sockets bound for `UDP ASSOCIATE`.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use simple_socks5::conn::reply::{ConnReply, Rep};
+use simple_socks5::conn::request::{CMD, ConnRequest};
+use simple_socks5::parse::AddrPort;
+use simple_socks5::testkit;
+use simple_socks5::udp::{UdpAssociation, run_while_connected};
+use simple_socks5::{ATYP, Socks5};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time;
+
+/// Returns both ends of a loopback TCP connection, server side first.
+async fn control() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    (server, client)
+}
+
+#[tokio::test]
+async fn association_binds_on_the_control_interface() {
+    let (server, _client) = control().await;
+    let unknown = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+
+    let association = UdpAssociation::bind(&server, &unknown).await.unwrap();
+    assert!(matches!(
+        association.local_addr().unwrap(),
+        AddrPort::V4(ip, port) if ip.is_loopback() && port != 0
+    ));
+}
+
+#[tokio::test]
+async fn unknown_client_address_falls_back_to_the_peer_ip() {
+    let (server, client) = control().await;
+    let peer_port = client.local_addr().unwrap().port();
+
+    let unknown = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+    let association = UdpAssociation::bind(&server, &unknown).await.unwrap();
+    assert!(association.accepts(SocketAddr::from((Ipv4Addr::LOCALHOST, 40000))));
+    assert!(!association.accepts(SocketAddr::from((Ipv4Addr::new(192, 0, 2, 1), 40000))));
+
+    let known = AddrPort::V4(Ipv4Addr::UNSPECIFIED, peer_port);
+    let association = UdpAssociation::bind(&server, &known).await.unwrap();
+    assert!(association.accepts(SocketAddr::from((Ipv4Addr::LOCALHOST, peer_port))));
+    assert!(!association.accepts(SocketAddr::from((Ipv4Addr::LOCALHOST, 40000))));
+}
+
+#[tokio::test]
+async fn server_replies_with_the_bound_address() {
+    let addr = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.enable_command(CMD::UdpAssociate);
+    })
+    .await
+    .unwrap();
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut selection = [0; 2];
+    stream.read_exact(&mut selection).await.unwrap();
+
+    let dst = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+    let request = ConnRequest::new(0x05, CMD::UdpAssociate, 0x00, ATYP::V4, dst);
+    stream
+        .write_all(&request.to_bytes().unwrap())
+        .await
+        .unwrap();
+    let mut reply = [0; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+
+    let reply = ConnReply::try_from(&reply[..]).unwrap();
+    assert_eq!(reply.rep, Rep::Succeeded);
+    assert!(matches!(reply.bnd, AddrPort::V4(ip, port) if ip.is_loopback() && port != 0));
+}
+
+#[tokio::test]
+async fn advertised_udp_host_is_sent_as_a_domain() {
+    let addr = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.enable_command(CMD::UdpAssociate);
+        server
+            .set_advertised_udp_host(Some("relay.example".into()))
+            .unwrap();
+    })
+    .await
+    .unwrap();
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut selection = [0; 2];
+    stream.read_exact(&mut selection).await.unwrap();
+
+    let request = ConnRequest::udp_associate(AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0));
+    stream
+        .write_all(&request.to_bytes().unwrap())
+        .await
+        .unwrap();
+    let mut reply = [0; 4 + 1 + 13 + 2];
+    stream.read_exact(&mut reply).await.unwrap();
+
+    let reply = ConnReply::try_from(&reply[..]).unwrap();
+    assert_eq!(reply.rep, Rep::Succeeded);
+    assert_eq!(reply.atyp, ATYP::DomainName);
+    assert!(
+        matches!(reply.bnd, AddrPort::Domain(host, port) if host == "relay.example" && port != 0)
+    );
+}
+
+#[tokio::test]
+async fn advertised_udp_host_must_fit_the_reply() {
+    let mut server = Socks5::bind("127.0.0.1:0").await.unwrap();
+    assert!(
+        server
+            .set_advertised_udp_host(Some("x".repeat(255)))
+            .is_ok()
+    );
+    assert!(
+        server
+            .set_advertised_udp_host(Some("x".repeat(256)))
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn task_is_cancelled_when_the_control_connection_closes() {
+    let (mut server, client) = control().await;
+    let (dropped_tx, dropped_rx) = tokio::sync::oneshot::channel::<()>();
+
+    let task = async move {
+        let _guard = dropped_tx;
+        std::future::pending::<()>().await
+    };
+    let running = tokio::spawn(async move { run_while_connected(&mut server, task).await });
+
+    drop(client);
+    assert_eq!(running.await.unwrap(), None);
+    assert!(dropped_rx.await.is_err());
+}
+
+#[tokio::test]
+async fn task_output_is_returned_while_connected() {
+    let (mut server, _client) = control().await;
+
+    let output = run_while_connected(&mut server, async { 7 }).await;
+    assert_eq!(output, Some(7));
+}
+
+#[tokio::test]
+async fn server_releases_the_socket_when_the_client_leaves() {
+    let addr = testkit::spawn_server(|server| {
+        server.allow_no_auth();
+        server.enable_command(CMD::UdpAssociate);
+    })
+    .await
+    .unwrap();
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    let mut selection = [0; 2];
+    stream.read_exact(&mut selection).await.unwrap();
+    let dst = AddrPort::V4(Ipv4Addr::UNSPECIFIED, 0);
+    let request = ConnRequest::new(0x05, CMD::UdpAssociate, 0x00, ATYP::V4, dst);
+    stream
+        .write_all(&request.to_bytes().unwrap())
+        .await
+        .unwrap();
+    let mut reply = [0; 10];
+    stream.read_exact(&mut reply).await.unwrap();
+    let AddrPort::V4(ip, port) = ConnReply::try_from(&reply[..]).unwrap().bnd else {
+        panic!("expected an IPv4 bound address");
+    };
+
+    assert!(UdpSocket::bind((ip, port)).await.is_err());
+    drop(stream);
+
+    let rebound = time::timeout(Duration::from_secs(5), async {
+        loop {
+            if UdpSocket::bind((ip, port)).await.is_ok() {
+                return;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+    rebound.await.expect("UDP socket still bound");
+}
+
+#[tokio::test]
+async fn udp_associate_reply_carries_the_relay_address() {
+    for relay in ["127.0.0.1:0", "[::1]:0"] {
+        let Ok(relay) = UdpSocket::bind(relay).await else {
+            continue;
+        };
+        let bound = relay.local_addr().unwrap();
+        let (mut server, mut client) = control().await;
+
+        Socks5::send_udp_associate_reply(&mut server, bound)
+            .await
+            .unwrap();
+        drop(server);
+
+        let mut bytes = Vec::new();
+        client.read_to_end(&mut bytes).await.unwrap();
+        let reply = ConnReply::try_from(&bytes[..]).unwrap();
+        assert_eq!(reply.rep, Rep::Succeeded);
+        assert_eq!(reply.bnd, AddrPort::from(bound));
+        let atyp = if bound.is_ipv4() { ATYP::V4 } else { ATYP::V6 };
+        assert_eq!(reply.atyp, atyp);
+    }
+}